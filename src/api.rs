@@ -1,74 +1,149 @@
-use crate::{ContentType, ErrorType, HttpCode, HttpMethod, Logger, MyDefault, Request, Response};
+use crate::{
+    Compression, ContentType, CookieJar, ErrorType, Form, FormConfig, HttpCode, Logger, Protocol,
+    Request, RequestLogger, RequireSession, Response, Router, Session, SessionConfig,
+    SessionManager, SessionStore, Teapot, Timing, UploadDir, User, UserStore,
+};
 use argon2::password_hash::SaltString;
 use argon2::PasswordHash;
 use argon2::{Argon2, PasswordHasher, PasswordVerifier};
 use colored::Colorize;
 use log::{error, info};
 use rand::rngs::OsRng;
-use rand::Rng;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::io::ErrorKind;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use tokio::fs::{self, File, OpenOptions};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::fs;
 use tokio::sync::Mutex;
 
+/// Path of the file holding registered users, one JSON record per line.
+#[cfg(not(feature = "sqlite"))]
+const USERS_FILE: &str = "data/users.jsonl";
+
+/// Directory holding the files managed through `PUT`, `PATCH` and `DELETE` under `/files`.
+const UPLOAD_ROOT: &str = "data/uploads";
+
 /// Reads the contents of a file asynchronously and returns its data as bytes.
 ///
 /// # Arguments
 /// - `path`: A string slice representing the file path.
 ///
 /// # Returns
-/// A `Vec<u8>` containing the file's contents.
-///
-/// # Panics
-/// Panics if the file cannot be accessed, or if the read operation fails.
-pub async fn read_file_to_bytes(path: &str) -> Vec<u8> {
-    let metadata = fs::metadata(path).await.unwrap();
-    let mut file = File::open(path).await.unwrap();
-    let mut buffer: Vec<u8> = Vec::with_capacity(metadata.len() as usize);
-    file.read_to_end(&mut buffer).await.unwrap();
-    return buffer;
+/// - `Ok(Vec<u8>)` containing the file's contents.
+/// - `Err(ErrorType::NotFound)` if the file does not exist.
+/// - `Err(ErrorType::ReadError)` if the file cannot be read.
+pub async fn read_file_to_bytes(path: &str) -> Result<Vec<u8>, ErrorType> {
+    return match fs::read(path).await {
+        Ok(buffer) => Ok(buffer),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            Err(ErrorType::NotFound(format!("File {} does not exist", path)))
+        }
+        Err(e) => Err(ErrorType::ReadError(format!(
+            "Failed to read file {}: {}",
+            path, e
+        ))),
+    };
+}
+
+/// Path of the user database used instead of `USERS_FILE` with the `sqlite` feature.
+#[cfg(feature = "sqlite")]
+const USERS_DATABASE: &str = "data/users.db";
+
+/// Builds the route table for the server's built-in pages and API, with the
+/// users kept in `data/users.jsonl` and sessions kept in memory, or both in
+/// the SQLite database `data/users.db` when the `sqlite` feature is enabled.
+/// Sessions use the default [`SessionConfig`]. See [`api_router_with_stores`].
+pub fn api_router() -> Router {
+    let (users, sessions) = default_stores();
+    return api_router_with_stores(
+        users,
+        Arc::new(SessionManager::new(sessions, SessionConfig::default())),
+    );
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn default_stores() -> (Arc<dyn UserStore>, Arc<dyn SessionStore>) {
+    return (
+        Arc::new(crate::FileUserStore::new(USERS_FILE)),
+        Arc::new(crate::MemorySessionStore::new()),
+    );
 }
 
-/// Handles incoming HTTP requests by delegating to specific handlers
-/// based on the HTTP method.
+#[cfg(feature = "sqlite")]
+fn default_stores() -> (Arc<dyn UserStore>, Arc<dyn SessionStore>) {
+    let store = Arc::new(
+        crate::SqliteUserStore::open(USERS_DATABASE).expect("Failed to open the user database"),
+    );
+    return (store.clone(), store);
+}
+
+/// Builds the route table for the server's built-in pages and API.
 ///
 /// # Arguments
-/// - `request`: The HTTP request to be processed.
-/// - `logger`: A thread-safe `Logger` instance for recording log entries.
+/// - `users`: The store the account endpoints use.
+/// - `sessions`: The manager issuing and checking login sessions.
 ///
 /// # Returns
-/// A `Response` that corresponds to the processed request.
-pub async fn handle_response(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    match request.method {
-        HttpMethod::GET => handle_get(request, logger).await,
-        HttpMethod::POST => handle_post(request, logger).await,
-        HttpMethod::PUT => handle_put(request, logger).await,
-        HttpMethod::PATCH => handle_patch(request, logger).await,
-        HttpMethod::DELETE => handle_delete(request, logger).await,
-    }
+/// A `Router` with the static pages, the files under `static/` mounted at
+/// `/static`, the account endpoints, file deletion and file uploads under
+/// `/files` (stored in `data/uploads`) registered, wrapped in
+/// request logging, timing, compression, the teapot check and session
+/// authentication for `/files`.
+pub fn api_router_with_stores(users: Arc<dyn UserStore>, sessions: Arc<SessionManager>) -> Router {
+    let signup_users: Arc<dyn UserStore> = Arc::clone(&users);
+    let signup_sessions: Arc<SessionManager> = Arc::clone(&sessions);
+    let login_sessions: Arc<SessionManager> = Arc::clone(&sessions);
+    let logout_sessions: Arc<SessionManager> = Arc::clone(&sessions);
+    let uploads: Arc<UploadDir> = Arc::new(UploadDir::new(UPLOAD_ROOT));
+    let delete_uploads: Arc<UploadDir> = Arc::clone(&uploads);
+
+    let mut router = Router::new();
+    router
+        .layer(RequestLogger)
+        .layer(Timing)
+        .layer(Compression)
+        .layer(Teapot)
+        .layer(RequireSession::new(&["/files"], sessions))
+        .get("/", handle_index)
+        .get("/hayley", handle_hayley)
+        .get("/home", handle_home)
+        .get("/coffee", handle_teapot)
+        .static_dir("/static", "static")
+        .cache_control("public, max-age=3600")
+        .post("/signup", move |request, logger| {
+            handle_signup(
+                request,
+                logger,
+                Arc::clone(&signup_users),
+                Arc::clone(&signup_sessions),
+            )
+        })
+        .post("/login", move |request, logger| {
+            handle_login(
+                request,
+                logger,
+                Arc::clone(&users),
+                Arc::clone(&login_sessions),
+            )
+        })
+        .post("/logout", move |request, logger| {
+            handle_logout(request, logger, Arc::clone(&logout_sessions))
+        })
+        .delete("/files", move |request, logger| {
+            handle_delete(request, logger, Arc::clone(&delete_uploads))
+        })
+        .upload_dir("/files", uploads);
+    return router;
 }
 
-/// Processes HTTP GET requests and returns an appropriate response.
-///
-/// # Arguments
-/// - `request`: The HTTP GET request to process.
-/// - `logger`: A shared logger instance to track activity.
+/// Builds the `418 I'm a teapot` response.
 ///
 /// # Returns
-/// A `Response` specific to the GET request, such as HTML content
-/// or an error response if applicable.
-async fn handle_get(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    if request.headers.contains(&String::from("Brew")) || request.uri == "/coffee" {
-        let response = Response::default()
-            .await
-            .code(HttpCode::Teapot)
-            .content_type(ContentType::Text)
-            .compression(request.is_compression_supported())
-            .body(
-                r#"
+/// A `Response` with the teapot status and ASCII art body.
+pub(crate) fn teapot_response() -> Response {
+    return Response::new(Protocol::Http, HttpCode::Teapot, ContentType::Text, false).body(
+        r#"
       I'm a Teapot, I can't brew coffee
          _______
         /       \
@@ -76,428 +151,414 @@ async fn handle_get(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
        |    ^    |
         \_______/
 "#
-                .as_bytes()
-                .to_vec(),
-            );
-
-        return response;
-    }
-
-    let mut response = Response::default()
-        .await
-        .compression(request.is_compression_supported());
-
-    if request.uri == "/" {
-        // Add Response Body
-        info!("GET / from status 200");
-        response.add_body(read_file_to_bytes("static/index.html").await);
-    } else if request.uri == "/hayley" {
-        thread::sleep(Duration::from_secs(5));
-        info!("GET /hayley status 200");
-        response.add_body(read_file_to_bytes("static/index.html").await);
-    } else if request.uri == "/home" {
-        info!("GET /home status: 200");
-        response.add_body(read_file_to_bytes("static/home.html").await);
-    } else {
-        // Error
-        error!("Failed to serve request GET {}", request.uri);
-        info!("GET {} status: 404", request.uri);
-        println!(
-            "{} {} {} {}",
-            ">>".red().bold(),
-            "No matching routes for".red(),
-            request.method.to_string().magenta(),
-            request.uri.cyan()
-        );
-        return response
-            .body(String::from("404: Invalid route").into())
-            .content_type(ContentType::Text)
-            .code(HttpCode::BadRequest);
-    }
-    return response;
+        .as_bytes()
+        .to_vec(),
+    );
 }
 
-/// Handles HTTP POST requests for various operations, such as user
-/// signup and login.
+/// Serves a static HTML page.
 ///
 /// # Arguments
-/// - `request`: The HTTP POST request containing the payload.
-/// - `logger`: A thread-safe logger to capture logs during processing.
+/// - `file`: Path of the HTML file to serve.
 ///
 /// # Returns
-/// A `Response` corresponding to the POST request, with outcomes like
-/// successful account creation, login confirmation, or error handling.
-async fn handle_post(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    let mut response = Response::default()
-        .await
-        .compression(request.is_compression_supported())
-        .body(read_file_to_bytes("static/index.html").await)
-        .content_type(ContentType::Text);
-
-    if request.uri == "/signup" {
-        // parse the JSON into a hashmap
-        info!("POST /signup from");
-        let user: HashMap<String, String> = match serde_json::from_str(&request.body) {
-            Ok(u) => u,
-            Err(_) => {
-                error!("Failed to parse JSON in request from");
-                let error = ErrorType::BadRequest(String::from("Invalid JSON request."));
-                logger.lock().await.log_error(&error);
-                println!(
-                    "{} {} {} {}",
-                    ">>".red().bold(),
-                    "Invalid JSON for".red(),
-                    request.method.to_string().magenta(),
-                    request.uri.cyan()
-                );
-                return response
-                    .body(String::from("Invalid JSON.").into())
-                    .code(HttpCode::BadRequest);
-            }
-        };
-        let session_id: String = generate_session_id();
-
-        // insert the new user into the file
-        match insert_user(
-            user["username"].clone(),
-            user["password"].clone(),
-            session_id.clone(),
-        )
-        .await
-        {
-            Ok(_) => (),
-            Err(_) => {
-                error!("Failed to insert user into the database");
-                let error = ErrorType::InternalServerError(String::from(
-                    "Problem when attempting to insert new user.",
-                ));
-                logger.lock().await.log_error(&error);
-                println!(
-                    "{} {} {} {}",
-                    ">>".red().bold(),
-                    "Error while creating new user ".red(),
-                    request.method.to_string().magenta(),
-                    request.uri.cyan()
-                );
-                return response
-                    .body(String::from("Problem occured when attempting to add new user.").into())
-                    .code(HttpCode::InternalServerError);
-            }
+/// A `Response` with the page as its body, or a `404 Not Found` response if
+/// the page cannot be read.
+async fn serve_page(file: &str) -> Response {
+    return match read_file_to_bytes(file).await {
+        Ok(page) => {
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Html, false).body(page)
+        }
+        Err(e) => {
+            error!("Failed to serve page: {}", e);
+            Response::new(Protocol::Http, HttpCode::NotFound, ContentType::Text, false)
+                .body(String::from("404: Page not found").into())
         }
+    };
+}
 
-        response.add_header(
-            String::from("Set-Cookie"),
-            format!("session={}; HttpOnly", session_id),
-        );
+/// Handles `GET /coffee`.
+async fn handle_teapot(_request: Request, _logger: Arc<Mutex<Logger>>) -> Response {
+    return teapot_response();
+}
 
-        return response
-            .body(String::from("New user successfully created!").into())
-            .code(HttpCode::Ok);
-    } else if request.uri == "/login" {
-        info!("POST /login from ");
-        let user: HashMap<String, String> = match serde_json::from_str(&request.body) {
-            Ok(u) => u,
-            Err(_) => {
-                error!("Failed to parse JSON");
-                info!("POST /login status 404");
-                let error = ErrorType::BadRequest(String::from("Invalid JSON request."));
-                logger.lock().await.log_error(&error);
-                println!(
-                    "{} {} {} {}",
-                    ">>".red().bold(),
-                    "Invaid JSON for".red(),
-                    request.method.to_string().magenta(),
-                    request.uri.cyan()
-                );
-                return response
-                    .body(String::from("Invalid JSON.").into())
-                    .code(HttpCode::BadRequest)
-                    .content_type(ContentType::Text);
-            }
-        };
+/// Handles `GET /`, serving the index page.
+async fn handle_index(_request: Request, _logger: Arc<Mutex<Logger>>) -> Response {
+    return serve_page("static/index.html").await;
+}
 
-        let input_username: &str = &user["username"];
-        let input_password: &str = &user["password"];
-
-        let contents: String = fs::read_to_string("static/users.txt").await.unwrap();
-
-        let user_values: String = match contents
-            .lines()
-            .filter(|l| l.contains(input_username))
-            .collect::<Vec<&str>>()
-            .get(0)
-        {
-            Some(l) => l.to_string(),
-            None => {
-                error!(
-                    "Failed to find user account with username {}",
-                    input_username
-                );
-                let error = ErrorType::BadRequest(String::from(
-                    "Attempt to login to a user account that does not exist",
-                ));
-                logger.lock().await.log_error(&error);
-                println!(
-                    "{} {} {} {}",
-                    ">>".red().bold(),
-                    "User not found for".red(),
-                    request.method.to_string().magenta(),
-                    request.uri.cyan()
-                );
-                return response
-                    .body(String::from("No user exists with the provided details.").into())
-                    .code(HttpCode::BadRequest)
-                    .content_type(ContentType::Text);
-            }
-        };
+/// Handles `GET /hayley`, serving the index page after a deliberate delay.
+async fn handle_hayley(_request: Request, _logger: Arc<Mutex<Logger>>) -> Response {
+    thread::sleep(Duration::from_secs(5));
+    return serve_page("static/index.html").await;
+}
 
-        let user_values: Vec<&str> = user_values.split('|').collect();
+/// Handles `GET /home`, serving the home page.
+async fn handle_home(_request: Request, _logger: Arc<Mutex<Logger>>) -> Response {
+    return serve_page("static/home.html").await;
+}
 
-        if user_values.len() != 3 {
-            error!("Failed to find user account from IP address");
-            let error = ErrorType::BadRequest(String::from(
-                "Attempt to login to a user account that does not exist",
-            ));
-            logger.lock().await.log_error(&error);
-            return response
-                .body(String::from("No user exists with the provided details.").into())
-                .code(HttpCode::BadRequest);
-        }
+/// The username and password sent to `POST /signup` and `POST /login`.
+#[derive(Debug, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+}
 
-        if user_values[0] == input_username {
-            match validate_password(input_password, user_values[1]) {
-                Ok(v) if v == true => (),
-                Ok(_) => {
-                    error!("Failed to login user with incorrect password");
-                    let error = ErrorType::BadRequest(String::from(
-                        "Attempt to login with incorrect password.",
-                    ));
-                    logger.lock().await.log_error(&error);
-                    println!(
-                        "{} {} {}",
-                        ">>".red().bold(),
-                        "Attempt to login with incorrect password for".red(),
-                        input_username.cyan()
-                    );
-                    return response
-                        .body(String::from("Incorrect Password.").into())
-                        .code(HttpCode::BadRequest);
-                }
-                Err(_) => {
-                    error!("Failed to validate password");
-                    let error = ErrorType::InternalServerError(String::from(
-                        "Problem when validating password.",
-                    ));
-                    logger.lock().await.log_error(&error);
-                    return response
-                        .body(String::from("Problem occured when validating password.").into())
-                        .code(HttpCode::InternalServerError);
-                }
-            }
+/// The body of `DELETE /files`, naming the file to remove.
+#[derive(Debug, Deserialize)]
+struct DeleteFile {
+    file_name: String,
+}
 
-            response.add_header(
-                String::from("Set-Cookie"),
-                format!("session={}; HttpOnly", user_values[2]),
-            );
+/// The JSON body of successful account and file responses.
+#[derive(Debug, Serialize)]
+struct Message<'a> {
+    message: &'a str,
+}
 
-            return response
-                .body(String::from("Authentification successful!").into())
-                .code(HttpCode::Ok);
-        }
+/// Builds a JSON `{"message": ...}` response with the given code.
+fn message_response(code: HttpCode, message: &str) -> Response {
+    return Response::json(&Message { message }).code(code);
+}
 
-        //}
-    }
-    error!("Failed to parse invalid POST request");
-    let error = ErrorType::BadRequest(String::from("Invalid post request."));
+/// Logs a request body that could not be parsed and builds the `400` or
+/// `422` response describing the problem.
+async fn invalid_body(
+    request: &Request,
+    error: ErrorType,
+    logger: &Arc<Mutex<Logger>>,
+) -> Response {
+    error!("Failed to parse request body: {}", error);
     logger.lock().await.log_error(&error);
-    return response
-        .body(String::from("Invalid post URI.").into())
-        .code(HttpCode::BadRequest);
+    println!(
+        "{} {} {} {}",
+        ">>".red().bold(),
+        "Invalid body for".red(),
+        request.method.to_string().magenta(),
+        request.uri.cyan()
+    );
+    return Response::from_error(&error);
 }
 
-/// Handles HTTP PUT requests. Currently, it responds with a `MethodNotAllowed`
-/// HTTP status code, as this method is not implemented.
+/// Handles `POST /signup`, creating a new user account.
 ///
 /// # Arguments
-/// - `request`: The HTTP PUT request to process.
-/// - `logger`: A shared logger instance for recording log entries.
+/// - `request`: The HTTP POST request containing the username and password.
+/// - `logger`: A thread-safe logger to capture logs during processing.
+/// - `users`: The store the new user is added to.
+/// - `sessions`: The manager issuing the new user's session.
 ///
 /// # Returns
-/// A `Response` with a `MethodNotAllowed` status.
-async fn handle_put(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    info!("PUT {} status 404", request.uri);
-    let response = Response::default()
-        .await
-        .compression(request.is_compression_supported())
-        .body(read_file_to_bytes("static/index.html").await)
-        .code(HttpCode::MethodNotAllowed);
+/// A `201 Created` JSON response with a session cookie, a `409 Conflict`
+/// response if the username is taken, or another JSON error response.
+async fn handle_signup(
+    request: Request,
+    logger: Arc<Mutex<Logger>>,
+    users: Arc<dyn UserStore>,
+    sessions: Arc<SessionManager>,
+) -> Response {
+    let credentials: Credentials = match credentials(&request).await {
+        Ok(c) => c,
+        Err(e) => return invalid_body(&request, e, &logger).await,
+    };
+
+    let password_hash: String = match hash_password(&credentials.password) {
+        Ok(h) => h,
+        Err(e) => {
+            logger.lock().await.log_error(&e);
+            return Response::json_error(
+                HttpCode::InternalServerError,
+                "Problem occured when attempting to add new user.",
+            );
+        }
+    };
+
+    let user = User {
+        username: credentials.username.clone(),
+        password_hash,
+    };
+
+    match users.create(user).await {
+        Ok(_) => (),
+        Err(ErrorType::Conflict(msg)) => {
+            error!("Failed to create user with a taken username");
+            logger.lock().await.log_error(&ErrorType::Conflict(msg));
+            return Response::json_error(HttpCode::Conflict, "Username is already taken.");
+        }
+        Err(e) => {
+            error!("Failed to insert user into the database: {}", e);
+            logger.lock().await.log_error(&e);
+            println!(
+                "{} {} {} {}",
+                ">>".red().bold(),
+                "Error while creating new user ".red(),
+                request.method.to_string().magenta(),
+                request.uri.cyan()
+            );
+            return Response::json_error(
+                HttpCode::InternalServerError,
+                "Problem occured when attempting to add new user.",
+            );
+        }
+    }
+
+    let session: Session = match sessions.create(&credentials.username).await {
+        Ok(s) => s,
+        Err(e) => return session_error(e, &logger).await,
+    };
+
+    let mut response = message_response(HttpCode::Created, "New user successfully created!");
+    if let Err(e) = response.add_cookie(&sessions.cookie(&session)) {
+        return session_error(e, &logger).await;
+    }
 
     return response;
 }
 
-/// Handles HTTP PATCH requests. Currently, it responds with a `MethodNotAllowed`
-/// HTTP status code, as this method is not implemented.
+/// Handles `POST /login`, authenticating an existing user.
 ///
 /// # Arguments
-/// - `request`: The HTTP PATCH request to process.
-/// - `logger`: A shared logger instance for recording log entries.
+/// - `request`: The HTTP POST request containing the username and password.
+/// - `logger`: A thread-safe logger to capture logs during processing.
+/// - `users`: The store the user is looked up in.
+/// - `sessions`: The manager issuing the session.
 ///
 /// # Returns
-/// A `Response` with a `MethodNotAllowed` status.
-async fn handle_patch(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    info!("PATCH {} status 404", request.uri);
-    let response = Response::default()
-        .await
-        .compression(request.is_compression_supported())
-        .body(read_file_to_bytes("static/index.html").await)
-        .code(HttpCode::MethodNotAllowed);
+/// A JSON response with a fresh session cookie on success, or a JSON error
+/// response. Any session the request already carried is revoked, so an ID
+/// planted before login never becomes an authenticated one.
+async fn handle_login(
+    request: Request,
+    logger: Arc<Mutex<Logger>>,
+    users: Arc<dyn UserStore>,
+    sessions: Arc<SessionManager>,
+) -> Response {
+    let credentials: Credentials = match credentials(&request).await {
+        Ok(c) => c,
+        Err(e) => return invalid_body(&request, e, &logger).await,
+    };
+
+    let user: User = match users.get(&credentials.username).await {
+        Ok(Some(u)) => u,
+        Ok(None) => {
+            error!(
+                "Failed to find user account with username {}",
+                credentials.username
+            );
+            let error = ErrorType::BadRequest(String::from(
+                "Attempt to login to a user account that does not exist",
+            ));
+            logger.lock().await.log_error(&error);
+            println!(
+                "{} {} {} {}",
+                ">>".red().bold(),
+                "User not found for".red(),
+                request.method.to_string().magenta(),
+                request.uri.cyan()
+            );
+            return Response::json_error(
+                HttpCode::Unauthorized,
+                "No user exists with the provided details.",
+            );
+        }
+        Err(e) => {
+            error!("Failed to look up user: {}", e);
+            logger.lock().await.log_error(&e);
+            return Response::json_error(
+                HttpCode::InternalServerError,
+                "Problem occured when looking up the user.",
+            );
+        }
+    };
+
+    match validate_password(&credentials.password, &user.password_hash) {
+        Ok(true) => (),
+        Ok(_) => {
+            error!("Failed to login user with incorrect password");
+            let error =
+                ErrorType::BadRequest(String::from("Attempt to login with incorrect password."));
+            logger.lock().await.log_error(&error);
+            println!(
+                "{} {} {}",
+                ">>".red().bold(),
+                "Attempt to login with incorrect password for".red(),
+                user.username.cyan()
+            );
+            return Response::json_error(HttpCode::Unauthorized, "Incorrect Password.");
+        }
+        Err(_) => {
+            error!("Failed to validate password");
+            let error =
+                ErrorType::InternalServerError(String::from("Problem when validating password."));
+            logger.lock().await.log_error(&error);
+            return Response::json_error(
+                HttpCode::InternalServerError,
+                "Problem occured when validating password.",
+            );
+        }
+    }
+
+    if let Some(old) = sessions.session_id(&request.cookies()) {
+        if let Err(e) = sessions.revoke(&old).await {
+            return session_error(e, &logger).await;
+        }
+    }
+
+    let session: Session = match sessions.create(&user.username).await {
+        Ok(s) => s,
+        Err(e) => return session_error(e, &logger).await,
+    };
+
+    let mut response = message_response(HttpCode::Ok, "Authentification successful!");
+    if let Err(e) = response.add_cookie(&sessions.cookie(&session)) {
+        return session_error(e, &logger).await;
+    }
 
     return response;
 }
 
-/// Processes HTTP DELETE requests to remove specified files if the user
-/// is authenticated via a valid session cookie.
+/// Handles `POST /logout`, ending the session named by the request's
+/// cookie. With `?all=true` every session of the cookie's user is ended,
+/// logging them out on all devices.
 ///
 /// # Arguments
-/// - `request`: The HTTP DELETE request, containing the file information and session.
-/// - `logger`: A thread-safe logger to track errors and actions.
+/// - `request`: The HTTP POST request carrying the session cookie.
+/// - `logger`: A thread-safe logger to capture logs during processing.
+/// - `sessions`: The manager holding the sessions.
 ///
 /// # Returns
-/// A `Response` indicating success or failure of the file deletion process.
-async fn handle_delete(request: Request, logger: Arc<Mutex<Logger>>) -> Response {
-    let response = Response::default()
-        .await
-        .compression(request.is_compression_supported())
-        .body(read_file_to_bytes("static/index.html").await)
-        .code(HttpCode::BadRequest)
-        .content_type(ContentType::Text);
+/// A JSON response that also clears the session cookie, a `401
+/// Unauthorized` response if `all` is asked for without a live session, or
+/// a JSON error response.
+async fn handle_logout(
+    request: Request,
+    logger: Arc<Mutex<Logger>>,
+    sessions: Arc<SessionManager>,
+) -> Response {
+    let id: String = sessions.session_id(&request.cookies()).unwrap_or_default();
 
-    let file: HashMap<String, String> = match serde_json::from_str(&request.body) {
-        Ok(u) => u,
-        Err(_) => {
-            error!("Failed to parse invalid JSON");
-            let error = ErrorType::BadRequest(String::from("Invalid JSON request."));
-            logger.lock().await.log_error(&error);
-            return response
-                .body(String::from("Invalid JSON").into())
-                .code(HttpCode::BadRequest);
+    let result: Result<(), ErrorType> = if request.query_param("all") == Some("true") {
+        match sessions.authenticate(&request.cookies()).await {
+            Ok(Some(session)) => sessions.revoke_user(&session.username).await.map(|_| ()),
+            Ok(None) => {
+                return Response::json_error(HttpCode::Unauthorized, "No session to log out of.");
+            }
+            Err(e) => Err(e),
         }
+    } else {
+        sessions.revoke(&id).await
     };
 
-    let file_name: &String = &file["file_name"];
+    if let Err(e) = result {
+        return session_error(e, &logger).await;
+    }
 
-    let cookie_header: Vec<String> = request
-        .headers
-        .into_iter()
-        .filter(|h| h.contains("Cookie: session="))
-        .collect();
+    let mut response = message_response(HttpCode::Ok, "Logged out.");
+    if let Err(e) = response.add_cookie(&sessions.removal_cookie()) {
+        return session_error(e, &logger).await;
+    }
 
-    let cookie_header = match cookie_header.get(0) {
-        Some(h) => h,
-        None => {
-            error!("Attempt to delete without proper authentification from IP address");
-            let error = ErrorType::BadRequest(String::from(
-                "Attempt to delete without proper authentification.",
-            ));
-            logger.lock().await.log_error(&error);
-            return response
-                .body(String::from("Unable to delete file without proper authentification.").into())
-                .code(HttpCode::BadRequest);
-        }
+    return response;
+}
+
+/// Logs a failure of the session store and builds the `500` response for it.
+async fn session_error(error: ErrorType, logger: &Arc<Mutex<Logger>>) -> Response {
+    error!("Failed to access sessions: {}", error);
+    logger.lock().await.log_error(&error);
+    return Response::json_error(
+        HttpCode::InternalServerError,
+        "Problem occured when accessing the session.",
+    );
+}
+
+/// Reads the credentials of a signup or login body. HTML forms post
+/// `application/x-www-form-urlencoded` bodies, any other body is parsed as
+/// JSON.
+///
+/// # Returns
+/// - `Ok(Credentials)` with the fields; for repeated form fields the first value is kept.
+/// - `Err(ErrorType::BadRequest)` if the body cannot be parsed.
+/// - `Err(ErrorType::UnprocessableEntity)` if the username or password is missing.
+async fn credentials(request: &Request) -> Result<Credentials, ErrorType> {
+    if request.media_type().as_deref() != Some("application/x-www-form-urlencoded") {
+        return request.json();
+    }
+
+    let form: Form = request.form(&FormConfig::default()).await?;
+    let field = |name: &str| match form.get(name) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(ErrorType::UnprocessableEntity(format!(
+            "missing field `{}`",
+            name
+        ))),
     };
 
-    let header_parts: Vec<&str> = cookie_header.split_whitespace().collect();
+    return Ok(Credentials {
+        username: field("username")?,
+        password: field("password")?,
+    });
+}
 
-    let cookie_value: &str = match header_parts.get(1) {
-        Some(v) => v,
-        None => {
-            error!("Attempt to delete without proper authentification from IP address");
-            let error = ErrorType::BadRequest(String::from(
-                "Attempt to delete without proper authentification.",
-            ));
-            logger.lock().await.log_error(&error);
-            return response
-                .body(String::from("Unable to delete file without proper authentification.").into())
-                .code(HttpCode::BadRequest);
-        }
+/// Processes `DELETE /files` requests to remove the specified file. Requests
+/// only reach this handler once `RequireSession` has verified the session cookie.
+///
+/// The file name is resolved through [`UploadDir::delete`] of the upload
+/// directory also mounted at `/files`, so only files it manages can be removed.
+///
+/// # Arguments
+/// - `request`: The HTTP DELETE request, with a JSON body naming the file
+///   relative to the upload root.
+/// - `logger`: A thread-safe logger to track errors and actions.
+/// - `uploads`: The upload directory shared with the `/files` routes.
+///
+/// # Returns
+/// A JSON response if the file was deleted, or the error response of
+/// [`UploadDir::delete`] otherwise.
+async fn handle_delete(
+    request: Request,
+    logger: Arc<Mutex<Logger>>,
+    uploads: Arc<UploadDir>,
+) -> Response {
+    let file: DeleteFile = match request.json() {
+        Ok(f) => f,
+        Err(e) => return invalid_body(&request, e, &logger).await,
     };
 
-    // cookie_value = session=sessionID
-    if verify_cookie(cookie_value).await {
-        // session has been verified process the delete
-        match fs::remove_file(file_name).await {
-            Ok(_) => {
-                return response
-                    .body(String::from("File successfully deleted.").into())
-                    .code(HttpCode::Ok);
-            }
-            Err(_) => {
-                error!("Failed to delete file that does not exist");
-                let error = ErrorType::BadRequest(String::from(
-                    "Attempt to remove file that does not exist",
-                ));
-                logger.lock().await.log_error(&error);
-                return response
-                    .body(String::from("Unable to delete file: File does not exist.").into())
-                    .code(HttpCode::BadRequest);
-            }
-        }
+    // the session cookie has been verified by the RequireSession middleware
+    let response: Response = uploads.delete(&file.file_name).await;
+    if response.code != HttpCode::NoContent {
+        return response;
     }
 
-    return response
-        .body(String::from("Unable to delete file.").into())
-        .code(HttpCode::BadRequest);
+    info!(
+        "User {} deleted {}",
+        request.user().unwrap_or_default(),
+        file.file_name
+    );
+    return message_response(HttpCode::Ok, "File successfully deleted.");
 }
 
-/// Inserts a user into the database.
-///
-/// Hashes the user's password using Argon2, then appends the user's details
-/// (username, hashed password, session ID) to the `users.txt` file.
+/// Hashes a password with Argon2 and a random salt.
 ///
 /// # Arguments
-/// - `username`: The username of the new user.
-/// - `password`: The password of the new user.
-/// - `session`: A unique session ID for the user.
+/// - `password`: The plaintext password of the new user.
 ///
 /// # Returns
-/// - `Ok(())` if the operation is successful.
-/// - `Err(ErrorType)` if an error occurs.
-async fn insert_user(username: String, password: String, session: String) -> Result<(), ErrorType> {
-    let password = password.as_bytes();
+/// - `Ok(String)` with the hash in PHC string format.
+/// - `Err(ErrorType::InternalServerError)` if hashing fails.
+fn hash_password(password: &str) -> Result<String, ErrorType> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
-    let hash = match argon2.hash_password(&password, salt.as_salt()) {
-        Ok(hash) => hash,
+    return match argon2.hash_password(password.as_bytes(), salt.as_salt()) {
+        Ok(hash) => Ok(hash.to_string()),
         Err(_) => {
             error!("Failed to create new user");
-            return Err(ErrorType::InternalServerError(String::from(
+            Err(ErrorType::InternalServerError(String::from(
                 "Problem occured when creating password",
-            )));
+            )))
         }
     };
-
-    let mut file_input: Vec<u8> = username.into_bytes();
-    file_input.push(b'|');
-    file_input.extend_from_slice(hash.to_string().as_bytes());
-    file_input.push(b'|');
-    file_input.extend_from_slice(session.as_bytes());
-    let mut file = OpenOptions::new()
-        .append(true)
-        .open("static/users.txt")
-        .await
-        .expect("cannot open file");
-
-    match file.write(&file_input).await {
-        Ok(_) => (),
-        Err(_) => {
-            error!("Failed to write to database");
-            return Err(ErrorType::InternalServerError(String::from(
-                "Problem occured when writing user to db",
-            )));
-        }
-    };
-
-    Ok(())
 }
 
 /// Validates a password against a hashed password.
@@ -510,116 +571,493 @@ async fn insert_user(username: String, password: String, session: String) -> Res
 ///
 /// # Returns
 /// - `Ok(true)` if the password matches the hash.
-/// - `Ok(false)` if the password does not match the hash.
+/// - `Ok(false)` if the password does not match the hash, or the stored hash
+///   is not a PHC string, so no password can match it.
 /// - `Err(ErrorType)` if a validation error occurs.
 fn validate_password(password: &str, hashed_password: &str) -> Result<bool, ErrorType> {
     let argon2 = Argon2::default();
 
     // Parse the hashed password
-    let parsed_hash = PasswordHash::new(hashed_password).map_err(|_| {
-        error!("Failed to validated hashed password");
-        ErrorType::InternalServerError(String::from(
-            "Problem occurred when validating the password",
-        ))
-    })?;
+    let parsed_hash = match PasswordHash::new(hashed_password) {
+        Ok(h) => h,
+        Err(_) => {
+            error!("Stored password hash is not a valid PHC string");
+            return Ok(false);
+        }
+    };
 
     // Verify the password against the hashed password
     match argon2.verify_password(password.as_bytes(), &parsed_hash) {
         Ok(_) => Ok(true),
-        Err(_) => {
+        Err(argon2::password_hash::Error::Password) => {
             error!("Login attempt failed due to incorrect password");
-            return Err(ErrorType::BadRequest(String::from("Incorrect Password")));
+            return Ok(false);
+        }
+        Err(_) => {
+            error!("Failed to verify password");
+            return Err(ErrorType::InternalServerError(String::from(
+                "Problem occurred when validating the password",
+            )));
         }
     }
 }
 
-/// Generates a random session ID.
-///
-/// # Returns
-/// - A `String` representing the session ID.
-fn generate_session_id() -> String {
-    let mut rng = rand::thread_rng();
-    (0..32)
-        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
-        .collect()
-}
-
 /// Verifies the session cookie.
 ///
-/// Reads the `users.txt` file to check if the provided session cookie matches an active session.
+/// Checks that the `session` cookie exactly matches a session that has not
+/// expired, recording that the session is in use. See
+/// [`SessionManager::validate`] for how the ID is compared.
 ///
 /// # Arguments
-/// - `cookie`: The session cookie to verify.
+/// - `cookies`: The cookies sent with the request.
+/// - `sessions`: The manager holding the sessions.
 ///
 /// # Returns
-/// - `true` if the session is valid.
-/// - `false` otherwise.
-async fn verify_cookie(cookie: &str) -> bool {
-    if cookie.starts_with("session=") {
-        return match fs::read_to_string("static/users.txt").await {
-            Ok(f) => {
-                let cookie_value: &str = cookie.split('=').collect::<Vec<&str>>()[1];
-                f.contains(cookie_value)
-            }
-            Err(_) => false,
-        };
-    }
-    false
+/// - `Some(Session)` naming the authenticated user if the session is valid.
+/// - `None` otherwise, including when the session store fails.
+pub(crate) async fn verify_cookie(
+    cookies: &CookieJar,
+    sessions: &SessionManager,
+) -> Option<Session> {
+    return match sessions.authenticate(cookies).await {
+        Ok(session) => session,
+        Err(e) => {
+            error!("Failed to verify session: {}", e);
+            None
+        }
+    };
 }
 
 #[cfg(test)]
 mod tests {
 
-    use std::sync::Arc;
+    use std::sync::{Arc, OnceLock};
 
     use serde_json::json;
     use tokio::sync::Mutex;
 
-    use crate::api::{handle_post, verify_cookie};
-    use crate::{HttpCode, HttpMethod, Logger, Request, Response};
+    use crate::api::{api_router, api_router_with_stores, hash_password, verify_cookie};
+    use crate::session_key;
+    use crate::{
+        ContentType, CookieJar, ErrorType, HttpCode, Logger, MemorySessionStore, MemoryUserStore,
+        Request, Response, Router, Session, SessionConfig, SessionManager, SessionStore, User,
+        UserStore,
+    };
+
+    /// The session of `user_test` in the test stores.
+    const TEST_SESSION: &str = "sloth101sloth101sloth101sloth101";
+    /// The session of `hayley` in the test stores.
+    const HAYLEY_SESSION: &str = "vtcL6o5VTdj4diOow5Hb0BXkcDIZK3nY";
+
+    /// Stores holding `user_test` with the session `TEST_SESSION` and
+    /// `hayley` with the session `HAYLEY_SESSION`, both with the password
+    /// `password`.
+    async fn test_stores() -> (Arc<MemoryUserStore>, Arc<SessionManager>) {
+        // hashing is slow in debug builds, so every test shares one hash
+        static HASH: OnceLock<String> = OnceLock::new();
+        let hash: &String = HASH.get_or_init(|| hash_password("password").unwrap());
+
+        let users = Arc::new(MemoryUserStore::new());
+        let store = Arc::new(MemorySessionStore::new());
+        let now: i64 = chrono::Utc::now().timestamp();
+        for (username, session) in [("user_test", TEST_SESSION), ("hayley", HAYLEY_SESSION)] {
+            let user = User {
+                username: username.to_string(),
+                password_hash: hash.clone(),
+            };
+            users.create(user).await.unwrap();
+            let session = Session {
+                id: session_key(session),
+                username: username.to_string(),
+                created_at: now,
+                last_seen: now,
+            };
+            store.insert(session).await.unwrap();
+        }
+
+        let sessions = Arc::new(SessionManager::new(store, SessionConfig::default()));
+        return (users, sessions);
+    }
+
+    async fn test_router() -> Router {
+        let (users, sessions) = test_stores().await;
+        return api_router_with_stores(users, sessions);
+    }
+
+    /// Builds a request without a body with the given extra header lines.
+    fn test_request(method: &str, uri: &str, headers: &str) -> Request {
+        let raw: String = format!("{} {} HTTP/1.1\r\nHost: a\r\n{}\r\n", method, uri, headers);
+        return Request::new(raw.as_bytes()).unwrap();
+    }
+
+    /// Builds a `POST` request with the given extra header lines and body.
+    fn post(uri: &str, headers: &str, body: &str) -> Request {
+        return Request::new(
+            format!(
+                "POST {} HTTP/1.1\r\nHost: a\r\nContent-Type: application/json\r\n{}\r\n{}",
+                uri, headers, body
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    }
+
+    /// Returns the value of the response's `Set-Cookie` header.
+    fn set_cookie(response: &Response) -> String {
+        return response
+            .headers
+            .iter()
+            .find(|h| h.title == "Set-Cookie")
+            .map(|h| h.value.clone())
+            .unwrap();
+    }
+
+    /// Verifies the cookies of a `Cookie` header value.
+    async fn verify(cookie: &str, sessions: &SessionManager) -> Option<Session> {
+        return verify_cookie(&CookieJar::parse(cookie), sessions).await;
+    }
 
     #[tokio::test]
     async fn test_verify_cookie() {
-        let cookie: String = String::from("session=sloth101");
-        let res = verify_cookie(&cookie).await;
-        assert_eq!(res, true);
+        let (_, sessions) = test_stores().await;
+
+        let session: Session = verify(&format!("session={}", TEST_SESSION), &sessions)
+            .await
+            .unwrap();
+        assert_eq!(session.username, "user_test");
+        let session: Session = verify(
+            &format!("theme=dark; session={}", HAYLEY_SESSION),
+            &sessions,
+        )
+        .await
+        .unwrap();
+        assert_eq!(session.username, "hayley");
+
+        // empty, partial, extended and altered IDs, and the stored key, are all rejected
+        for cookie in [
+            String::new(),
+            String::from("session="),
+            String::from("session=\"\""),
+            String::from("session=sloth"),
+            format!("session={}", &TEST_SESSION[..TEST_SESSION.len() - 1]),
+            format!("session={}", &TEST_SESSION[1..]),
+            format!("session={}1", TEST_SESSION),
+            format!("session={}", TEST_SESSION.to_uppercase()),
+            format!("session={}", session_key(TEST_SESSION)),
+            format!("Session={}", TEST_SESSION),
+            format!("token={}", TEST_SESSION),
+            TEST_SESSION.to_string(),
+        ] {
+            assert!(verify(&cookie, &sessions).await.is_none(), "{}", cookie);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_static_dir() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let request: Request = test_request("GET", "/static/home.html", "");
+        let response: Response = api_router().handle(request, Arc::clone(&logger)).await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.content_type, ContentType::Html);
+
+        let request: Request = test_request("GET", "/static/%2E%2E/data/users.jsonl", "");
+        let response: Response = api_router().handle(request, logger).await;
+        assert_eq!(response.code, HttpCode::NotFound);
     }
 
     #[tokio::test]
     async fn test_signup() {
+        let (users, sessions) = test_stores().await;
+        let router = api_router_with_stores(users.clone(), sessions);
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+
+        for (username, code) in [
+            ("sloth", HttpCode::Created),
+            ("hayley", HttpCode::Conflict),
+            ("sloth", HttpCode::Conflict),
+        ] {
+            let request_body = json!({
+                "username": username,
+                "password": "password"
+            })
+            .to_string();
+
+            let response: Response = router
+                .handle(post("/signup", "", &request_body), Arc::clone(&logger))
+                .await;
+            assert_eq!(response.code, code);
+        }
+
+        assert!(users.get("sloth").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_login() {
         let request_body = json!({
             "username": "hayley",
             "password": "password"
         })
         .to_string();
 
-        let request = Request {
-            headers: Vec::new(),
-            body: request_body,
-            method: HttpMethod::POST,
-            uri: "/signup".to_string(),
-        };
+        let request: Request = post("/login", "", &request_body);
         let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
-        let response: Response = handle_post(request, logger).await;
+        let response: Response = test_router().await.handle(request, logger).await;
         assert_eq!(response.code, HttpCode::Ok);
     }
 
     #[tokio::test]
-    async fn test_login() {
-        let request_body = json!({
-            "username": "hayley",
-            "password": "password"
-        })
-        .to_string();
+    async fn test_login_with_form_body() {
+        let request = Request::new(
+            b"POST /login HTTP/1.1\r\nHost: a\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\nusername=hayley&password=password",
+        )
+        .unwrap();
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let response: Response = test_router().await.handle(request, logger).await;
+        assert_eq!(response.code, HttpCode::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_login_unauthorized() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        for body in [
+            json!({ "username": "hayley", "password": "wrong" }),
+            json!({ "username": "nobody-at-all", "password": "password" }),
+            json!({ "username": "hay", "password": "password" }),
+        ] {
+            let request: Request = post("/login", "", &body.to_string());
+            let response: Response = test_router()
+                .await
+                .handle(request, Arc::clone(&logger))
+                .await;
+            assert_eq!(response.code, HttpCode::Unauthorized);
+        }
+    }
 
-        let request = Request {
-            headers: Vec::new(),
-            body: request_body,
-            method: HttpMethod::POST,
-            uri: "/login".to_string(),
+    #[tokio::test]
+    async fn test_login_with_unparsable_hash() {
+        let (users, sessions) = test_stores().await;
+        let user = User {
+            username: String::from("legacy"),
+            password_hash: String::from("password"),
         };
+        users.create(user).await.unwrap();
+        let router = api_router_with_stores(users, sessions);
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+
+        let body: &str = r#"{"username": "legacy", "password": "password"}"#;
+        let response: Response = router.handle(post("/login", "", body), logger).await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_bodies() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        for (raw, code) in [
+            (
+                &b"POST /login HTTP/1.1\r\nHost: a\r\n\r\n{\"username\": "[..],
+                HttpCode::BadRequest,
+            ),
+            (
+                &b"POST /login HTTP/1.1\r\nHost: a\r\n\r\n{\"username\": \"hayley\"}"[..],
+                HttpCode::UnprocessableEntity,
+            ),
+            (
+                &b"POST /signup HTTP/1.1\r\nHost: a\r\n\r\n{\"username\": 1, \"password\": \"p\"}"[..],
+                HttpCode::UnprocessableEntity,
+            ),
+            (
+                &b"POST /signup HTTP/1.1\r\nHost: a\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\nusername=hayley"[..],
+                HttpCode::UnprocessableEntity,
+            ),
+            (
+                &b"DELETE /files HTTP/1.1\r\nHost: a\r\nCookie: session=sloth101sloth101sloth101sloth101\r\n\r\n{\"file\": \"a\"}"[..],
+                HttpCode::UnprocessableEntity,
+            ),
+        ] {
+            let request = Request::new(raw).unwrap();
+            let response: Response = test_router().await.handle(request, Arc::clone(&logger)).await;
+            assert_eq!(response.code, code);
+            assert_eq!(response.content_type, ContentType::Json);
+
+            let body: serde_json::Value = serde_json::from_slice(&response.body).unwrap();
+            assert!(body["error"].as_str().unwrap().starts_with("Invalid JSON body")
+                || body["error"] == "missing field `password`");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_issues_fresh_session() {
+        let (users, sessions) = test_stores().await;
+        let router = api_router_with_stores(users, Arc::clone(&sessions));
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let body: &str = r#"{"username": "hayley", "password": "password"}"#;
+
+        let first: Response = router
+            .handle(post("/login", "", body), Arc::clone(&logger))
+            .await;
+        let second: Response = router
+            .handle(
+                post(
+                    "/login",
+                    &format!("Cookie: session={}\r\n", HAYLEY_SESSION),
+                    body,
+                ),
+                Arc::clone(&logger),
+            )
+            .await;
+        assert_eq!(second.code, HttpCode::Ok);
+        assert_ne!(set_cookie(&first), set_cookie(&second));
+        assert!(set_cookie(&second).ends_with("; Path=/; Max-Age=86400; HttpOnly; SameSite=Lax"));
+
+        // the session sent along with the login is replaced
+        assert!(verify(&format!("session={}", HAYLEY_SESSION), &sessions)
+            .await
+            .is_none());
+        let cookie: String = set_cookie(&second);
+        let session: Session = verify(&cookie, &sessions).await.unwrap();
+        assert_eq!(session.username, "hayley");
+    }
+
+    #[tokio::test]
+    async fn test_logout() {
+        let (users, sessions) = test_stores().await;
+        let router = api_router_with_stores(users, Arc::clone(&sessions));
         let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
-        let response: Response = handle_post(request, logger).await;
+
+        let response: Response = router
+            .handle(
+                post(
+                    "/logout",
+                    &format!("Cookie: session={}\r\n", TEST_SESSION),
+                    "",
+                ),
+                Arc::clone(&logger),
+            )
+            .await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert!(set_cookie(&response).starts_with("session=; Path=/; Max-Age=0;"));
+        assert!(verify(&format!("session={}", TEST_SESSION), &sessions)
+            .await
+            .is_none());
+        assert!(verify(&format!("session={}", HAYLEY_SESSION), &sessions)
+            .await
+            .is_some());
+
+        // the old cookie no longer opens protected paths
+        let response: Response = router
+            .handle(
+                Request::new(
+                    b"DELETE /files HTTP/1.1\r\nHost: a\r\nCookie: session=sloth101sloth101sloth101sloth101\r\n\r\n{}",
+                )
+                .unwrap(),
+                Arc::clone(&logger),
+            )
+            .await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+
+        // logging out everywhere needs a live session
+        let other: Session = sessions.create("hayley").await.unwrap();
+        let response: Response = router
+            .handle(
+                post(
+                    "/logout?all=true",
+                    &format!("Cookie: session={}\r\n", TEST_SESSION),
+                    "",
+                ),
+                Arc::clone(&logger),
+            )
+            .await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+
+        let response: Response = router
+            .handle(
+                post(
+                    "/logout?all=true",
+                    &format!("Cookie: session={}\r\n", HAYLEY_SESSION),
+                    "",
+                ),
+                Arc::clone(&logger),
+            )
+            .await;
         assert_eq!(response.code, HttpCode::Ok);
+        assert!(verify(&format!("session={}", HAYLEY_SESSION), &sessions)
+            .await
+            .is_none());
+        assert!(verify(&format!("session={}", other.id), &sessions)
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_stays_in_upload_root() {
+        let router: Router = test_router().await;
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+
+        for (file_name, code) in [
+            ("../../Cargo.toml", HttpCode::Forbidden),
+            ("/etc/passwd", HttpCode::Forbidden),
+            ("", HttpCode::BadRequest),
+            ("missing.txt", HttpCode::NotFound),
+        ] {
+            let raw: String = format!(
+                "DELETE /files HTTP/1.1\r\nHost: a\r\nCookie: session={}\r\n\r\n{}",
+                TEST_SESSION,
+                json!({ "file_name": file_name })
+            );
+            let request: Request = Request::new(raw.as_bytes()).unwrap();
+            let response: Response = router.handle(request, Arc::clone(&logger)).await;
+            assert_eq!(response.code, code, "{}", file_name);
+        }
+        assert!(std::path::Path::new("Cargo.toml").exists());
+    }
+
+    #[tokio::test]
+    async fn test_head_responses_have_no_body() {
+        let router: Router = test_router().await;
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+
+        for (uri, code) in [
+            ("/files/x", HttpCode::Unauthorized),
+            ("/missing", HttpCode::NotFound),
+            ("/coffee", HttpCode::Teapot),
+        ] {
+            let mut response: Response = router
+                .handle(test_request("HEAD", uri, ""), Arc::clone(&logger))
+                .await;
+
+            assert_eq!(response.code, code, "{}", uri);
+            let mut output: Vec<u8> = Vec::new();
+            response.write_to(&mut output).await.unwrap();
+            assert!(output.ends_with(b"\r\n\r\n"), "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_files_require_session_for_unusual_paths() {
+        let router: Router = test_router().await;
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+
+        for raw in [
+            "PUT //files/x HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\n\r\na",
+            "DELETE //files/x HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET ///files/ HTTP/1.1\r\nHost: a\r\n\r\n",
+        ] {
+            let request: Request = Request::new(raw.as_bytes()).unwrap();
+            let response: Response = router.handle(request, Arc::clone(&logger)).await;
+            assert_eq!(response.code, HttpCode::Unauthorized, "{}", raw);
+        }
+
+        // an encoded '/' would move the prefix after decoding, so it is refused outright
+        for raw in [
+            "PUT /%2Ffiles/x HTTP/1.1\r\nHost: a\r\nContent-Length: 1\r\n\r\na",
+            "DELETE /%2ffiles/x HTTP/1.1\r\nHost: a\r\n\r\n",
+        ] {
+            assert_eq!(
+                Request::new(raw.as_bytes()).err(),
+                Some(ErrorType::BadRequest(String::new())),
+                "{}",
+                raw
+            );
+        }
     }
 }
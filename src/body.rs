@@ -0,0 +1,265 @@
+use crate::encoding::Encoder;
+use crate::{Encoding, ErrorType};
+use std::fmt::{self, Debug};
+use std::io::SeekFrom;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+
+/// Size of the buffer used to copy a streamed body to the client. At most
+/// this much of the body is held in memory at once.
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Source of a chunked response body. Each message received is written to the
+/// client as one chunk and the body ends when every sender has been dropped.
+pub type BodyStream = mpsc::Receiver<Vec<u8>>;
+
+/// An async reader producing a response body.
+pub type BodyReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// A response body that is produced while the response is being written,
+/// instead of being held in `Response::body`.
+pub enum StreamBody {
+    /// Chunks received from a channel, sent with `Transfer-Encoding: chunked`,
+    /// or until the connection closes to HTTP/1.0 clients.
+    Channel(BodyStream),
+    /// Exactly `length` bytes read from `reader`.
+    Reader { reader: BodyReader, length: u64 },
+    /// `length` bytes of `file` starting at `offset`. On Linux these are
+    /// sent with `sendfile` when the response is not compressed.
+    File {
+        file: File,
+        offset: u64,
+        length: u64,
+    },
+}
+
+impl Debug for StreamBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamBody::Channel(_) => write!(f, "StreamBody::Channel"),
+            StreamBody::Reader { length, .. } => {
+                write!(f, "StreamBody::Reader {{ length: {} }}", length)
+            }
+            StreamBody::File { offset, length, .. } => write!(
+                f,
+                "StreamBody::File {{ offset: {}, length: {} }}",
+                offset, length
+            ),
+        }
+    }
+}
+
+impl StreamBody {
+    /// The length of the body, if it is known before it is written.
+    pub fn length(&self) -> Option<u64> {
+        return match self {
+            StreamBody::Channel(_) => None,
+            StreamBody::Reader { length, .. } => Some(*length),
+            StreamBody::File { length, .. } => Some(*length),
+        };
+    }
+
+    /// Turns a fixed length body into a reader positioned at its first byte.
+    ///
+    /// # Returns
+    /// - `Ok(Some((reader, length)))` for reader and file bodies.
+    /// - `Ok(None)` for channel bodies.
+    /// - `Err(ErrorType::ReadError)` if the file cannot be positioned.
+    pub(crate) async fn into_reader(self) -> Result<Option<(BodyReader, u64)>, ErrorType> {
+        return match self {
+            StreamBody::Channel(_) => Ok(None),
+            StreamBody::Reader { reader, length } => Ok(Some((reader, length))),
+            StreamBody::File {
+                mut file,
+                offset,
+                length,
+            } => match file.seek(SeekFrom::Start(offset)).await {
+                Ok(_) => Ok(Some((Box::new(file.take(length)), length))),
+                Err(e) => Err(ErrorType::ReadError(format!(
+                    "Failed to seek to byte {}: {}",
+                    offset, e
+                ))),
+            },
+        };
+    }
+}
+
+/// Frames `data` as a single chunk of a chunked body.
+pub fn encode_chunk(data: &[u8]) -> Vec<u8> {
+    let mut chunk: Vec<u8> = format!("{:X}\r\n", data.len()).into_bytes();
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(b"\r\n");
+    return chunk;
+}
+
+pub(crate) async fn write_bytes<W>(writer: &mut W, bytes: &[u8]) -> Result<(), ErrorType>
+where
+    W: AsyncWrite + Unpin,
+{
+    return match writer.write_all(bytes).await {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ErrorType::SocketError(String::from(
+            "Failed to write response to client",
+        ))),
+    };
+}
+
+/// Copies exactly `length` bytes from `reader` to `writer`, holding at most
+/// [`STREAM_CHUNK_SIZE`] bytes in memory.
+///
+/// # Returns
+/// - `Ok(())` once `length` bytes have been written.
+/// - `Err(ErrorType::ReadError)` if the reader fails or ends early.
+/// - `Err(ErrorType::SocketError)` if writing to the client fails.
+pub(crate) async fn copy_body<W>(
+    writer: &mut W,
+    reader: &mut BodyReader,
+    length: u64,
+) -> Result<(), ErrorType>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer: Vec<u8> = vec![0; STREAM_CHUNK_SIZE];
+    let mut remaining: u64 = length;
+
+    while remaining > 0 {
+        let limit: usize = remaining.min(STREAM_CHUNK_SIZE as u64) as usize;
+        let read: usize = match reader.read(&mut buffer[..limit]).await {
+            Ok(0) => {
+                return Err(ErrorType::ReadError(format!(
+                    "Body ended {} bytes early",
+                    remaining
+                )))
+            }
+            Ok(n) => n,
+            Err(e) => {
+                return Err(ErrorType::ReadError(format!(
+                    "Failed to read response body: {}",
+                    e
+                )))
+            }
+        };
+
+        write_bytes(writer, &buffer[..read]).await?;
+        remaining -= read as u64;
+    }
+
+    return Ok(());
+}
+
+/// Writes the body from `reader` compressed with `encoding`, holding at most
+/// [`STREAM_CHUNK_SIZE`] uncompressed bytes in memory. The body is framed as
+/// a chunked body if `chunked` is set, and written as is otherwise, leaving
+/// the end of the body to be marked by closing the connection.
+///
+/// # Returns
+/// - `Ok(())` once the whole body, and any terminating chunk, has been written.
+/// - `Err(ErrorType::ReadError)` if the reader fails.
+/// - `Err(ErrorType::SocketError)` if writing to the client fails.
+pub(crate) async fn copy_encoded<W>(
+    writer: &mut W,
+    reader: &mut BodyReader,
+    encoding: Encoding,
+    chunked: bool,
+) -> Result<(), ErrorType>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer: Vec<u8> = vec![0; STREAM_CHUNK_SIZE];
+    let mut encoder = Encoder::new(encoding);
+
+    loop {
+        let read: usize = match reader.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                return Err(ErrorType::ReadError(format!(
+                    "Failed to read response body: {}",
+                    e
+                )))
+            }
+        };
+
+        let data: Vec<u8> = encoder.write(&buffer[..read]);
+        if !data.is_empty() {
+            write_bytes(writer, &frame(&data, chunked)).await?;
+        }
+    }
+
+    let data: Vec<u8> = encoder.finish();
+    if !data.is_empty() {
+        write_bytes(writer, &frame(&data, chunked)).await?;
+    }
+
+    if !chunked {
+        return Ok(());
+    }
+    return write_bytes(writer, b"0\r\n\r\n").await;
+}
+
+/// Frames `data` as a chunk if `chunked` is set, or returns it unchanged.
+pub(crate) fn frame(data: &[u8], chunked: bool) -> Vec<u8> {
+    if chunked {
+        return encode_chunk(data);
+    }
+    return data.to_vec();
+}
+
+/// Sends `length` bytes of `file` starting at `offset` to `socket` with the
+/// `sendfile` system call, so the data never passes through user space.
+///
+/// # Returns
+/// - `Ok(())` once every byte has been sent.
+/// - `Err(ErrorType::SocketError)` if the transfer fails or the file ends early.
+#[cfg(all(target_os = "linux", feature = "sendfile"))]
+pub(crate) async fn sendfile(
+    socket: &tokio::net::TcpStream,
+    file: File,
+    offset: u64,
+    length: u64,
+) -> Result<(), ErrorType> {
+    use std::io::ErrorKind;
+    use std::os::unix::io::AsRawFd;
+    use tokio::io::Interest;
+
+    let file: std::fs::File = file.into_std().await;
+    let mut offset: libc::off_t = offset as libc::off_t;
+    let mut remaining: u64 = length;
+
+    while remaining > 0 {
+        let count: usize = remaining.min(STREAM_CHUNK_SIZE as u64 * 16) as usize;
+        let sent = socket
+            .async_io(Interest::WRITABLE, || {
+                // SAFETY: both descriptors stay open for the duration of the
+                // call and `offset` is a valid pointer to an `off_t`.
+                let n: isize = unsafe {
+                    libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut offset, count)
+                };
+                if n < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                return Ok(n as u64);
+            })
+            .await;
+
+        match sent {
+            Ok(0) => {
+                return Err(ErrorType::SocketError(format!(
+                    "File ended {} bytes early",
+                    remaining
+                )))
+            }
+            Ok(n) => remaining -= n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(ErrorType::SocketError(format!(
+                    "Failed to send file to client: {}",
+                    e
+                )))
+            }
+        }
+    }
+
+    return Ok(());
+}
@@ -13,15 +13,43 @@ pub mod connections {
 
     use crate::request_validation::handle_request;
     use crate::shutdown::Message;
-    use crate::ErrorType;
+    use crate::{ErrorType, RequestLimits};
 
     const MAX_CONNECTIONS: usize = 5;
 
+    /// Default time a persistent connection may sit idle between requests.
+    pub const DEFAULT_KEEP_ALIVE_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Default number of requests served on one connection before it is closed.
+    pub const DEFAULT_MAX_REQUESTS: usize = 100;
+
+    /// Settings that govern how each client connection is served.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ConnectionConfig {
+        /// How long to wait for the next request before closing an idle connection.
+        pub keep_alive_timeout: Duration,
+        /// Maximum number of requests served on a single connection. `0` is
+        /// treated like `1`: the first request is answered and the connection closed.
+        pub max_requests: usize,
+        /// Size limits applied to each incoming request.
+        pub limits: RequestLimits,
+    }
+
+    impl Default for ConnectionConfig {
+        fn default() -> Self {
+            return ConnectionConfig {
+                keep_alive_timeout: DEFAULT_KEEP_ALIVE_TIMEOUT,
+                max_requests: DEFAULT_MAX_REQUESTS,
+                limits: RequestLimits::default(),
+            };
+        }
+    }
+
     #[derive(Debug)]
     pub struct Listener {
         pub listener: TcpListener,
         pub connection_limit: Arc<Semaphore>,
         pub shutdown_tx: Arc<Mutex<Sender<Message>>>,
+        pub config: ConnectionConfig,
     }
 
     #[derive(Debug)]
@@ -0,0 +1,506 @@
+use crate::{http_date, is_token, ErrorType, HeaderMap};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::Sha256;
+use std::fmt::{Debug, Display};
+use std::time::Duration;
+
+/// The smallest secret accepted by [`CookieKey::new`], in bytes.
+pub const MIN_COOKIE_KEY_LENGTH: usize = 32;
+
+/// The `SameSite` attribute of a cookie, controlling whether browsers send
+/// it with requests started by other sites.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    /// Only sent with requests from the same site.
+    Strict,
+    /// Also sent when following a link from another site.
+    Lax,
+    /// Sent with every request. Browsers require `Secure` with it.
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        };
+    }
+}
+
+/// A cookie to send to the client in a `Set-Cookie` header.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{Cookie, SameSite};
+/// use std::time::Duration;
+///
+/// let cookie = Cookie::new("theme", "dark")
+///     .path("/")
+///     .max_age(Duration::from_secs(3600))
+///     .secure(true)
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// assert_eq!(
+///     cookie.to_header().unwrap(),
+///     "theme=dark; Path=/; Max-Age=3600; Secure; HttpOnly; SameSite=Lax"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<DateTime<Utc>>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Creates a cookie without attributes. The browser keeps it until it is
+    /// closed and sends it back only to this host.
+    pub fn new(name: &str, value: &str) -> Self {
+        return Cookie {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        };
+    }
+
+    /// Creates a cookie whose value is signed with `key`, see
+    /// [`CookieKey::sign`]. Read it back with [`CookieJar::get_signed`].
+    pub fn signed(name: &str, value: &str, key: &CookieKey) -> Self {
+        return Cookie::new(name, &key.sign(name, value));
+    }
+
+    /// Creates a cookie that makes the browser delete the cookie `name`. Its
+    /// `Path` and `Domain` must match the ones the cookie was set with.
+    pub fn removal(name: &str) -> Self {
+        return Cookie::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(DateTime::UNIX_EPOCH);
+    }
+
+    pub fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    pub fn value(&self) -> &str {
+        return &self.value;
+    }
+
+    /// Limits the cookie to request paths at or below `path`.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        return self;
+    }
+
+    /// Sends the cookie to `domain` and its subdomains as well.
+    pub fn domain(mut self, domain: &str) -> Self {
+        self.domain = Some(domain.to_string());
+        return self;
+    }
+
+    /// Lets the cookie expire `max_age` after it is received. Takes
+    /// precedence over [`Cookie::expires`] in current browsers.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        return self;
+    }
+
+    /// Lets the cookie expire at `expires`, for clients that ignore `Max-Age`.
+    pub fn expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        return self;
+    }
+
+    /// Only sends the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        return self;
+    }
+
+    /// Hides the cookie from JavaScript.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        return self;
+    }
+
+    /// Sets the `SameSite` attribute. [`SameSite::None`] also makes the
+    /// cookie `Secure`, as browsers drop it otherwise.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        if same_site == SameSite::None {
+            self.secure = true;
+        }
+        self.same_site = Some(same_site);
+        return self;
+    }
+
+    /// Builds the value of the `Set-Cookie` header for the cookie.
+    ///
+    /// # Returns
+    /// - `Ok(String)` with the cookie and its attributes.
+    /// - `Err(ErrorType::InternalServerError)` if the name is not a token, or
+    ///   the value, path or domain holds characters that would change the
+    ///   meaning of the header, such as `;`, whitespace or control characters.
+    pub fn to_header(&self) -> Result<String, ErrorType> {
+        if !is_token(&self.name) {
+            return Err(ErrorType::InternalServerError(format!(
+                "Invalid cookie name {:?}",
+                self.name
+            )));
+        }
+        if !self.value.bytes().all(is_cookie_octet) {
+            return Err(ErrorType::InternalServerError(format!(
+                "Invalid value for cookie {}",
+                self.name
+            )));
+        }
+
+        let mut header: String = format!("{}={}", self.name, self.value);
+
+        for (attribute, value) in [("Path", &self.path), ("Domain", &self.domain)] {
+            if let Some(value) = value {
+                if value.is_empty() || value.bytes().any(|b| b == b';' || b.is_ascii_control()) {
+                    return Err(ErrorType::InternalServerError(format!(
+                        "Invalid {} for cookie {}",
+                        attribute, self.name
+                    )));
+                }
+                header.push_str(&format!("; {}={}", attribute, value));
+            }
+        }
+
+        if let Some(max_age) = self.max_age {
+            header.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+        }
+        if let Some(expires) = self.expires {
+            header.push_str(&format!("; Expires={}", http_date(expires)));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = self.same_site {
+            header.push_str(&format!("; SameSite={}", same_site));
+        }
+
+        return Ok(header);
+    }
+}
+
+/// Returns `true` for the bytes allowed unquoted in a cookie value by RFC 6265.
+fn is_cookie_octet(byte: u8) -> bool {
+    return match byte {
+        0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E => true,
+        _ => false,
+    };
+}
+
+/// The cookies a client sent in its `Cookie` headers.
+///
+/// # Example
+/// ```rust
+/// use rust_server::CookieJar;
+///
+/// let cookies = CookieJar::parse("theme=dark; session=abc;lang=\"en\"");
+/// assert_eq!(cookies.get("session"), Some("abc"));
+/// assert_eq!(cookies.get("lang"), Some("en"));
+/// assert_eq!(cookies.get("missing"), None);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        return CookieJar::default();
+    }
+
+    /// Parses the value of one `Cookie` header. Pairs without a name or an
+    /// `=` are skipped, and a value in double quotes is unquoted.
+    pub fn parse(header: &str) -> Self {
+        let mut jar = CookieJar::new();
+        jar.add_header(header);
+        return jar;
+    }
+
+    /// Collects the cookies of every `Cookie` header in `headers`.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut jar = CookieJar::new();
+        for header in headers.get_all("Cookie") {
+            jar.add_header(header);
+        }
+        return jar;
+    }
+
+    fn add_header(&mut self, header: &str) {
+        for pair in header.split(';') {
+            let (name, value) = match pair.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => continue,
+            };
+            if name.is_empty() {
+                continue;
+            }
+
+            let value: &str = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(unquoted) => unquoted,
+                None => value,
+            };
+            self.cookies.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// Returns the value of the first cookie called `name`. Names are case-sensitive.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self
+            .cookies
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str());
+    }
+
+    /// Returns the value of the first cookie called `name` if its signature
+    /// was made with `key`, see [`CookieKey::verify`].
+    pub fn get_signed(&self, name: &str, key: &CookieKey) -> Option<String> {
+        return key.verify(name, self.get(name)?);
+    }
+
+    /// Iterates over the cookies as `(name, value)` pairs, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        return self.cookies.iter().map(|(n, v)| (n.as_str(), v.as_str()));
+    }
+
+    pub fn len(&self) -> usize {
+        return self.cookies.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.cookies.is_empty();
+    }
+}
+
+/// A secret for signing cookie values with HMAC-SHA256, so clients can read
+/// but not forge or alter them.
+///
+/// A signed value is the original value, a `.` and the URL-safe base64 MAC
+/// of the cookie's name and value. Signatures are checked in constant time,
+/// and a value signed for one cookie name is rejected under another.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{CookieJar, CookieKey};
+///
+/// let key = CookieKey::generate();
+/// let signed: String = key.sign("user", "hayley");
+/// assert_eq!(key.verify("user", &signed), Some(String::from("hayley")));
+///
+/// let forged: String = signed.replacen("hayley", "admin", 1);
+/// assert_eq!(key.verify("user", &forged), None);
+/// assert_eq!(CookieJar::parse(&format!("user={}", forged)).get_signed("user", &key), None);
+/// ```
+#[derive(Clone)]
+pub struct CookieKey {
+    secret: Vec<u8>,
+}
+
+impl CookieKey {
+    /// Creates a key from a secret of at least [`MIN_COOKIE_KEY_LENGTH`]
+    /// random bytes. Keep the secret stable to accept cookies signed before
+    /// a restart.
+    ///
+    /// # Returns
+    /// - `Ok(CookieKey)` with the key.
+    /// - `Err(ErrorType::InternalServerError)` if the secret is too short.
+    pub fn new(secret: &[u8]) -> Result<Self, ErrorType> {
+        if secret.len() < MIN_COOKIE_KEY_LENGTH {
+            return Err(ErrorType::InternalServerError(format!(
+                "Cookie keys need at least {} bytes, got {}",
+                MIN_COOKIE_KEY_LENGTH,
+                secret.len()
+            )));
+        }
+
+        return Ok(CookieKey {
+            secret: secret.to_vec(),
+        });
+    }
+
+    /// Creates a key from [`MIN_COOKIE_KEY_LENGTH`] bytes of the operating
+    /// system's secure random number generator. Cookies signed with it are
+    /// rejected once the server restarts.
+    pub fn generate() -> Self {
+        let mut secret: Vec<u8> = vec![0; MIN_COOKIE_KEY_LENGTH];
+        OsRng.fill_bytes(&mut secret);
+        return CookieKey { secret };
+    }
+
+    fn mac(&self, name: &str, value: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        return mac;
+    }
+
+    /// Signs `value` for the cookie called `name`.
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        let tag = self.mac(name, value).finalize().into_bytes();
+        return format!("{}.{}", value, URL_SAFE_NO_PAD.encode(tag));
+    }
+
+    /// Checks a value made by [`CookieKey::sign`] for the cookie called `name`.
+    ///
+    /// # Returns
+    /// - `Some(String)` with the original value if the signature matches.
+    /// - `None` if the value is unsigned, altered or signed with another key or name.
+    pub fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        let (value, tag) = signed.rsplit_once('.')?;
+        let tag: Vec<u8> = URL_SAFE_NO_PAD.decode(tag).ok()?;
+
+        return match self.mac(name, value).verify_slice(&tag) {
+            Ok(_) => Some(value.to_string()),
+            Err(_) => None,
+        };
+    }
+}
+
+impl Debug for CookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the secret, e.g. in a logged configuration
+        return write!(f, "CookieKey(..)");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+
+    #[test]
+    fn test_parse_cookies() {
+        let request = Request::new(
+            b"GET / HTTP/1.1\r\nHost: a\r\nCookie: theme=dark;session=abc\r\nCookie:  lang = \"en\" ; flag; =x; empty=\r\n\r\n",
+        )
+        .unwrap();
+        let cookies: CookieJar = request.cookies();
+
+        assert_eq!(
+            cookies.iter().collect::<Vec<(&str, &str)>>(),
+            vec![
+                ("theme", "dark"),
+                ("session", "abc"),
+                ("lang", "en"),
+                ("empty", "")
+            ]
+        );
+        assert_eq!(cookies.get("session"), Some("abc"));
+        assert_eq!(cookies.get("Session"), None);
+        assert_eq!(cookies.get("flag"), None);
+
+        // the first of two cookies with the same name wins
+        assert_eq!(CookieJar::parse("a=1; a=2").get("a"), Some("1"));
+        assert!(CookieJar::parse("").is_empty());
+    }
+
+    #[test]
+    fn test_set_cookie_header() {
+        let expires: DateTime<Utc> = DateTime::from_timestamp(784111777, 0).unwrap();
+        let cookie = Cookie::new("id", "a3fWa")
+            .domain("example.com")
+            .path("/docs")
+            .expires(expires)
+            .same_site(SameSite::Strict);
+        assert_eq!(
+            cookie.to_header().unwrap(),
+            "id=a3fWa; Path=/docs; Domain=example.com; Expires=Sun, 06 Nov 1994 08:49:37 GMT; SameSite=Strict"
+        );
+
+        assert_eq!(
+            Cookie::new("id", "")
+                .same_site(SameSite::None)
+                .to_header()
+                .unwrap(),
+            "id=; Secure; SameSite=None"
+        );
+        assert_eq!(
+            Cookie::removal("id").path("/").to_header().unwrap(),
+            "id=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+
+        for cookie in [
+            Cookie::new("", "a"),
+            Cookie::new("a b", "a"),
+            Cookie::new("a=b", "a"),
+            Cookie::new("id", "a;b"),
+            Cookie::new("id", "a b"),
+            Cookie::new("id", "a\r\nSet-Cookie: b=c"),
+            Cookie::new("id", "\"a\""),
+            Cookie::new("id", "a").path("/; Domain=evil.example"),
+            Cookie::new("id", "a").domain(""),
+        ] {
+            assert_eq!(
+                cookie.to_header(),
+                Err(ErrorType::InternalServerError(String::new()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_signed_cookies() {
+        let key = CookieKey::new(&[7; 32]).unwrap();
+        let cookie = Cookie::signed("session", "abc", &key);
+        assert!(cookie.value().starts_with("abc."));
+        assert!(cookie.to_header().is_ok());
+
+        let cookies = CookieJar::parse(&cookie.to_header().unwrap());
+        assert_eq!(
+            cookies.get_signed("session", &key),
+            Some(String::from("abc"))
+        );
+
+        // another name, another key, a changed value or tag, or no tag at all
+        assert_eq!(key.verify("user", cookie.value()), None);
+        assert_eq!(
+            CookieKey::generate().verify("session", cookie.value()),
+            None
+        );
+        assert_eq!(
+            key.verify("session", &cookie.value().replacen("abc", "abd", 1)),
+            None
+        );
+        assert_eq!(key.verify("session", &format!("{}A", cookie.value())), None);
+        assert_eq!(key.verify("session", "abc"), None);
+        assert_eq!(key.verify("session", "abc."), None);
+        assert_eq!(key.verify("session", ""), None);
+
+        // values may contain dots themselves
+        assert_eq!(
+            key.verify("v", &key.sign("v", "1.2")),
+            Some(String::from("1.2"))
+        );
+
+        assert!(CookieKey::new(&[7; 31]).is_err());
+        assert_eq!(format!("{:?}", key), "CookieKey(..)");
+    }
+}
@@ -0,0 +1,502 @@
+use crate::{ErrorType, HeaderMap};
+use flate2::read::{MultiGzDecoder, ZlibDecoder};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use flate2::Compression;
+use std::fmt::Display;
+use std::io::{Read, Write};
+
+/// Bodies smaller than this are sent uncompressed, since the encoding
+/// overhead outweighs the bytes saved.
+pub const MIN_COMPRESSION_SIZE: usize = 1024;
+
+/// The content codings accepted on request bodies, as advertised in the
+/// `Accept-Encoding` header of a `415 Unsupported Media Type` response.
+pub const REQUEST_ENCODINGS: &str = "gzip, deflate";
+
+/// A content coding for response bodies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// The supported compressed encodings, in the order the server prefers
+    /// them when the client accepts several with the same quality.
+    pub const SUPPORTED: [Encoding; 4] = [
+        Encoding::Brotli,
+        Encoding::Zstd,
+        Encoding::Gzip,
+        Encoding::Deflate,
+    ];
+
+    /// Looks up an encoding by its `Accept-Encoding` token, ignoring case.
+    ///
+    /// # Returns
+    /// - `Some(Encoding)` for `identity`, `gzip` (or `x-gzip`), `deflate`,
+    ///   `br` and `zstd`.
+    /// - `None` for any other token.
+    pub fn from_token(token: &str) -> Option<Encoding> {
+        return match token.trim().to_ascii_lowercase().as_str() {
+            "identity" => Some(Encoding::Identity),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            _ => None,
+        };
+    }
+
+    /// The file extension of precompressed copies in this encoding, e.g.
+    /// `app.js.br` for brotli.
+    ///
+    /// # Returns
+    /// `Some(extension)` for brotli (`br`), gzip (`gz`) and zstd (`zst`),
+    /// `None` for the others.
+    pub fn file_extension(&self) -> Option<&'static str> {
+        return match self {
+            Encoding::Brotli => Some("br"),
+            Encoding::Gzip => Some("gz"),
+            Encoding::Zstd => Some("zst"),
+            Encoding::Deflate | Encoding::Identity => None,
+        };
+    }
+
+    /// Encodes a complete body.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::Encoding;
+    /// let body = Encoding::Gzip.encode(b"hello");
+    /// assert_eq!(&body[..2], &[0x1f, 0x8b]);
+    /// assert_eq!(Encoding::Identity.encode(b"hello"), b"hello");
+    /// ```
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(*self);
+        let mut output: Vec<u8> = encoder.write(data);
+        output.extend_from_slice(&encoder.finish());
+        return output;
+    }
+
+    /// Decodes a complete request body sent in this encoding.
+    ///
+    /// At most `max_size` decoded bytes are produced, so a small compressed
+    /// body cannot expand into an unbounded allocation.
+    ///
+    /// # Returns
+    /// - `Ok(Vec<u8>)` with the decoded body.
+    /// - `Err(ErrorType::PayloadTooLarge)` if the decoded body exceeds `max_size`.
+    /// - `Err(ErrorType::BadRequest)` if `data` is not valid in this encoding.
+    /// - `Err(ErrorType::UnsupportedMediaType)` for brotli and zstd, which are
+    ///   only used for responses.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::Encoding;
+    /// let body = Encoding::Gzip.encode(b"hello");
+    /// assert_eq!(Encoding::Gzip.decode(&body, 1024).unwrap(), b"hello");
+    /// assert!(Encoding::Gzip.decode(&body, 4).is_err());
+    /// ```
+    pub fn decode(&self, data: &[u8], max_size: usize) -> Result<Vec<u8>, ErrorType> {
+        let reader: Box<dyn Read + '_> = match self {
+            Encoding::Identity => Box::new(data),
+            Encoding::Gzip => Box::new(MultiGzDecoder::new(data)),
+            Encoding::Deflate => Box::new(ZlibDecoder::new(data)),
+            Encoding::Brotli | Encoding::Zstd => {
+                return Err(ErrorType::UnsupportedMediaType(format!(
+                    "Unsupported Content-Encoding: {}",
+                    self
+                )));
+            }
+        };
+
+        // read one byte past the limit to tell a body of exactly max_size apart
+        let mut output: Vec<u8> = Vec::new();
+        if reader
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut output)
+            .is_err()
+        {
+            return Err(ErrorType::BadRequest(format!(
+                "Invalid {} request body",
+                self
+            )));
+        }
+
+        if output.len() > max_size {
+            return Err(ErrorType::PayloadTooLarge(format!(
+                "Decoded request body exceeds {} bytes",
+                max_size
+            )));
+        }
+
+        return Ok(output);
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Encoding::Identity => write!(f, "identity"),
+            Encoding::Gzip => write!(f, "gzip"),
+            Encoding::Deflate => write!(f, "deflate"),
+            Encoding::Brotli => write!(f, "br"),
+            Encoding::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+/// Chooses the response encoding from a request's `Accept-Encoding` headers.
+///
+/// Each supported encoding gets the quality the client assigned to it, or to
+/// `*` if it is not listed; the highest non-zero quality wins, ties going to
+/// the order of [`Encoding::SUPPORTED`]. `identity` is acceptable unless it is
+/// excluded with `identity;q=0`, or with `*;q=0` without being listed. A
+/// request without `Accept-Encoding` gets `identity`.
+///
+/// # Returns
+/// - `Some(Encoding)` with the encoding to use.
+/// - `None` if the client accepts none of the supported encodings and has
+///   excluded `identity`.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{negotiate_encoding, Encoding, HeaderMap};
+/// let headers = HeaderMap::from_lines(["Accept-Encoding: gzip;q=0.8, br;q=0.9"].into_iter());
+/// assert_eq!(negotiate_encoding(&headers), Some(Encoding::Brotli));
+/// let headers = HeaderMap::from_lines(["Accept-Encoding: compress, identity;q=0"].into_iter());
+/// assert_eq!(negotiate_encoding(&headers), None);
+/// ```
+pub fn negotiate_encoding(headers: &HeaderMap) -> Option<Encoding> {
+    return negotiate_encoding_among(headers, &Encoding::SUPPORTED);
+}
+
+/// Chooses the response encoding like [`negotiate_encoding`], considering
+/// only the compressed encodings in `available`, in order of preference.
+pub fn negotiate_encoding_among(headers: &HeaderMap, available: &[Encoding]) -> Option<Encoding> {
+    let mut qualities: Vec<(&str, f32)> = Vec::new();
+    for item in headers.get_list("Accept-Encoding") {
+        let mut parts = item.split(';');
+        let coding: &str = parts.next().unwrap_or("").trim();
+
+        let mut quality: Option<f32> = Some(1.0);
+        for param in parts {
+            if let Some((name, value)) = param.split_once('=') {
+                if name.trim().eq_ignore_ascii_case("q") {
+                    quality = match value.trim().parse::<f32>() {
+                        Ok(q) if (0.0..=1.0).contains(&q) => Some(q),
+                        _ => None,
+                    };
+                }
+            }
+        }
+
+        // items with an invalid quality value are ignored
+        if let Some(q) = quality {
+            qualities.push((coding, q));
+        }
+    }
+
+    let quality_of = |token: &str| -> Option<f32> {
+        return qualities
+            .iter()
+            .find(|(coding, _)| Encoding::from_token(coding) == Encoding::from_token(token))
+            .map(|(_, q)| *q);
+    };
+    let wildcard: Option<f32> = qualities
+        .iter()
+        .find(|(coding, _)| *coding == "*")
+        .map(|(_, q)| *q);
+
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in available.iter().copied() {
+        let q: f32 = quality_of(&encoding.to_string())
+            .or(wildcard)
+            .unwrap_or(0.0);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((encoding, q));
+        }
+    }
+
+    if let Some((encoding, _)) = best {
+        return Some(encoding);
+    }
+
+    let identity: f32 = quality_of("identity").or(wildcard).unwrap_or(1.0);
+    if identity > 0.0 {
+        return Some(Encoding::Identity);
+    }
+    return None;
+}
+
+/// Removes the content codings listed in a request's `Content-Encoding`
+/// headers from its body.
+///
+/// Codings are undone in the reverse of the order they were applied. Only
+/// `gzip` (or `x-gzip`), `deflate` and `identity` are accepted.
+///
+/// # Arguments
+/// - `headers`: The request headers.
+/// - `body`: The body as received.
+/// - `max_size`: The largest decoded body accepted, checked after every coding.
+///
+/// # Returns
+/// - `Ok(Vec<u8>)` with the decoded body, or a copy of `body` if it has no
+///   content coding.
+/// - `Err(ErrorType::UnsupportedMediaType)` for any other coding.
+/// - `Err(ErrorType::PayloadTooLarge)` if the decoded body exceeds `max_size`.
+/// - `Err(ErrorType::BadRequest)` if the body is not validly encoded.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{decode_content, Encoding, HeaderMap};
+/// let headers = HeaderMap::from_lines(["Content-Encoding: deflate, gzip"].into_iter());
+/// let body = Encoding::Gzip.encode(&Encoding::Deflate.encode(b"hello"));
+/// assert_eq!(decode_content(&headers, &body, 1024).unwrap(), b"hello");
+/// ```
+pub fn decode_content(
+    headers: &HeaderMap,
+    body: &[u8],
+    max_size: usize,
+) -> Result<Vec<u8>, ErrorType> {
+    let mut body: Vec<u8> = body.to_vec();
+
+    for token in headers.get_list("Content-Encoding").iter().rev() {
+        let encoding: Encoding = match Encoding::from_token(token) {
+            Some(e @ (Encoding::Identity | Encoding::Gzip | Encoding::Deflate)) => e,
+            _ => {
+                return Err(ErrorType::UnsupportedMediaType(format!(
+                    "Unsupported Content-Encoding: {}",
+                    token
+                )));
+            }
+        };
+        body = encoding.decode(&body, max_size)?;
+    }
+
+    return Ok(body);
+}
+
+/// An incremental encoder that hands back the encoded bytes as they are
+/// produced, so a body can be compressed while it is streamed.
+pub(crate) enum Encoder {
+    Identity(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    pub(crate) fn new(encoding: Encoding) -> Self {
+        return match encoding {
+            Encoding::Identity => Encoder::Identity(Vec::new()),
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Compression::default())),
+            Encoding::Deflate => {
+                Encoder::Deflate(ZlibEncoder::new(Vec::new(), Compression::default()))
+            }
+            // quality 5 and a 4 MiB window keep brotli fast enough for dynamic content
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                5,
+                22,
+            ))),
+            Encoding::Zstd => Encoder::Zstd(
+                zstd::stream::write::Encoder::new(Vec::new(), 3)
+                    .expect("Failed to create zstd encoder"),
+            ),
+        };
+    }
+
+    /// Feeds `data` to the encoder and returns the encoded bytes produced so far.
+    pub(crate) fn write(&mut self, data: &[u8]) -> Vec<u8> {
+        let result = match self {
+            Encoder::Identity(buffer) => buffer.write_all(data),
+            Encoder::Gzip(e) => e.write_all(data),
+            Encoder::Deflate(e) => e.write_all(data),
+            Encoder::Brotli(e) => e.write_all(data),
+            Encoder::Zstd(e) => e.write_all(data),
+        };
+        result.expect("Failed to write body to encoder");
+        return self.take();
+    }
+
+    /// Flushes everything written so far and returns the encoded bytes.
+    pub(crate) fn flush(&mut self) -> Vec<u8> {
+        let result = match self {
+            Encoder::Identity(_) => Ok(()),
+            Encoder::Gzip(e) => e.flush(),
+            Encoder::Deflate(e) => e.flush(),
+            Encoder::Brotli(e) => e.flush(),
+            Encoder::Zstd(e) => e.flush(),
+        };
+        result.expect("Failed to flush encoder");
+        return self.take();
+    }
+
+    /// Ends the encoded stream and returns its remaining bytes.
+    pub(crate) fn finish(self) -> Vec<u8> {
+        let result = match self {
+            Encoder::Identity(buffer) => Ok(buffer),
+            Encoder::Gzip(e) => e.finish(),
+            Encoder::Deflate(e) => e.finish(),
+            Encoder::Brotli(e) => Ok(e.into_inner()),
+            Encoder::Zstd(e) => e.finish(),
+        };
+        return result.expect("Failed to finish encoding");
+    }
+
+    /// Removes the encoded bytes buffered so far.
+    fn take(&mut self) -> Vec<u8> {
+        let buffer: &mut Vec<u8> = match self {
+            Encoder::Identity(buffer) => buffer,
+            Encoder::Gzip(e) => e.get_mut(),
+            Encoder::Deflate(e) => e.get_mut(),
+            Encoder::Brotli(e) => e.get_mut(),
+            Encoder::Zstd(e) => e.get_mut(),
+        };
+        return std::mem::take(buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let line: String = format!("Accept-Encoding: {}", accept_encoding);
+        return negotiate_encoding(&HeaderMap::from_lines([line.as_str()].into_iter()));
+    }
+
+    #[test]
+    fn test_negotiate_encoding() {
+        assert_eq!(
+            negotiate_encoding(&HeaderMap::new()),
+            Some(Encoding::Identity)
+        );
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("GZIP, deflate"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip, deflate, br, zstd"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip;q=1.0, br;q=0.5"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("x-gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("*"), Some(Encoding::Brotli));
+        assert_eq!(negotiate("*;q=0.5, br;q=0"), Some(Encoding::Zstd));
+
+        assert_eq!(negotiate("gzip;q=0"), Some(Encoding::Identity));
+        assert_eq!(negotiate("compress"), Some(Encoding::Identity));
+        assert_eq!(negotiate("gzip;q=abc"), Some(Encoding::Identity));
+        assert_eq!(negotiate("identity;q=0"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate("*;q=0, identity"), Some(Encoding::Identity));
+    }
+
+    #[test]
+    fn test_encodings_round_trip() {
+        let data: Vec<u8> = b"hello world ".repeat(200);
+
+        let mut decoded: Vec<u8> = Vec::new();
+        flate2::read::GzDecoder::new(&Encoding::Gzip.encode(&data)[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut decoded: Vec<u8> = Vec::new();
+        flate2::read::ZlibDecoder::new(&Encoding::Deflate.encode(&data)[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let mut decoded: Vec<u8> = Vec::new();
+        brotli::Decompressor::new(&Encoding::Brotli.encode(&data)[..], 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        let decoded: Vec<u8> = zstd::decode_all(&Encoding::Zstd.encode(&data)[..]).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn test_decode_content() {
+        let data: Vec<u8> = b"hello world ".repeat(200);
+        let headers = |value: &str| {
+            let line: String = format!("Content-Encoding: {}", value);
+            return HeaderMap::from_lines([line.as_str()].into_iter());
+        };
+
+        assert_eq!(decode_content(&HeaderMap::new(), &data, 10).unwrap(), data);
+        assert_eq!(
+            decode_content(&headers("GZIP"), &Encoding::Gzip.encode(&data), 4096).unwrap(),
+            data
+        );
+        assert_eq!(
+            decode_content(&headers("deflate"), &Encoding::Deflate.encode(&data), 4096).unwrap(),
+            data
+        );
+        assert_eq!(
+            decode_content(&headers("identity"), &data, 4096).unwrap(),
+            data
+        );
+
+        let stacked: Vec<u8> = Encoding::Deflate.encode(&Encoding::Gzip.encode(&data));
+        assert_eq!(
+            decode_content(&headers("gzip, deflate"), &stacked, 4096).unwrap(),
+            data
+        );
+        assert_eq!(
+            decode_content(&headers("deflate, gzip"), &stacked, 4096),
+            Err(ErrorType::BadRequest(String::new()))
+        );
+
+        assert_eq!(
+            decode_content(&headers("br"), &Encoding::Brotli.encode(&data), 4096),
+            Err(ErrorType::UnsupportedMediaType(String::new()))
+        );
+        assert_eq!(
+            decode_content(&headers("compress"), &data, 4096),
+            Err(ErrorType::UnsupportedMediaType(String::new()))
+        );
+        assert_eq!(
+            decode_content(&headers("gzip"), b"not gzip", 4096),
+            Err(ErrorType::BadRequest(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_decode_limits_decompressed_size() {
+        // a megabyte of zeros compresses to about a kilobyte
+        let bomb: Vec<u8> = Encoding::Gzip.encode(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 4096);
+
+        assert_eq!(
+            Encoding::Gzip.decode(&bomb, 64 * 1024),
+            Err(ErrorType::PayloadTooLarge(String::new()))
+        );
+        assert_eq!(
+            Encoding::Gzip.decode(&bomb, 1024 * 1024).unwrap().len(),
+            1024 * 1024
+        );
+    }
+
+    #[test]
+    fn test_encoder_flushes_incrementally() {
+        let mut encoder = Encoder::new(Encoding::Brotli);
+        let mut output: Vec<u8> = encoder.write(b"first ");
+        output.extend_from_slice(&encoder.flush());
+        assert!(!output.is_empty());
+
+        output.extend_from_slice(&encoder.write(b"second"));
+        output.extend_from_slice(&encoder.finish());
+
+        let mut decoded: Vec<u8> = Vec::new();
+        brotli::Decompressor::new(&output[..], 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, b"first second");
+    }
+}
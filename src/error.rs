@@ -9,9 +9,16 @@ pub mod my_errors {
         WriteError(String),
         BadRequest(String),
         NotFound(String),
+        Forbidden(String),
+        Conflict(String),
         InternalServerError(String),
         ProtocolError(String),
         ConnectionError(String),
+        PayloadTooLarge(String),
+        UnsupportedMediaType(String),
+        UnprocessableEntity(String),
+        HeadersTooLarge(String),
+        Timeout(String),
     }
 
     pub struct Logger {
@@ -26,9 +33,16 @@ pub mod my_errors {
                 ErrorType::WriteError(msg) => msg,
                 ErrorType::BadRequest(msg) => msg,
                 ErrorType::NotFound(msg) => msg,
+                ErrorType::Forbidden(msg) => msg,
+                ErrorType::Conflict(msg) => msg,
                 ErrorType::InternalServerError(msg) => msg,
                 ErrorType::ProtocolError(msg) => msg,
                 ErrorType::ConnectionError(msg) => msg,
+                ErrorType::PayloadTooLarge(msg) => msg,
+                ErrorType::UnsupportedMediaType(msg) => msg,
+                ErrorType::UnprocessableEntity(msg) => msg,
+                ErrorType::HeadersTooLarge(msg) => msg,
+                ErrorType::Timeout(msg) => msg,
             }
         }
     }
@@ -41,9 +55,20 @@ pub mod my_errors {
                 ErrorType::WriteError(msg) => write!(f, "Error writing to file: {}", msg),
                 ErrorType::BadRequest(msg) => write!(f, "Error bad request: {}", msg),
                 ErrorType::NotFound(msg) => write!(f, "Error resource not found: {}", msg),
+                ErrorType::Forbidden(msg) => write!(f, "Error forbidden: {}", msg),
+                ErrorType::Conflict(msg) => write!(f, "Error conflict: {}", msg),
                 ErrorType::InternalServerError(msg) => write!(f, "Internal Server Error: {}", msg),
                 ErrorType::ProtocolError(msg) => write!(f, "Protocol Error: {}", msg),
                 ErrorType::ConnectionError(msg) => write!(f, "Connection Error: {}", msg),
+                ErrorType::PayloadTooLarge(msg) => write!(f, "Payload Too Large: {}", msg),
+                ErrorType::UnsupportedMediaType(msg) => {
+                    write!(f, "Unsupported Media Type: {}", msg)
+                }
+                ErrorType::UnprocessableEntity(msg) => {
+                    write!(f, "Unprocessable Entity: {}", msg)
+                }
+                ErrorType::HeadersTooLarge(msg) => write!(f, "Headers Too Large: {}", msg),
+                ErrorType::Timeout(msg) => write!(f, "Timeout: {}", msg),
             }
         }
     }
@@ -97,6 +122,24 @@ pub mod my_errors {
                         msg
                     )
                 }
+                ErrorType::Forbidden(msg) => {
+                    write!(
+                        f,
+                        "Forbidden Error: {{ file: {}, line: {} message: {} }}",
+                        file!(),
+                        line!(),
+                        msg
+                    )
+                }
+                ErrorType::Conflict(msg) => {
+                    write!(
+                        f,
+                        "Conflict Error: {{ file: {}, line: {} message: {} }}",
+                        file!(),
+                        line!(),
+                        msg
+                    )
+                }
                 ErrorType::InternalServerError(msg) => {
                     write!(
                         f,
@@ -122,6 +165,41 @@ pub mod my_errors {
                     line!(),
                     msg
                 ),
+                ErrorType::PayloadTooLarge(msg) => write!(
+                    f,
+                    "Payload Too Large Error: {{ file: {}, line: {} message: {} }}",
+                    file!(),
+                    line!(),
+                    msg
+                ),
+                ErrorType::UnsupportedMediaType(msg) => write!(
+                    f,
+                    "Unsupported Media Type Error: {{ file: {}, line: {} message: {} }}",
+                    file!(),
+                    line!(),
+                    msg
+                ),
+                ErrorType::UnprocessableEntity(msg) => write!(
+                    f,
+                    "Unprocessable Entity Error: {{ file: {}, line: {} message: {} }}",
+                    file!(),
+                    line!(),
+                    msg
+                ),
+                ErrorType::HeadersTooLarge(msg) => write!(
+                    f,
+                    "Headers Too Large Error: {{ file: {}, line: {} message: {} }}",
+                    file!(),
+                    line!(),
+                    msg
+                ),
+                ErrorType::Timeout(msg) => write!(
+                    f,
+                    "Timeout Error: {{ file: {}, line: {} message: {} }}",
+                    file!(),
+                    line!(),
+                    msg
+                ),
             }
         }
     }
@@ -169,6 +247,14 @@ pub mod my_errors {
                     ErrorType::NotFound(_) => true,
                     _ => false,
                 },
+                ErrorType::Forbidden(_) => match other {
+                    ErrorType::Forbidden(_) => true,
+                    _ => false,
+                },
+                ErrorType::Conflict(_) => match other {
+                    ErrorType::Conflict(_) => true,
+                    _ => false,
+                },
                 ErrorType::InternalServerError(_) => match other {
                     ErrorType::InternalServerError(_) => true,
                     _ => false,
@@ -181,6 +267,26 @@ pub mod my_errors {
                     ErrorType::InternalServerError(_) => true,
                     _ => false,
                 },
+                ErrorType::PayloadTooLarge(_) => match other {
+                    ErrorType::PayloadTooLarge(_) => true,
+                    _ => false,
+                },
+                ErrorType::UnsupportedMediaType(_) => match other {
+                    ErrorType::UnsupportedMediaType(_) => true,
+                    _ => false,
+                },
+                ErrorType::UnprocessableEntity(_) => match other {
+                    ErrorType::UnprocessableEntity(_) => true,
+                    _ => false,
+                },
+                ErrorType::HeadersTooLarge(_) => match other {
+                    ErrorType::HeadersTooLarge(_) => true,
+                    _ => false,
+                },
+                ErrorType::Timeout(_) => match other {
+                    ErrorType::Timeout(_) => true,
+                    _ => false,
+                },
             }
         }
     }
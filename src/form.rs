@@ -0,0 +1,673 @@
+use crate::{
+    find_subsequence, percent_decode, ErrorType, HeaderMap, DEFAULT_MAX_UPLOAD_SIZE,
+    STREAM_CHUNK_SIZE,
+};
+use rand::Rng;
+use std::io::Cursor;
+use std::path::PathBuf;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Default limit on the size of a single text field of a form.
+pub const DEFAULT_MAX_FIELD_SIZE: usize = 64 * 1024;
+/// Default limit on the number of fields and files in a form.
+pub const DEFAULT_MAX_PARTS: usize = 64;
+/// Limit on the size of the headers of one multipart part.
+const MAX_PART_HEADER_SIZE: usize = 8 * 1024;
+
+/// Limits and storage settings used when parsing form bodies.
+#[derive(Debug, Clone)]
+pub struct FormConfig {
+    /// Directory the file parts of `multipart/form-data` bodies are written to.
+    pub upload_dir: PathBuf,
+    /// Maximum size of a single text field.
+    pub max_field_size: usize,
+    /// Maximum size of a single uploaded file.
+    pub max_file_size: u64,
+    /// Maximum number of fields and files in one body.
+    pub max_parts: usize,
+}
+
+impl Default for FormConfig {
+    fn default() -> Self {
+        return FormConfig {
+            upload_dir: std::env::temp_dir(),
+            max_field_size: DEFAULT_MAX_FIELD_SIZE,
+            max_file_size: DEFAULT_MAX_UPLOAD_SIZE,
+            max_parts: DEFAULT_MAX_PARTS,
+        };
+    }
+}
+
+/// A file part of a `multipart/form-data` body, already written to disk.
+///
+/// The file is stored under a random name in [`FormConfig::upload_dir`] and
+/// is not removed automatically: move it somewhere permanent or delete it
+/// once the request has been handled. `file_name` is the name sent by the
+/// client and must not be trusted as a path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormFile {
+    /// The name of the form field.
+    pub name: String,
+    /// The client's name for the file, without any directory components.
+    pub file_name: Option<String>,
+    /// The `Content-Type` of the part, `application/octet-stream` if none was sent.
+    pub content_type: String,
+    /// Where the contents were written.
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+/// The fields and files of a form body, in the order they were sent.
+///
+/// # Example
+/// ```rust
+/// use rust_server::Form;
+/// let form = Form {
+///     fields: rust_server::parse_urlencoded("tag=a&tag=b&name=J%C3%BCrgen").unwrap(),
+///     files: Vec::new(),
+/// };
+/// assert_eq!(form.get("name"), Some("Jürgen"));
+/// assert_eq!(form.get_all("tag"), vec!["a", "b"]);
+/// ```
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Form {
+    pub fields: Vec<(String, String)>,
+    pub files: Vec<FormFile>,
+}
+
+impl Form {
+    /// Returns the first value of the field `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self
+            .fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str());
+    }
+
+    /// Returns every value of the field `name`.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        return self
+            .fields
+            .iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .collect();
+    }
+
+    /// Returns the first file uploaded under the field `name`.
+    pub fn file(&self, name: &str) -> Option<&FormFile> {
+        return self.files.iter().find(|f| f.name == name);
+    }
+}
+
+/// Parses `application/x-www-form-urlencoded` data, such as a query string
+/// or a form body, into its name/value pairs.
+///
+/// # Returns
+/// - `Ok(pairs)` in the order they appear, with `+` and escapes decoded.
+/// - `Err(ErrorType::BadRequest)` if an escape is malformed or does not decode to UTF-8.
+pub fn parse_urlencoded(input: &str) -> Result<Vec<(String, String)>, ErrorType> {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+
+    for pair in input.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once('=') {
+            Some((k, v)) => (k, v),
+            None => (pair, ""),
+        };
+
+        let key: String = percent_decode(&key.replace('+', " "))?;
+        let value: String = percent_decode(&value.replace('+', " "))?;
+        pairs.push((key, value));
+    }
+
+    return Ok(pairs);
+}
+
+/// Returns the lowercased media type of a `Content-Type` value without its
+/// parameters, e.g. `multipart/form-data`.
+pub fn media_type(content_type: &str) -> String {
+    return content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+}
+
+/// Returns the parameter `name` of a header value such as
+/// `form-data; name="file"; filename="a;b.txt"`, with quotes and escapes removed.
+pub fn header_param(value: &str, name: &str) -> Option<String> {
+    let (_, mut rest) = value.split_once(';')?;
+
+    loop {
+        rest = rest.trim_start_matches([' ', '\t', ';']);
+        if rest.is_empty() {
+            return None;
+        }
+
+        let (key, after_key) = rest.split_once('=')?;
+        let key: &str = key.trim();
+
+        let (param, remaining): (String, &str) = if let Some(quoted) = after_key.strip_prefix('"') {
+            let mut param: String = String::new();
+            let mut chars = quoted.char_indices();
+            let mut end: usize = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            param.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => param.push(c),
+                }
+            }
+            (param, &quoted[end..])
+        } else {
+            match after_key.split_once(';') {
+                Some((param, remaining)) => (param.trim().to_string(), remaining),
+                None => (after_key.trim().to_string(), ""),
+            }
+        };
+
+        if key.eq_ignore_ascii_case(name) {
+            return Some(param);
+        }
+        rest = remaining;
+    }
+}
+
+/// Parses a form body, choosing the format from its `Content-Type`.
+///
+/// # Arguments
+/// - `headers`: The request headers.
+/// - `body`: The request body.
+/// - `config`: Limits, and where uploaded files are written.
+///
+/// # Returns
+/// - `Ok(Form)` for `application/x-www-form-urlencoded` and
+///   `multipart/form-data` bodies.
+/// - `Err(ErrorType::UnsupportedMediaType)` for any other `Content-Type`.
+/// - `Err(ErrorType::BadRequest)` if the body is malformed.
+/// - `Err(ErrorType::PayloadTooLarge)` if a limit in `config` is exceeded.
+pub async fn parse_form(
+    headers: &HeaderMap,
+    body: &[u8],
+    config: &FormConfig,
+) -> Result<Form, ErrorType> {
+    let content_type: &str = match headers.get("Content-Type") {
+        Some(c) => c,
+        None => {
+            return Err(ErrorType::UnsupportedMediaType(String::from(
+                "Missing Content-Type for form body",
+            )))
+        }
+    };
+
+    match media_type(content_type).as_str() {
+        "application/x-www-form-urlencoded" => {
+            let text: &str = match std::str::from_utf8(body) {
+                Ok(t) => t,
+                Err(_) => {
+                    return Err(ErrorType::BadRequest(String::from(
+                        "Form body is not valid UTF-8",
+                    )))
+                }
+            };
+
+            let fields: Vec<(String, String)> = parse_urlencoded(text)?;
+            if fields.len() > config.max_parts {
+                return Err(ErrorType::PayloadTooLarge(format!(
+                    "Form has more than {} fields",
+                    config.max_parts
+                )));
+            }
+            if fields.iter().any(|(_, v)| v.len() > config.max_field_size) {
+                return Err(ErrorType::PayloadTooLarge(format!(
+                    "Form field larger than {} bytes",
+                    config.max_field_size
+                )));
+            }
+
+            return Ok(Form {
+                fields,
+                files: Vec::new(),
+            });
+        }
+        "multipart/form-data" => {
+            let boundary: String = match header_param(content_type, "boundary") {
+                Some(b) => b,
+                None => {
+                    return Err(ErrorType::BadRequest(String::from(
+                        "Missing multipart boundary",
+                    )))
+                }
+            };
+            return parse_multipart(&mut Cursor::new(body), &boundary, config).await;
+        }
+        other => {
+            return Err(ErrorType::UnsupportedMediaType(format!(
+                "Unsupported form Content-Type: {}",
+                other
+            )))
+        }
+    }
+}
+
+/// Parses a `multipart/form-data` body read from `reader`.
+///
+/// The body is processed in chunks of at most [`STREAM_CHUNK_SIZE`] bytes:
+/// file parts are written to [`FormConfig::upload_dir`] as they are read,
+/// so only text fields are held in memory. If parsing fails, every file
+/// written so far is removed again.
+///
+/// # Arguments
+/// - `reader`: The body.
+/// - `boundary`: The `boundary` parameter of the `Content-Type` header.
+/// - `config`: Limits, and where uploaded files are written.
+///
+/// # Returns
+/// - `Ok(Form)` with the text fields and the uploaded files.
+/// - `Err(ErrorType::BadRequest)` if the body is malformed.
+/// - `Err(ErrorType::PayloadTooLarge)` if a limit in `config` is exceeded.
+/// - `Err(ErrorType::WriteError)` if a file cannot be written.
+pub async fn parse_multipart<R>(
+    reader: &mut R,
+    boundary: &str,
+    config: &FormConfig,
+) -> Result<Form, ErrorType>
+where
+    R: AsyncRead + Unpin,
+{
+    if boundary.is_empty() || boundary.len() > 70 {
+        return Err(ErrorType::BadRequest(String::from(
+            "Invalid multipart boundary",
+        )));
+    }
+
+    let mut form: Form = Form::default();
+    let result = read_parts(reader, boundary, config, &mut form).await;
+
+    if let Err(e) = result {
+        for file in &form.files {
+            let _ = fs::remove_file(&file.path).await;
+        }
+        return Err(e);
+    }
+
+    return Ok(form);
+}
+
+async fn read_parts<R>(
+    reader: &mut R,
+    boundary: &str,
+    config: &FormConfig,
+    form: &mut Form,
+) -> Result<(), ErrorType>
+where
+    R: AsyncRead + Unpin,
+{
+    // every delimiter is preceded by a line break, except the first one which
+    // may open the body, so start the stream with one
+    let mut parts: PartReader<R> = PartReader {
+        reader,
+        buffer: b"\r\n".to_vec(),
+    };
+    let delimiter: Vec<u8> = format!("\r\n--{}", boundary).into_bytes();
+
+    // skip the preamble
+    parts
+        .copy_until(&delimiter, &mut tokio::io::sink(), u64::MAX)
+        .await?;
+
+    loop {
+        if parts.peek(2).await? == b"--" {
+            // the closing delimiter, anything after it is an epilogue
+            return Ok(());
+        }
+
+        if form.fields.len() + form.files.len() >= config.max_parts {
+            return Err(ErrorType::PayloadTooLarge(format!(
+                "Form has more than {} parts",
+                config.max_parts
+            )));
+        }
+
+        let head: Vec<u8> = parts.read_until(b"\r\n\r\n", MAX_PART_HEADER_SIZE).await?;
+        let head: &str = match std::str::from_utf8(&head) {
+            Ok(h) => {
+                // the line break after the delimiter, optionally preceded by padding
+                let h: &str = h.trim_start_matches([' ', '\t']);
+                h.strip_prefix("\r\n").unwrap_or(h)
+            }
+            Err(_) => {
+                return Err(ErrorType::BadRequest(String::from(
+                    "Invalid multipart part headers",
+                )))
+            }
+        };
+        let headers: HeaderMap = HeaderMap::from_lines(head.split("\r\n"));
+
+        let disposition: &str = match headers.get("Content-Disposition") {
+            Some(d) if media_type(d) == "form-data" => d,
+            _ => {
+                return Err(ErrorType::BadRequest(String::from(
+                    "Multipart part without form-data disposition",
+                )))
+            }
+        };
+        let name: String = match header_param(disposition, "name") {
+            Some(n) => n,
+            None => {
+                return Err(ErrorType::BadRequest(String::from(
+                    "Multipart part without a name",
+                )))
+            }
+        };
+
+        match header_param(disposition, "filename") {
+            Some(file_name) => {
+                let path: PathBuf = config
+                    .upload_dir
+                    .join(format!("upload-{:016x}", rand::thread_rng().gen::<u64>()));
+                let mut file = match OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .await
+                {
+                    Ok(f) => f,
+                    Err(e) => {
+                        return Err(ErrorType::WriteError(format!(
+                            "Failed to create {}: {}",
+                            path.display(),
+                            e
+                        )))
+                    }
+                };
+
+                // record the file first so it is removed if the copy fails
+                form.files.push(FormFile {
+                    name,
+                    // browsers on some platforms send the full client path
+                    file_name: file_name
+                        .rsplit(['/', '\\'])
+                        .next()
+                        .filter(|n| !n.is_empty())
+                        .map(|n| n.to_string()),
+                    content_type: headers
+                        .get("Content-Type")
+                        .unwrap_or("application/octet-stream")
+                        .trim()
+                        .to_string(),
+                    path,
+                    size: 0,
+                });
+
+                let size: u64 = parts
+                    .copy_until(&delimiter, &mut file, config.max_file_size)
+                    .await?;
+                if let Err(e) = file.flush().await {
+                    return Err(ErrorType::WriteError(format!(
+                        "Failed to write uploaded file: {}",
+                        e
+                    )));
+                }
+                if let Some(uploaded) = form.files.last_mut() {
+                    uploaded.size = size;
+                }
+            }
+            None => {
+                let value: Vec<u8> = parts.read_until(&delimiter, config.max_field_size).await?;
+                let value: String = match String::from_utf8(value) {
+                    Ok(v) => v,
+                    Err(_) => {
+                        return Err(ErrorType::BadRequest(format!(
+                            "Form field {} is not valid UTF-8",
+                            name
+                        )))
+                    }
+                };
+                form.fields.push((name, value));
+            }
+        }
+    }
+}
+
+/// Buffers a multipart body while it is split at its delimiters.
+struct PartReader<'a, R> {
+    reader: &'a mut R,
+    buffer: Vec<u8>,
+}
+
+impl<R> PartReader<'_, R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Reads another chunk into the buffer. Returns `false` at the end of the body.
+    async fn fill(&mut self) -> Result<bool, ErrorType> {
+        let mut chunk: Vec<u8> = vec![0; STREAM_CHUNK_SIZE];
+        return match self.reader.read(&mut chunk).await {
+            Ok(0) => Ok(false),
+            Ok(n) => {
+                self.buffer.extend_from_slice(&chunk[..n]);
+                Ok(true)
+            }
+            Err(e) => Err(ErrorType::ReadError(format!(
+                "Failed to read multipart body: {}",
+                e
+            ))),
+        };
+    }
+
+    /// Returns the next `n` bytes without consuming them.
+    async fn peek(&mut self, n: usize) -> Result<&[u8], ErrorType> {
+        while self.buffer.len() < n {
+            if !self.fill().await? {
+                return Err(unexpected_end());
+            }
+        }
+        return Ok(&self.buffer[..n]);
+    }
+
+    /// Consumes everything up to and including `needle`, returning what came
+    /// before it. Fails with `PayloadTooLarge` if that is more than `limit` bytes.
+    async fn read_until(&mut self, needle: &[u8], limit: usize) -> Result<Vec<u8>, ErrorType> {
+        loop {
+            if let Some(i) = find_subsequence(&self.buffer, needle) {
+                if i > limit {
+                    return Err(too_large(limit as u64));
+                }
+                let data: Vec<u8> = self.buffer[..i].to_vec();
+                self.buffer.drain(..i + needle.len());
+                return Ok(data);
+            }
+
+            if self.buffer.len() > limit + needle.len() {
+                return Err(too_large(limit as u64));
+            }
+            if !self.fill().await? {
+                return Err(unexpected_end());
+            }
+        }
+    }
+
+    /// Copies everything up to `needle` to `writer` and consumes the needle,
+    /// holding at most one chunk in memory.
+    ///
+    /// # Returns
+    /// The number of bytes copied, or `PayloadTooLarge` once more than
+    /// `limit` bytes come before the needle.
+    async fn copy_until<W>(
+        &mut self,
+        needle: &[u8],
+        writer: &mut W,
+        limit: u64,
+    ) -> Result<u64, ErrorType>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut copied: u64 = 0;
+
+        loop {
+            let (end, found): (usize, bool) = match find_subsequence(&self.buffer, needle) {
+                Some(i) => (i, true),
+                // the tail may hold the start of the needle
+                None => (self.buffer.len().saturating_sub(needle.len() - 1), false),
+            };
+
+            copied += end as u64;
+            if copied > limit {
+                return Err(too_large(limit));
+            }
+            if let Err(e) = writer.write_all(&self.buffer[..end]).await {
+                return Err(ErrorType::WriteError(format!(
+                    "Failed to write uploaded file: {}",
+                    e
+                )));
+            }
+
+            if found {
+                self.buffer.drain(..end + needle.len());
+                return Ok(copied);
+            }
+            self.buffer.drain(..end);
+
+            if !self.fill().await? {
+                return Err(unexpected_end());
+            }
+        }
+    }
+}
+
+fn unexpected_end() -> ErrorType {
+    return ErrorType::BadRequest(String::from("Unexpected end of multipart body"));
+}
+
+fn too_large(limit: u64) -> ErrorType {
+    return ErrorType::PayloadTooLarge(format!("Form part larger than {} bytes", limit));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config(name: &str) -> FormConfig {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("rust_server_form_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        return FormConfig {
+            upload_dir: dir,
+            ..FormConfig::default()
+        };
+    }
+
+    fn multipart_headers() -> HeaderMap {
+        return HeaderMap::from_lines(
+            ["Content-Type: multipart/form-data; boundary=\"XyZ\""].into_iter(),
+        );
+    }
+
+    #[test]
+    fn test_header_param() {
+        let value = "form-data; name=\"file\"; filename=\"a;\\\"b\\\".txt\"";
+        assert_eq!(header_param(value, "name").as_deref(), Some("file"));
+        assert_eq!(
+            header_param(value, "filename").as_deref(),
+            Some("a;\"b\".txt")
+        );
+        assert_eq!(
+            header_param("multipart/form-data; boundary=abc", "boundary").as_deref(),
+            Some("abc")
+        );
+        assert!(header_param("form-data", "name").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_urlencoded_form() {
+        let headers = HeaderMap::from_lines(
+            ["Content-Type: application/x-www-form-urlencoded; charset=UTF-8"].into_iter(),
+        );
+        let form = parse_form(&headers, b"name=a+b&x=%26&x=2&flag", &FormConfig::default())
+            .await
+            .unwrap();
+
+        assert_eq!(form.get("name"), Some("a b"));
+        assert_eq!(form.get_all("x"), vec!["&", "2"]);
+        assert_eq!(form.get("flag"), Some(""));
+
+        let headers = HeaderMap::from_lines(["Content-Type: application/json"].into_iter());
+        assert_eq!(
+            parse_form(&headers, b"{}", &FormConfig::default())
+                .await
+                .unwrap_err(),
+            ErrorType::UnsupportedMediaType(String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multipart_form() {
+        let config = test_config("multipart");
+        let mut data: Vec<u8> = vec![0, 159, 146, 150, b'\r', b'\n', b'-', b'-'];
+        data.extend_from_slice(&[7; 3 * STREAM_CHUNK_SIZE]);
+
+        let mut body: Vec<u8> = b"preamble\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            line one\r\nline two\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"upload\"; filename=\"C:\\\\docs\\\\a.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n"
+            .to_vec();
+        body.extend_from_slice(&data);
+        body.extend_from_slice(b"\r\n--XyZ--\r\nepilogue");
+
+        let form = parse_form(&multipart_headers(), &body, &config)
+            .await
+            .unwrap();
+
+        assert_eq!(form.get("title"), Some("line one\r\nline two"));
+        let file = form.file("upload").unwrap();
+        assert_eq!(file.file_name.as_deref(), Some("a.bin"));
+        assert_eq!(file.content_type, "application/octet-stream");
+        assert_eq!(file.size, data.len() as u64);
+        assert!(file.path.starts_with(&config.upload_dir));
+        assert_eq!(std::fs::read(&file.path).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_errors_remove_files() {
+        let config = FormConfig {
+            max_file_size: 4,
+            ..test_config("errors")
+        };
+
+        let body = b"--XyZ\r\n\
+            Content-Disposition: form-data; name=\"a\"; filename=\"a.txt\"\r\n\r\n\
+            1234\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"b\"; filename=\"b.txt\"\r\n\r\n\
+            12345\r\n--XyZ--\r\n";
+        assert_eq!(
+            parse_form(&multipart_headers(), body, &config)
+                .await
+                .unwrap_err(),
+            ErrorType::PayloadTooLarge(String::new())
+        );
+
+        let truncated = b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nvalue";
+        assert_eq!(
+            parse_form(&multipart_headers(), truncated, &config)
+                .await
+                .unwrap_err(),
+            ErrorType::BadRequest(String::new())
+        );
+
+        assert_eq!(std::fs::read_dir(&config.upload_dir).unwrap().count(), 0);
+    }
+}
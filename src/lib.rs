@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return, clippy::match_like_matches_macro)]
+
 pub mod error;
 pub use crate::error::my_errors::{ErrorType, Logger};
 
@@ -7,12 +9,53 @@ pub use shutdown::*;
 pub mod api;
 pub use api::*;
 
+pub mod router;
+pub use router::*;
+
+pub mod middleware;
+pub use middleware::*;
+
+pub mod static_files;
+pub use static_files::*;
+
+pub mod uploads;
+pub use uploads::*;
+
+pub mod users;
+pub use users::*;
+
+pub mod sessions;
+pub use sessions::*;
+
+pub mod cookie;
+pub use cookie::*;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "sqlite")]
+pub use sqlite::*;
+
+pub mod form;
+pub use form::*;
+
+pub mod range;
+pub use range::*;
+
 pub mod socket;
 pub use socket::*;
 
 pub mod request;
 pub use request::*;
 
+pub mod encoding;
+pub use encoding::{
+    decode_content, negotiate_encoding, negotiate_encoding_among, Encoding, MIN_COMPRESSION_SIZE,
+    REQUEST_ENCODINGS,
+};
+
+pub mod body;
+pub use body::{encode_chunk, BodyReader, BodyStream, StreamBody, STREAM_CHUNK_SIZE};
+
 pub mod connection;
 pub use crate::connection::connections::*;
 
@@ -1,14 +1,16 @@
+#![allow(clippy::needless_return, clippy::match_like_matches_macro)]
+
 use colored::Colorize;
 use log::{error, info};
 use rust_server::connection::connections::*;
 use rust_server::error::my_errors::*;
 use rust_server::request_validation::handle_request;
-use rust_server::{handle_response, my_socket::*, request::*, shutdown::*};
+use rust_server::{api_router, my_socket::*, request::*, shutdown::*, Router, REQUEST_ENCODINGS};
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
 use tokio::sync::{broadcast, Mutex, Semaphore};
 use tokio::time::timeout;
@@ -51,7 +53,7 @@ async fn main() -> Result<(), ErrorType> {
     let listener = match get_listener(socket) {
         Ok(s) => s,
         Err(e) => {
-            error("Failed to create TCP listener");
+            error!("Failed to create TCP listener");
             logger.log_error(&e);
             panic!(
                 "{}",
@@ -74,12 +76,13 @@ async fn main() -> Result<(), ErrorType> {
         listener,
         connection_limit: Arc::new(Semaphore::new(5)),
         shutdown_tx: Arc::clone(&tx),
+        config: ConnectionConfig::default(),
     };
 
     print_server_info(port);
 
     tokio::select! {
-        _ = run_server(listener,logger) => {
+        _ = run_server(listener, logger, Arc::new(api_router())) => {
             println!("{}","Gracefull shutdown completed successfully.".cyan());
         }
         _ = shutdown_signal => {
@@ -94,10 +97,15 @@ async fn main() -> Result<(), ErrorType> {
     Ok(())
 }
 
-async fn run_server(mut listener: Listener, logger: Logger) -> Result<(), ErrorType> {
+async fn run_server(
+    mut listener: Listener,
+    logger: Logger,
+    router: Arc<Router>,
+) -> Result<(), ErrorType> {
     let logger = Arc::new(Mutex::new(logger));
     loop {
         let logger = Arc::clone(&logger);
+        let router = Arc::clone(&router);
 
         // Returns an error when the semaphore has been closed, since I do not close it
         // unwrap should be safe
@@ -109,7 +117,7 @@ async fn run_server(mut listener: Listener, logger: Logger) -> Result<(), ErrorT
             .unwrap();
 
         let (client, addr): (TcpStream, SocketAddr) = match listener.accept().await {
-            Ok((c, a)) => (c, a.into()),
+            Ok((c, a)) => (c, a),
             Err(_) => {
                 error!("Failed to connect to the client");
                 return Err(ErrorType::SocketError(String::from(
@@ -124,49 +132,91 @@ async fn run_server(mut listener: Listener, logger: Logger) -> Result<(), ErrorT
             shutdown_rx: listener.shutdown_tx.lock().await.subscribe(),
         };
 
+        let config: ConnectionConfig = listener.config;
+
         tokio::spawn(async move {
             let logger = Arc::clone(&logger);
+            let mut parser = RequestParser::new(config.limits);
+            let mut requests_served: usize = 0;
 
             loop {
-                let mut buffer: [u8; 4096] = [0; 4096];
-                let bytes_read =
-                    match timeout(Duration::from_secs(5), handler.stream.read(&mut buffer)).await {
-                        Ok(Ok(number_bytes)) if number_bytes == 0 => break,
-                        Ok(Ok(number_bytes)) => number_bytes,
-                        Ok(Err(_)) => {
-                            let e =
-                                ErrorType::SocketError(String::from("Error connecting to client"));
-                            error!("Failed to connect to client");
-                            logger.lock().await.log_error(&e);
-                            break;
-                        }
-                        Err(_) => break,
-                    };
-
-                // check request for any potential maliciousness
-                match handle_request(&buffer[..bytes_read]) {
-                    Ok(_) => (),
+                let raw_request: Vec<u8> = match read_request(
+                    &mut handler,
+                    &mut parser,
+                    config.keep_alive_timeout,
+                )
+                .await
+                {
+                    Ok(Some(r)) => r,
+                    Ok(None) => break,
                     Err(e) => {
                         logger.lock().await.log_error(&e);
+                        if let Some(mut response) = error_response(&e) {
+                            let _ = response.write_to(&mut handler.stream).await;
+                        }
+                        break;
                     }
                 };
 
-                let request: Request = match Request::new(&buffer[..bytes_read]) {
+                // check request for any potential maliciousness
+                if let Err(e) = handle_request(&raw_request) {
+                    logger.lock().await.log_error(&e);
+                    if let Some(mut response) = error_response(&e) {
+                        let _ = response.write_to(&mut handler.stream).await;
+                    }
+                    break;
+                }
+
+                let mut request: Request = match Request::new(&raw_request) {
                     Ok(r) => {
                         r.print();
                         r
                     }
                     Err(e) => {
                         logger.lock().await.log_error(&e);
+                        if let Some(mut response) = error_response(&e) {
+                            let _ = response.write_to(&mut handler.stream).await;
+                        }
                         break;
                     }
                 };
 
-                let mut response = handle_response(request, Arc::clone(&logger)).await;
+                // handlers always see the body without its content coding
+                if let Err(e) = request.decode_body(config.limits.max_decoded_body_size) {
+                    logger.lock().await.log_error(&e);
+                    if let Some(mut response) = error_response(&e) {
+                        let _ = response.write_to(&mut handler.stream).await;
+                    }
+                    break;
+                }
+
+                requests_served += 1;
+                let mut keep_alive: bool =
+                    request.keep_alive() && requests_served < config.max_requests;
+                let http10: bool = request.protocol == Protocol::Http10;
 
-                if let Err(_) = handler.stream.write_all(&response.to_bytes()).await {
-                    let e = ErrorType::SocketError(String::from("Error connecting to client"));
+                let mut response = router.handle(request, Arc::clone(&logger)).await;
+
+                // HTTP/1.0 has no chunked coding, so a body of unknown length
+                // is sent as is and ends when the connection is closed
+                if http10 {
+                    response.allow_chunked = false;
+                    keep_alive = keep_alive && response.has_known_length();
+                }
+                set_connection_headers(
+                    &mut response,
+                    keep_alive,
+                    &config,
+                    config.max_requests.saturating_sub(requests_served),
+                );
+
+                if let Err(e) = response.send(&mut handler.stream).await {
                     logger.lock().await.log_error(&e);
+                    break;
+                }
+
+                if !keep_alive {
+                    break;
                 }
 
                 if !handler.shutdown_rx.is_empty() {
@@ -191,6 +241,106 @@ async fn run_server(mut listener: Listener, logger: Logger) -> Result<(), ErrorT
     }
 }
 
+/// Reads from the connection until the parser holds one complete request.
+///
+/// Requests that were pipelined behind an earlier one are already buffered in
+/// the parser and are returned without reading from the socket.
+///
+/// # Returns
+/// - `Ok(Some(bytes))` with the raw request once it has been fully received.
+/// - `Ok(None)` if the client closed the connection or stayed idle past `idle_timeout`.
+/// - `Err(ErrorType::Timeout)` if the client stalled part way through a request.
+/// - `Err(ErrorType)` if reading failed or the request exceeded the configured limits.
+async fn read_request(
+    handler: &mut ConnectionHandler,
+    parser: &mut RequestParser,
+    idle_timeout: Duration,
+) -> Result<Option<Vec<u8>>, ErrorType> {
+    loop {
+        if let Some(request) = parser.next_request()? {
+            return Ok(Some(request));
+        }
+
+        let mut buffer: [u8; 4096] = [0; 4096];
+        let bytes_read = match timeout(idle_timeout, handler.stream.read(&mut buffer)).await {
+            Ok(Ok(0)) => return Ok(None),
+            Ok(Ok(number_bytes)) => number_bytes,
+            Ok(Err(_)) => {
+                error!("Failed to connect to client");
+                return Err(ErrorType::SocketError(String::from(
+                    "Error connecting to client",
+                )));
+            }
+            Err(_) if parser.is_empty() => return Ok(None),
+            Err(_) => {
+                return Err(ErrorType::Timeout(String::from(
+                    "Client did not finish sending the request",
+                )));
+            }
+        };
+
+        parser.feed(&buffer[..bytes_read]);
+    }
+}
+
+/// Builds the response sent to the client before closing a connection whose
+/// request could not be read.
+///
+/// Returns `None` for errors that leave no client to respond to.
+fn error_response(error: &ErrorType) -> Option<Response> {
+    let code: HttpCode = match error {
+        ErrorType::HeadersTooLarge(_) => HttpCode::RequestHeaderFieldsTooLarge,
+        ErrorType::PayloadTooLarge(_) => HttpCode::PayloadTooLarge,
+        ErrorType::BadRequest(_) => HttpCode::BadRequest,
+        ErrorType::Timeout(_) => HttpCode::RequestTimeout,
+        ErrorType::UnsupportedMediaType(_) => HttpCode::UnsupportedMediaType,
+        _ => return None,
+    };
+
+    let mut response = Response::new(Protocol::Http, code, ContentType::Text, false)
+        .body(error.get_msg().as_bytes().to_vec());
+    response.add_header(String::from("Connection"), String::from("close"));
+
+    // tell the client which request body codings it may use instead
+    if let ErrorType::UnsupportedMediaType(_) = error {
+        response.add_header(
+            String::from("Accept-Encoding"),
+            String::from(REQUEST_ENCODINGS),
+        );
+    }
+
+    return Some(response);
+}
+
+/// Adds the `Connection` and `Keep-Alive` headers that tell the client whether
+/// the connection stays open after this response.
+///
+/// # Arguments
+/// - `response`: The response about to be written.
+/// - `keep_alive`: Whether the connection will be kept open.
+/// - `config`: The connection settings advertised in the `Keep-Alive` header.
+/// - `remaining`: How many more requests the connection will accept.
+fn set_connection_headers(
+    response: &mut Response,
+    keep_alive: bool,
+    config: &ConnectionConfig,
+    remaining: usize,
+) {
+    if keep_alive {
+        response.add_header(String::from("Connection"), String::from("keep-alive"));
+        response.add_header(
+            String::from("Keep-Alive"),
+            format!(
+                "timeout={}, max={}",
+                config.keep_alive_timeout.as_secs(),
+                remaining
+            ),
+        );
+    } else {
+        response.add_header(String::from("Connection"), String::from("close"));
+    }
+}
+
 fn print_server_info(port: u16) {
     println!("{}", "Server started:".cyan());
     println!(
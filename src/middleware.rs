@@ -0,0 +1,735 @@
+use crate::api::{teapot_response, verify_cookie};
+use crate::{
+    ContentType, Encoding, ErrorType, HttpCode, HttpMethod, Logger, Protocol, Request, Response,
+    Router, SessionManager,
+};
+use colored::Colorize;
+use log::{error, info};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// The future returned by a middleware.
+pub type MiddlewareFuture<'a> = Pin<Box<dyn Future<Output = Response> + Send + 'a>>;
+
+/// A layer that wraps the request to response path of a [`Router`].
+///
+/// A middleware receives the request together with [`Next`], the rest of the
+/// pipeline. It may inspect or modify the request before calling
+/// `next.run(..)`, inspect or modify the response afterwards, or return a
+/// response of its own without calling `next` at all to short-circuit the
+/// request.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{Logger, Middleware, MiddlewareFuture, Next, Request};
+/// use std::sync::Arc;
+/// use tokio::sync::Mutex;
+///
+/// struct PoweredBy;
+///
+/// impl Middleware for PoweredBy {
+///     fn handle<'a>(
+///         &'a self,
+///         request: Request,
+///         logger: Arc<Mutex<Logger>>,
+///         next: Next<'a>,
+///     ) -> MiddlewareFuture<'a> {
+///         Box::pin(async move {
+///             let mut response = next.run(request, logger).await;
+///             response.add_header(String::from("X-Powered-By"), String::from("Ferris"));
+///             response
+///         })
+///     }
+/// }
+/// ```
+pub trait Middleware: Send + Sync {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a>;
+}
+
+/// The remainder of the middleware pipeline, ending in the router's route table.
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    router: &'a Router,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middlewares: &'a [Arc<dyn Middleware>], router: &'a Router) -> Self {
+        return Next {
+            middlewares,
+            router,
+        };
+    }
+
+    /// Passes the request on to the next middleware, or to the matching
+    /// route handler once every middleware has run.
+    pub fn run(self, request: Request, logger: Arc<Mutex<Logger>>) -> MiddlewareFuture<'a> {
+        return match self.middlewares.split_first() {
+            Some((middleware, rest)) => middleware.handle(
+                request,
+                logger,
+                Next {
+                    middlewares: rest,
+                    router: self.router,
+                },
+            ),
+            None => Box::pin(self.router.dispatch(request, logger)),
+        };
+    }
+}
+
+/// Logs the method, URI and response status of every request.
+pub struct RequestLogger;
+
+impl Middleware for RequestLogger {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            let method: HttpMethod = request.method.clone();
+            let uri: String = request.uri.clone();
+
+            let response = next.run(request, logger).await;
+
+            info!("{} {} status: {}", method, uri, response.code);
+            return response;
+        });
+    }
+}
+
+/// Measures how long the rest of the pipeline takes and reports it in a
+/// `Server-Timing` header.
+pub struct Timing;
+
+impl Middleware for Timing {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            let start: Instant = Instant::now();
+            let uri: String = request.uri.clone();
+
+            let mut response = next.run(request, logger).await;
+
+            let elapsed: f64 = start.elapsed().as_secs_f64() * 1000.0;
+            info!("{} handled in {:.3}ms", uri, elapsed);
+            response.add_header(
+                String::from("Server-Timing"),
+                format!("app;dur={:.3}", elapsed),
+            );
+            return response;
+        });
+    }
+}
+
+/// Compresses response bodies with the best encoding the client accepts,
+/// negotiated from `Accept-Encoding`. Bodies that are too small, already
+/// compressed, or partial (their `Content-Range` refers to the unencoded
+/// bytes) are left alone. Compressible responses get `Vary: Accept-Encoding`
+/// whichever encoding is chosen, so caches keep one copy per encoding.
+pub struct Compression;
+
+impl Middleware for Compression {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            let encoding: Option<Encoding> = request.accepted_encoding();
+            let mut response = next.run(request, logger).await;
+            if !response.is_compressible() {
+                return response;
+            }
+
+            response.add_vary("Accept-Encoding");
+            return match encoding {
+                Some(encoding) => response.encoding(encoding),
+                // nothing acceptable: identity is the only option left
+                None => response,
+            };
+        });
+    }
+}
+
+/// Answers any GET request carrying a `Brew` header with `418 I'm a teapot`.
+pub struct Teapot;
+
+impl Middleware for Teapot {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            if request.method == HttpMethod::GET && request.headers.contains("Brew") {
+                return teapot_response();
+            }
+            return next.run(request, logger).await;
+        });
+    }
+}
+
+/// Rejects requests under the protected path prefixes unless they carry a
+/// `session` cookie naming a live session. Requests that pass reach the
+/// handler with the session in `request.session`, so `request.user()` names
+/// the authenticated user.
+pub struct RequireSession {
+    prefixes: Vec<String>,
+    sessions: Arc<SessionManager>,
+}
+
+impl RequireSession {
+    /// Creates a middleware protecting every path equal to or below one of
+    /// `prefixes`, checking cookies against `sessions`.
+    pub fn new(prefixes: &[&str], sessions: Arc<SessionManager>) -> Self {
+        return RequireSession {
+            prefixes: prefixes
+                .iter()
+                .map(|p| p.trim_end_matches('/').to_string())
+                .collect(),
+            sessions,
+        };
+    }
+
+    /// Returns `true` if `path` falls under one of the protected prefixes.
+    fn is_protected(&self, path: &str) -> bool {
+        return self.prefixes.iter().any(|prefix| {
+            path == prefix || path.starts_with(&format!("{}/", prefix)) || prefix.is_empty()
+        });
+    }
+}
+
+impl Middleware for RequireSession {
+    fn handle<'a>(
+        &'a self,
+        mut request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            if !self.is_protected(&request.path) {
+                return next.run(request, logger).await;
+            }
+
+            request.session = verify_cookie(&request.cookies(), &self.sessions).await;
+
+            if request.session.is_none() {
+                error!(
+                    "Attempt to access {} without proper authentification",
+                    request.uri
+                );
+                let error = ErrorType::BadRequest(format!(
+                    "Attempt to access {} without proper authentification.",
+                    request.uri
+                ));
+                logger.lock().await.log_error(&error);
+                println!(
+                    "{} {} {} {}",
+                    ">>".red().bold(),
+                    "Unauthenticated request for".red(),
+                    request.method.to_string().magenta(),
+                    request.uri.cyan()
+                );
+                let mut response = Response::new(
+                    Protocol::Http,
+                    HttpCode::Unauthorized,
+                    ContentType::Text,
+                    false,
+                )
+                .body(String::from("Authentification required.").into());
+                // a 401 must name a challenge; the session cookie is the only scheme
+                response.add_header(
+                    String::from("WWW-Authenticate"),
+                    String::from("Cookie realm=\"rust-server\""),
+                );
+                return response;
+            }
+
+            return next.run(request, logger).await;
+        });
+    }
+}
+
+/// Adds CORS headers to responses for cross-origin requests and answers
+/// CORS preflight requests (an `OPTIONS` request carrying
+/// `Access-Control-Request-Method`) without running the route.
+///
+/// By default any origin is allowed, without credentials, for the methods
+/// `GET, HEAD, POST, PUT, PATCH, DELETE`, and every requested header is
+/// allowed.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{Cors, HttpMethod, Router};
+/// let mut router = Router::new();
+/// router.layer(
+///     Cors::new()
+///         .allow_origin("https://example.com")
+///         .allow_methods(&[HttpMethod::GET, HttpMethod::POST])
+///         .allow_credentials(true)
+///         .max_age(3600),
+/// );
+/// ```
+pub struct Cors {
+    origins: Vec<String>,
+    methods: Vec<HttpMethod>,
+    headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u64>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        return Cors::new();
+    }
+}
+
+impl Cors {
+    pub fn new() -> Self {
+        return Cors {
+            origins: Vec::new(),
+            methods: vec![
+                HttpMethod::GET,
+                HttpMethod::HEAD,
+                HttpMethod::POST,
+                HttpMethod::PUT,
+                HttpMethod::PATCH,
+                HttpMethod::DELETE,
+            ],
+            headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        };
+    }
+
+    /// Allows requests from `origin`, e.g. `https://example.com`. Once an
+    /// origin has been added, requests from any other origin get no CORS
+    /// headers.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.origins.push(origin.trim_end_matches('/').to_string());
+        return self;
+    }
+
+    /// Sets the methods allowed in cross-origin requests.
+    pub fn allow_methods(mut self, methods: &[HttpMethod]) -> Self {
+        self.methods = methods.to_vec();
+        return self;
+    }
+
+    /// Sets the request headers allowed in cross-origin requests. Without
+    /// this every header the preflight asks for is allowed.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_string()).collect();
+        return self;
+    }
+
+    /// Lets cross-origin requests from the origins added with
+    /// [`Cors::allow_origin`] carry cookies. Without such a list credentials
+    /// are never allowed, as any site could otherwise read responses made
+    /// with the user's cookies.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        return self;
+    }
+
+    /// Sets how many seconds browsers may cache a preflight response.
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        return self;
+    }
+
+    /// The `Access-Control-Allow-Origin` value for `origin`, or `None` if
+    /// the origin is not allowed.
+    fn allowed_origin(&self, origin: &str) -> Option<String> {
+        if self.origins.is_empty() {
+            return Some(String::from("*"));
+        }
+        if self.origins.iter().any(|o| o == origin) {
+            return Some(origin.to_string());
+        }
+        return None;
+    }
+
+    /// Adds the headers shared by preflight and actual responses.
+    fn add_origin_headers(&self, response: &mut Response, allow_origin: String) {
+        if allow_origin != "*" {
+            response.add_vary("Origin");
+        }
+        // only origins on the allow-list are ever told they may send cookies
+        if self.credentials && allow_origin != "*" {
+            response.set_header("Access-Control-Allow-Credentials", String::from("true"));
+        }
+        response.set_header("Access-Control-Allow-Origin", allow_origin);
+    }
+
+    /// Builds the reply to a preflight request.
+    fn preflight(&self, request: &Request, allow_origin: String) -> Response {
+        let mut response = Response::new(
+            Protocol::Http,
+            HttpCode::NoContent,
+            ContentType::Text,
+            false,
+        );
+        self.add_origin_headers(&mut response, allow_origin);
+
+        let methods: Vec<String> = self.methods.iter().map(|m| m.to_string()).collect();
+        response.set_header("Access-Control-Allow-Methods", methods.join(", "));
+
+        let headers: Option<String> = if self.headers.is_empty() {
+            request
+                .headers
+                .get("Access-Control-Request-Headers")
+                .map(|h| h.to_string())
+        } else {
+            Some(self.headers.join(", "))
+        };
+        if let Some(headers) = headers {
+            response.set_header("Access-Control-Allow-Headers", headers);
+        }
+
+        if let Some(max_age) = self.max_age {
+            response.set_header("Access-Control-Max-Age", max_age.to_string());
+        }
+
+        return response;
+    }
+}
+
+impl Middleware for Cors {
+    fn handle<'a>(
+        &'a self,
+        request: Request,
+        logger: Arc<Mutex<Logger>>,
+        next: Next<'a>,
+    ) -> MiddlewareFuture<'a> {
+        return Box::pin(async move {
+            let origin: String = match request.headers.get("Origin") {
+                Some(o) => o.trim().to_string(),
+                None => return next.run(request, logger).await,
+            };
+            let allow_origin: Option<String> = self.allowed_origin(&origin);
+
+            if request.method == HttpMethod::OPTIONS {
+                if let Some(method) = request.headers.get("Access-Control-Request-Method") {
+                    return match (allow_origin, HttpMethod::new(method.trim())) {
+                        (Some(allow_origin), Ok(method)) if self.methods.contains(&method) => {
+                            self.preflight(&request, allow_origin)
+                        }
+                        // without CORS headers the browser refuses the request
+                        _ => Response::new(
+                            Protocol::Http,
+                            HttpCode::NoContent,
+                            ContentType::Text,
+                            false,
+                        ),
+                    };
+                }
+            }
+
+            let mut response = next.run(request, logger).await;
+            if let Some(allow_origin) = allow_origin {
+                self.add_origin_headers(&mut response, allow_origin);
+            }
+            return response;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemorySessionStore, SessionConfig, MIN_COMPRESSION_SIZE};
+
+    /// Records the order in which it sees the request and the response.
+    struct Trace(&'static str);
+
+    impl Middleware for Trace {
+        fn handle<'a>(
+            &'a self,
+            request: Request,
+            logger: Arc<Mutex<Logger>>,
+            next: Next<'a>,
+        ) -> MiddlewareFuture<'a> {
+            return Box::pin(async move {
+                let mut response = next.run(request, logger).await;
+                response.body.extend_from_slice(self.0.as_bytes());
+                return response;
+            });
+        }
+    }
+
+    fn test_request(method: &str, uri: &str, headers: &str) -> Request {
+        let raw: String = format!("{} {} HTTP/1.1\r\nHost: a\r\n{}\r\n", method, uri, headers);
+        return Request::new(raw.as_bytes()).unwrap();
+    }
+
+    fn test_router() -> Router {
+        let mut router = Router::new();
+        router.get("/", |_request, _logger| async move {
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+                .body(String::from("handler").into())
+        });
+        router.delete("/files", |request, _logger| async move {
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+                .body(request.user().unwrap_or("").as_bytes().to_vec())
+        });
+        return router;
+    }
+
+    fn test_logger() -> Arc<Mutex<Logger>> {
+        return Arc::new(Mutex::new(Logger::new("server.log")));
+    }
+
+    #[tokio::test]
+    async fn test_layers_run_in_order() {
+        let mut router = test_router();
+        router.layer(Trace(":outer")).layer(Trace(":inner"));
+
+        let response = router
+            .handle(test_request("GET", "/", ""), test_logger())
+            .await;
+        assert_eq!(response.body, b"handler:inner:outer");
+    }
+
+    #[tokio::test]
+    async fn test_teapot_short_circuits() {
+        let mut router = test_router();
+        router.layer(Teapot).layer(Trace(":inner"));
+
+        let response = router
+            .handle(test_request("GET", "/", "Brew: yes\r\n"), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::Teapot);
+        assert!(!response.body.ends_with(b":inner"));
+    }
+
+    #[tokio::test]
+    async fn test_compression_follows_accept_encoding() {
+        let mut router = test_router();
+        router
+            .layer(Compression)
+            .get("/large", |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+                    .body(vec![b'a'; MIN_COMPRESSION_SIZE])
+            })
+            .get("/image", |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Png, false)
+                    .body(vec![0; MIN_COMPRESSION_SIZE])
+            });
+        let vary = |response: &Response| -> bool {
+            return response
+                .headers
+                .iter()
+                .any(|h| h.title == "Vary" && h.value == "Accept-Encoding");
+        };
+
+        let response = router
+            .handle(
+                test_request("GET", "/large", "Accept-Encoding: gzip;q=0.5, br\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(response.encoding, Encoding::Brotli);
+        assert!(vary(&response));
+
+        let response = router
+            .handle(test_request("GET", "/large", ""), test_logger())
+            .await;
+        assert_eq!(response.encoding, Encoding::Identity);
+        assert!(vary(&response));
+
+        // too small to be worth it
+        let response = router
+            .handle(
+                test_request("GET", "/", "Accept-Encoding: gzip\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(response.encoding, Encoding::Identity);
+        assert!(!vary(&response));
+
+        // already compressed
+        let response = router
+            .handle(
+                test_request("GET", "/image", "Accept-Encoding: gzip\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(response.encoding, Encoding::Identity);
+        assert!(!vary(&response));
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let mut router = test_router();
+        router.layer(Cors::new().allow_origin("https://example.com").max_age(600));
+        let header = |response: &Response, name: &str| -> Option<String> {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == name)
+                .map(|h| h.value.clone());
+        };
+
+        let preflight = "Origin: https://example.com\r\nAccess-Control-Request-Method: DELETE\r\nAccess-Control-Request-Headers: content-type\r\n";
+        let response = router
+            .handle(test_request("OPTIONS", "/files", preflight), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::NoContent);
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://example.com")
+        );
+        assert!(header(&response, "Access-Control-Allow-Methods")
+            .unwrap()
+            .contains("DELETE"));
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Headers").as_deref(),
+            Some("content-type")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Max-Age").as_deref(),
+            Some("600")
+        );
+
+        let response = router
+            .handle(
+                test_request("GET", "/", "Origin: https://example.com\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(response.body, b"handler");
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://example.com")
+        );
+
+        let response = router
+            .handle(
+                test_request("GET", "/", "Origin: https://evil.example\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert!(header(&response, "Access-Control-Allow-Origin").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cors_credentials_need_an_origin_list() {
+        let header = |response: &Response, name: &str| -> Option<String> {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == name)
+                .map(|h| h.value.clone());
+        };
+
+        let mut router = test_router();
+        router.layer(Cors::new().allow_credentials(true));
+        let response = router
+            .handle(
+                test_request("GET", "/", "Origin: https://evil.example\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("*")
+        );
+        assert!(header(&response, "Access-Control-Allow-Credentials").is_none());
+
+        let mut router = test_router();
+        router.layer(
+            Cors::new()
+                .allow_credentials(true)
+                .allow_origin("https://example.com"),
+        );
+        let response = router
+            .handle(
+                test_request("GET", "/", "Origin: https://example.com\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Credentials").as_deref(),
+            Some("true")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_require_session_rejects_missing_cookie() {
+        let mut router = test_router();
+        router.layer(RequireSession::new(
+            &["/files"],
+            Arc::new(SessionManager::new(
+                Arc::new(MemorySessionStore::new()),
+                SessionConfig::default(),
+            )),
+        ));
+
+        let response = router
+            .handle(test_request("DELETE", "/files", ""), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+        assert!(response
+            .headers
+            .iter()
+            .any(|h| h.title == "WWW-Authenticate" && h.value == "Cookie realm=\"rust-server\""));
+
+        let response = router
+            .handle(
+                test_request("DELETE", "/files", "Cookie: theme=dark\r\n"),
+                test_logger(),
+            )
+            .await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+
+        let response = router
+            .handle(test_request("GET", "/", ""), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_require_session_passes_the_user_on() {
+        let sessions = Arc::new(SessionManager::new(
+            Arc::new(MemorySessionStore::new()),
+            SessionConfig::default(),
+        ));
+        let session = sessions.create("hayley").await.unwrap();
+        let mut router = test_router();
+        router.layer(RequireSession::new(&["/files"], Arc::clone(&sessions)));
+
+        let cookie: String = format!("Cookie: theme=dark; session={}\r\n", session.id);
+        let response = router
+            .handle(test_request("DELETE", "/files", &cookie), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.body, b"hayley");
+
+        let cookie: String = format!("Cookie: session={}\r\n", &session.id[..16]);
+        let response = router
+            .handle(test_request("DELETE", "/files", &cookie), test_logger())
+            .await;
+        assert_eq!(response.code, HttpCode::Unauthorized);
+    }
+}
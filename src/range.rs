@@ -0,0 +1,258 @@
+use chrono::{DateTime, Utc};
+
+use crate::{parse_http_date, HeaderMap};
+
+/// The most ranges honoured in a single request. Requests asking for more are
+/// served the full representation instead, so a client cannot make the server
+/// build a huge multipart response out of tiny slices.
+pub const MAX_RANGES: usize = 16;
+
+/// A satisfiable byte range of a representation, inclusive on both ends.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes covered by the range.
+    pub fn size(&self) -> u64 {
+        return self.end - self.start + 1;
+    }
+
+    /// Formats the `Content-Range` header value for this range.
+    ///
+    /// # Arguments
+    /// - `total`: The full length of the representation.
+    pub fn content_range(&self, total: u64) -> String {
+        return format!("bytes {}-{}/{}", self.start, self.end, total);
+    }
+}
+
+/// The outcome of evaluating a `Range` header.
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    /// Send the full representation with `200 OK`.
+    Full,
+    /// Send the listed ranges with `206 Partial Content`.
+    Partial(Vec<ByteRange>),
+    /// None of the ranges overlap the representation; send
+    /// `416 Range Not Satisfiable`.
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header value against a representation of `length` bytes.
+///
+/// Headers that are malformed, use a unit other than `bytes` or ask for more
+/// than [`MAX_RANGES`] ranges are ignored, as RFC 9110 allows, and yield
+/// [`RangeRequest::Full`]. Ranges reaching past the end are truncated.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{parse_range, ByteRange, RangeRequest};
+/// assert_eq!(
+///     parse_range("bytes=0-9, -5", 100),
+///     RangeRequest::Partial(vec![
+///         ByteRange { start: 0, end: 9 },
+///         ByteRange { start: 95, end: 99 },
+///     ])
+/// );
+/// assert_eq!(parse_range("bytes=200-", 100), RangeRequest::Unsatisfiable);
+/// assert_eq!(parse_range("lines=1-2", 100), RangeRequest::Full);
+/// ```
+pub fn parse_range(header: &str, length: u64) -> RangeRequest {
+    let specs: &str = match header.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return RangeRequest::Full,
+    };
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if specs.is_empty() || specs.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges: Vec<ByteRange> = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let (first, last) = match spec.split_once('-') {
+            Some(bounds) => bounds,
+            None => return RangeRequest::Full,
+        };
+
+        let range: Option<ByteRange> = if first.is_empty() {
+            // suffix range: the last N bytes
+            let suffix: u64 = match last.parse() {
+                Ok(n) => n,
+                Err(_) => return RangeRequest::Full,
+            };
+            if suffix == 0 || length == 0 {
+                None
+            } else {
+                Some(ByteRange {
+                    start: length.saturating_sub(suffix),
+                    end: length - 1,
+                })
+            }
+        } else {
+            let start: u64 = match first.parse() {
+                Ok(n) => n,
+                Err(_) => return RangeRequest::Full,
+            };
+            let end: u64 = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse() {
+                    Ok(n) => n,
+                    Err(_) => return RangeRequest::Full,
+                }
+            };
+            if end < start {
+                return RangeRequest::Full;
+            }
+            if start >= length {
+                None
+            } else {
+                Some(ByteRange {
+                    start,
+                    end: end.min(length - 1),
+                })
+            }
+        };
+
+        if let Some(range) = range {
+            ranges.push(range);
+        }
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    return RangeRequest::Partial(ranges);
+}
+
+/// Evaluates the `Range` and `If-Range` headers of a request.
+///
+/// The `Range` header is only honoured when `If-Range` is absent or still
+/// matches the current representation: either the same strong `ETag`, or the
+/// exact `Last-Modified` date.
+///
+/// # Arguments
+/// - `headers`: The request headers.
+/// - `length`: The full length of the representation.
+/// - `etag`: The current entity tag of the representation.
+/// - `last_modified`: The modification time of the representation, if known.
+pub fn range_request(
+    headers: &HeaderMap,
+    length: u64,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> RangeRequest {
+    let range: &str = match headers.get("Range") {
+        Some(r) => r,
+        None => return RangeRequest::Full,
+    };
+
+    if let Some(validator) = headers.get("If-Range") {
+        let validator: &str = validator.trim();
+        let current: bool = if validator.starts_with('"') {
+            validator == etag
+        } else if validator.starts_with("W/") {
+            // weak tags never match for If-Range
+            false
+        } else {
+            match (parse_http_date(validator), last_modified) {
+                (Some(date), Some(modified)) => date.timestamp() == modified.timestamp(),
+                _ => false,
+            }
+        };
+
+        if !current {
+            return RangeRequest::Full;
+        }
+    }
+
+    return parse_range(range, length);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            parse_range("bytes=0-0", 10),
+            RangeRequest::Partial(vec![ByteRange { start: 0, end: 0 }])
+        );
+        assert_eq!(
+            parse_range("bytes=5-", 10),
+            RangeRequest::Partial(vec![ByteRange { start: 5, end: 9 }])
+        );
+        assert_eq!(
+            parse_range("bytes=-20", 10),
+            RangeRequest::Partial(vec![ByteRange { start: 0, end: 9 }])
+        );
+        assert_eq!(
+            parse_range("bytes=8-20", 10),
+            RangeRequest::Partial(vec![ByteRange { start: 8, end: 9 }])
+        );
+
+        // unsatisfiable ranges are dropped when others remain
+        assert_eq!(
+            parse_range("bytes=50-60, 1-2", 10),
+            RangeRequest::Partial(vec![ByteRange { start: 1, end: 2 }])
+        );
+        assert_eq!(parse_range("bytes=10-", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 10), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+
+        // malformed headers are ignored
+        assert_eq!(parse_range("bytes=5-1", 10), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 10), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=", 10), RangeRequest::Full);
+        assert_eq!(parse_range("0-5", 10), RangeRequest::Full);
+        let many: String = format!("bytes={}", vec!["0-0"; MAX_RANGES + 1].join(","));
+        assert_eq!(parse_range(&many, 10), RangeRequest::Full);
+    }
+
+    #[test]
+    fn test_if_range() {
+        let modified: DateTime<Utc> = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        let partial = RangeRequest::Partial(vec![ByteRange { start: 0, end: 1 }]);
+
+        let headers = HeaderMap::from_lines(["Range: bytes=0-1"].into_iter());
+        assert_eq!(range_request(&headers, 10, "\"a\"", None), partial);
+
+        let headers = HeaderMap::from_lines(["Range: bytes=0-1", "If-Range: \"a\""].into_iter());
+        assert_eq!(range_request(&headers, 10, "\"a\"", None), partial);
+        assert_eq!(
+            range_request(&headers, 10, "\"b\"", None),
+            RangeRequest::Full
+        );
+
+        let headers = HeaderMap::from_lines(["Range: bytes=0-1", "If-Range: W/\"a\""].into_iter());
+        assert_eq!(
+            range_request(&headers, 10, "\"a\"", None),
+            RangeRequest::Full
+        );
+
+        let headers = HeaderMap::from_lines(
+            [
+                "Range: bytes=0-1",
+                "If-Range: Sun, 06 Nov 1994 08:49:37 GMT",
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            range_request(&headers, 10, "\"a\"", Some(modified)),
+            partial
+        );
+        assert_eq!(
+            range_request(&headers, 10, "\"a\"", Some(Utc::now())),
+            RangeRequest::Full
+        );
+    }
+}
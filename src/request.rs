@@ -1,22 +1,49 @@
-use crate::{read_file_to_bytes, ErrorType};
+#[cfg(all(target_os = "linux", feature = "sendfile"))]
+use crate::body::sendfile;
+use crate::body::{copy_body, copy_encoded, frame, write_bytes};
+use crate::encoding::Encoder;
+use crate::{
+    decode_content, media_type, negotiate_encoding, parse_form, parse_urlencoded,
+    read_file_to_bytes, BodyReader, BodyStream, Cookie, CookieJar, Encoding, ErrorType, Form,
+    FormConfig, Session, StreamBody, MIN_COMPRESSION_SIZE,
+};
 use chrono::{DateTime, Utc};
 use colored::Colorize;
 use core::str;
-use flate2::write::GzEncoder;
-use flate2::Compression;
 use log::error;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::error::Category;
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::Write;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Protocol {
+    /// HTTP/1.1, also used for any newer version a request may name.
     Http,
+    /// HTTP/1.0, where connections close after each response by default.
+    Http10,
+}
+
+impl Protocol {
+    pub fn new(protocol: &str) -> Protocol {
+        if protocol == "HTTP/1.0" {
+            Protocol::Http10
+        } else {
+            Protocol::Http
+        }
+    }
 }
 
 impl Display for Protocol {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Protocol::Http => write!(f, "HTTP/1.1"),
+            Protocol::Http10 => write!(f, "HTTP/1.0"),
         }
     }
 }
@@ -29,15 +56,224 @@ pub struct Header {
 
 impl Display for Header {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} : {}", self.title, self.value)
+        write!(f, "{}: {}", self.title, self.value)
     }
 }
 
-#[derive(Debug)]
+/// Ordered collection of parsed headers.
+///
+/// Names are matched case-insensitively and a name may appear several times,
+/// in which case every value is kept in the order it was received.
+#[derive(Debug, Default)]
+pub struct HeaderMap {
+    entries: Vec<Header>,
+}
+
+impl HeaderMap {
+    pub fn new() -> Self {
+        return HeaderMap {
+            entries: Vec::new(),
+        };
+    }
+
+    /// Parses `Name: value` lines into a header map.
+    ///
+    /// Parsing stops at the first empty line and lines without a `:` are skipped.
+    pub fn from_lines<'a>(lines: impl Iterator<Item = &'a str>) -> Self {
+        let mut headers = HeaderMap::new();
+
+        for line in lines {
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim(), value.trim());
+            }
+        }
+
+        return headers;
+    }
+
+    /// Appends a header, keeping any existing values with the same name.
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.entries.push(Header {
+            title: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    /// Returns the first value of the header `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        return self
+            .entries
+            .iter()
+            .find(|h| h.title.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str());
+    }
+
+    /// Returns every value of the header `name` in the order received.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        return self
+            .entries
+            .iter()
+            .filter(|h| h.title.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+            .collect();
+    }
+
+    /// Returns the comma-separated elements of every `name` header, trimmed
+    /// and with empty elements removed.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::HeaderMap;
+    /// let headers = HeaderMap::from_lines(["Accept-Encoding: gzip, br", "accept-encoding: zstd"].into_iter());
+    /// assert_eq!(headers.get_list("Accept-Encoding"), vec!["gzip", "br", "zstd"]);
+    /// ```
+    pub fn get_list(&self, name: &str) -> Vec<&str> {
+        return self
+            .get_all(name)
+            .into_iter()
+            .flat_map(|v| v.split(','))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect();
+    }
+
+    /// Returns `true` if at least one header named `name` is present.
+    pub fn contains(&self, name: &str) -> bool {
+        return self
+            .entries
+            .iter()
+            .any(|h| h.title.eq_ignore_ascii_case(name));
+    }
+
+    /// Removes every header named `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.entries.retain(|h| !h.title.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Header> {
+        return self.entries.iter();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ContentType {
     Text,
     Html,
     Json,
+    Css,
+    JavaScript,
+    Xml,
+    Csv,
+    Png,
+    Jpeg,
+    Gif,
+    Svg,
+    Webp,
+    Avif,
+    Icon,
+    Woff,
+    Woff2,
+    Ttf,
+    Otf,
+    Wasm,
+    Pdf,
+    Mp3,
+    Mp4,
+    Webm,
+    Zip,
+    OctetStream,
+    /// `message/http`, the body of a reply to `TRACE`.
+    MessageHttp,
+}
+
+impl ContentType {
+    /// Detects the content type from a file extension, ignoring case.
+    ///
+    /// # Arguments
+    /// - `extension`: The extension without the leading dot, e.g. `"css"`.
+    ///
+    /// # Returns
+    /// The matching `ContentType`, or `ContentType::OctetStream` if the
+    /// extension is not recognised.
+    pub fn from_extension(extension: &str) -> ContentType {
+        return match extension.to_ascii_lowercase().as_str() {
+            "txt" => ContentType::Text,
+            "html" | "htm" => ContentType::Html,
+            "json" | "map" => ContentType::Json,
+            "css" => ContentType::Css,
+            "js" | "mjs" => ContentType::JavaScript,
+            "xml" => ContentType::Xml,
+            "csv" => ContentType::Csv,
+            "png" => ContentType::Png,
+            "jpg" | "jpeg" => ContentType::Jpeg,
+            "gif" => ContentType::Gif,
+            "svg" => ContentType::Svg,
+            "webp" => ContentType::Webp,
+            "avif" => ContentType::Avif,
+            "ico" => ContentType::Icon,
+            "woff" => ContentType::Woff,
+            "woff2" => ContentType::Woff2,
+            "ttf" => ContentType::Ttf,
+            "otf" => ContentType::Otf,
+            "wasm" => ContentType::Wasm,
+            "pdf" => ContentType::Pdf,
+            "mp3" => ContentType::Mp3,
+            "mp4" => ContentType::Mp4,
+            "webm" => ContentType::Webm,
+            "zip" => ContentType::Zip,
+            _ => ContentType::OctetStream,
+        };
+    }
+
+    /// Returns `false` for formats that are already compressed, such as
+    /// images, audio, video, archives and woff fonts, which gain nothing from
+    /// another content coding.
+    pub fn is_compressible(&self) -> bool {
+        return match self {
+            ContentType::Png
+            | ContentType::Jpeg
+            | ContentType::Gif
+            | ContentType::Webp
+            | ContentType::Avif
+            | ContentType::Woff
+            | ContentType::Woff2
+            | ContentType::Pdf
+            | ContentType::Mp3
+            | ContentType::Mp4
+            | ContentType::Webm
+            | ContentType::Zip
+            | ContentType::OctetStream => false,
+            _ => true,
+        };
+    }
+
+    /// Detects the content type of a file from its extension.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::ContentType;
+    /// use std::path::Path;
+    /// assert_eq!(ContentType::from_path(Path::new("css/site.CSS")), ContentType::Css);
+    /// assert_eq!(ContentType::from_path(Path::new("README")), ContentType::OctetStream);
+    /// ```
+    pub fn from_path(path: &Path) -> ContentType {
+        return match path.extension().and_then(|e| e.to_str()) {
+            Some(extension) => ContentType::from_extension(extension),
+            None => ContentType::OctetStream,
+        };
+    }
 }
 
 impl Display for ContentType {
@@ -46,6 +282,29 @@ impl Display for ContentType {
             ContentType::Text => write!(f, "text/plain"),
             ContentType::Html => write!(f, "text/html"),
             ContentType::Json => write!(f, "application/json"),
+            ContentType::Css => write!(f, "text/css"),
+            ContentType::JavaScript => write!(f, "text/javascript"),
+            ContentType::Xml => write!(f, "application/xml"),
+            ContentType::Csv => write!(f, "text/csv"),
+            ContentType::Png => write!(f, "image/png"),
+            ContentType::Jpeg => write!(f, "image/jpeg"),
+            ContentType::Gif => write!(f, "image/gif"),
+            ContentType::Svg => write!(f, "image/svg+xml"),
+            ContentType::Webp => write!(f, "image/webp"),
+            ContentType::Avif => write!(f, "image/avif"),
+            ContentType::Icon => write!(f, "image/x-icon"),
+            ContentType::Woff => write!(f, "font/woff"),
+            ContentType::Woff2 => write!(f, "font/woff2"),
+            ContentType::Ttf => write!(f, "font/ttf"),
+            ContentType::Otf => write!(f, "font/otf"),
+            ContentType::Wasm => write!(f, "application/wasm"),
+            ContentType::Pdf => write!(f, "application/pdf"),
+            ContentType::Mp3 => write!(f, "audio/mpeg"),
+            ContentType::Mp4 => write!(f, "video/mp4"),
+            ContentType::Webm => write!(f, "video/webm"),
+            ContentType::Zip => write!(f, "application/zip"),
+            ContentType::OctetStream => write!(f, "application/octet-stream"),
+            ContentType::MessageHttp => write!(f, "message/http"),
         }
     }
 }
@@ -56,8 +315,18 @@ pub struct Response {
     pub code: HttpCode,
     pub content_type: ContentType,
     pub body: Vec<u8>,
-    pub compression: bool,
+    /// The content coding applied to the body when it is written.
+    pub encoding: Encoding,
     pub headers: Vec<Header>,
+    /// A body produced while the response is written, replacing `body`.
+    pub stream: Option<StreamBody>,
+    /// When set only the status line and headers are written, describing
+    /// the body without sending it, as in a reply to `HEAD`.
+    pub omit_body: bool,
+    /// Whether a body of unknown length may use `Transfer-Encoding: chunked`.
+    /// HTTP/1.0 clients do not understand it, so for them this is cleared and
+    /// such a body is written as is, ending when the connection is closed.
+    pub allow_chunked: bool,
 }
 
 #[allow(async_fn_in_trait)]
@@ -67,9 +336,12 @@ pub trait MyDefault {
 
 impl MyDefault for Response {
     async fn default() -> Self {
-        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Html, true);
+        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Html, false);
 
-        response.add_body(read_file_to_bytes("static/index.html").await);
+        match read_file_to_bytes("static/index.html").await {
+            Ok(body) => response.add_body(body),
+            Err(e) => error!("Failed to load default page: {}", e),
+        }
 
         return response;
     }
@@ -80,24 +352,52 @@ impl Response {
         self.headers.push(Header { title, value });
     }
 
-    pub fn to_bytes(&mut self) -> Vec<u8> {
-        // Response line: HTTP/1.1 <status code>
-        let response_line: String = format!("{} {}\r\n", self.protocol, self.code);
+    /// Adds a `Set-Cookie` header for `cookie`.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the header is added.
+    /// - `Err(ErrorType::InternalServerError)` if the cookie cannot be written
+    ///   safely, see [`Cookie::to_header`].
+    pub fn add_cookie(&mut self, cookie: &Cookie) -> Result<(), ErrorType> {
+        self.add_header(String::from("Set-Cookie"), cookie.to_header()?);
+        return Ok(());
+    }
 
-        let body: Vec<u8>;
+    /// Sets a header, replacing any existing headers with the same name.
+    /// Header names are compared case-insensitively.
+    pub fn set_header(&mut self, title: &str, value: String) {
+        self.headers
+            .retain(|h| !h.title.eq_ignore_ascii_case(title));
+        self.add_header(title.to_string(), value);
+    }
 
-        if !self.compression {
-            body = self.body.clone();
-        } else {
-            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-            encoder
-                .write_all(&self.body)
-                .expect("Failed to write body to gzip encoder");
-            body = encoder.finish().expect("Failed to finish gzip compression");
-            //self.add_header(String::from("Content-Encoding"), String::from("gzip"));
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        // a 304 has no body, and its headers describe the cached representation
+        if !self.code.allows_body() {
+            return self.head_to_bytes();
         }
 
+        let body: Vec<u8> = match self.encoding {
+            Encoding::Identity => self.body.clone(),
+            encoding => encoding.encode(&self.body),
+        };
+
         self.add_header(String::from("Content-Length"), body.len().to_string());
+        if self.omit_body {
+            return self.head_to_bytes();
+        }
+
+        let mut response = self.head_to_bytes();
+        response.extend_from_slice(&body);
+
+        return response;
+    }
+
+    /// Serialises the response line and headers, including the blank line
+    /// that separates them from the body.
+    fn head_to_bytes(&self) -> Vec<u8> {
+        // Response line: HTTP/1.1 <status code>
+        let response_line: String = format!("{} {}\r\n", self.protocol, self.code);
 
         let mut headers: Vec<String> = Vec::new();
 
@@ -111,15 +411,165 @@ impl Response {
         response.extend_from_slice(response_line.as_bytes());
         response.extend_from_slice(headers.join("\r\n").as_bytes());
         response.extend_from_slice(b"\r\n\r\n");
-        response.extend_from_slice(&body);
 
         return response;
     }
 
+    /// Writes the response to the client.
+    ///
+    /// Responses with a channel body are sent with `Transfer-Encoding: chunked`,
+    /// writing each chunk as soon as it is produced rather than buffering the
+    /// whole body. Reader and file bodies are copied in chunks of at most
+    /// [`STREAM_CHUNK_SIZE`] bytes with a `Content-Length` header, or chunked
+    /// when they are compressed. All other responses are written in one piece
+    /// with a `Content-Length` header.
+    ///
+    /// Without [`Response::allow_chunked`] bodies that would be chunked are
+    /// written without any framing, and the connection must be closed after
+    /// them, see [`Response::has_known_length`].
+    ///
+    /// # Returns
+    /// - `Ok(())` once the full response has been written.
+    /// - `Err(ErrorType::SocketError)` if writing to the client fails.
+    /// - `Err(ErrorType::ReadError)` if a streamed body cannot be read.
+    pub async fn write_to<W>(&mut self, writer: &mut W) -> Result<(), ErrorType>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let body: StreamBody = match self.stream.take() {
+            Some(s) => s,
+            None => return write_bytes(writer, &self.to_bytes()).await,
+        };
+
+        if self.omit_body {
+            match body.length() {
+                Some(length) if self.encoding == Encoding::Identity => {
+                    self.set_header("Content-Length", length.to_string())
+                }
+                _ if self.allow_chunked => {
+                    self.set_header("Transfer-Encoding", String::from("chunked"))
+                }
+                _ => (),
+            }
+            return write_bytes(writer, &self.head_to_bytes()).await;
+        }
+
+        let (mut reader, length): (BodyReader, u64) = match body {
+            StreamBody::Channel(stream) => return self.write_channel(writer, stream).await,
+            body => match body.into_reader().await? {
+                Some(r) => r,
+                None => unreachable!("only channel bodies have no reader"),
+            },
+        };
+
+        if self.encoding != Encoding::Identity {
+            if self.allow_chunked {
+                self.set_header("Transfer-Encoding", String::from("chunked"));
+            }
+            write_bytes(writer, &self.head_to_bytes()).await?;
+            return copy_encoded(writer, &mut reader, self.encoding, self.allow_chunked).await;
+        }
+
+        self.set_header("Content-Length", length.to_string());
+        write_bytes(writer, &self.head_to_bytes()).await?;
+        return copy_body(writer, &mut reader, length).await;
+    }
+
+    /// Whether the length of the body is known before it is written, so it
+    /// can be sent with a `Content-Length` header. Channel bodies and
+    /// compressed streamed bodies are only known once they have been written.
+    pub fn has_known_length(&self) -> bool {
+        return match &self.stream {
+            None => true,
+            Some(_) if self.omit_body => true,
+            Some(StreamBody::Channel(_)) => false,
+            Some(_) => self.encoding == Encoding::Identity,
+        };
+    }
+
+    /// Writes the response to a client socket.
+    ///
+    /// Behaves like [`Response::write_to`], except that on Linux uncompressed
+    /// file bodies are handed to the kernel with `sendfile` instead of being
+    /// copied through user space.
+    pub async fn send(&mut self, socket: &mut TcpStream) -> Result<(), ErrorType> {
+        #[cfg(all(target_os = "linux", feature = "sendfile"))]
+        if self.encoding == Encoding::Identity && !self.omit_body {
+            if let Some(StreamBody::File {
+                file,
+                offset,
+                length,
+            }) = self
+                .stream
+                .take_if(|s| matches!(s, StreamBody::File { .. }))
+            {
+                self.set_header("Content-Length", length.to_string());
+                write_bytes(socket, &self.head_to_bytes()).await?;
+                return sendfile(socket, file, offset, length).await;
+            }
+        }
+
+        return self.write_to(socket).await;
+    }
+
+    /// Writes a channel body with `Transfer-Encoding: chunked`, or unframed
+    /// without [`Response::allow_chunked`].
+    async fn write_channel<W>(
+        &mut self,
+        writer: &mut W,
+        mut stream: BodyStream,
+    ) -> Result<(), ErrorType>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if self.allow_chunked {
+            self.set_header("Transfer-Encoding", String::from("chunked"));
+        }
+        write_bytes(writer, &self.head_to_bytes()).await?;
+
+        let mut encoder: Option<Encoder> = match self.encoding {
+            Encoding::Identity => None,
+            encoding => Some(Encoder::new(encoding)),
+        };
+
+        while let Some(data) = stream.recv().await {
+            let data: Vec<u8> = match encoder.as_mut() {
+                Some(e) => {
+                    // flush so every chunk reaches the client as soon as it is produced
+                    let mut encoded: Vec<u8> = e.write(&data);
+                    encoded.extend_from_slice(&e.flush());
+                    encoded
+                }
+                None => data,
+            };
+
+            // an empty chunk would mark the end of the body
+            if data.is_empty() {
+                continue;
+            }
+
+            write_bytes(writer, &frame(&data, self.allow_chunked)).await?;
+        }
+
+        if let Some(e) = encoder {
+            let data: Vec<u8> = e.finish();
+            if !data.is_empty() {
+                write_bytes(writer, &frame(&data, self.allow_chunked)).await?;
+            }
+        }
+
+        if !self.allow_chunked {
+            return Ok(());
+        }
+        return write_bytes(writer, b"0\r\n\r\n").await;
+    }
+
     pub fn add_body(&mut self, body: Vec<u8>) {
         self.body = body;
     }
 
+    /// Creates a response with the `Server`, `Date` and `Content-Type`
+    /// headers set. `compression` gzip-encodes the body.
     pub fn new(
         protocol: Protocol,
         code: HttpCode,
@@ -128,10 +578,6 @@ impl Response {
     ) -> Self {
         let body = Vec::with_capacity(0);
 
-        // Date Header
-        let now: DateTime<Utc> = Utc::now();
-        let date = now.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
-
         let mut headers: Vec<Header> = vec![
             Header {
                 title: String::from("Server"),
@@ -139,11 +585,7 @@ impl Response {
             },
             Header {
                 title: String::from("Date"),
-                value: date,
-            },
-            Header {
-                title: String::from("Cache-Control"),
-                value: String::from("no-store"),
+                value: http_date(Utc::now()),
             },
             Header {
                 title: String::from("Content-Type"),
@@ -163,9 +605,82 @@ impl Response {
             code,
             content_type,
             body,
-            compression,
+            encoding: if compression {
+                Encoding::Gzip
+            } else {
+                Encoding::Identity
+            },
             headers,
+            stream: None,
+            omit_body: false,
+            allow_chunked: true,
+        };
+    }
+
+    /// Creates a `200 OK` response with `value` serialised as its JSON body.
+    ///
+    /// Values that cannot be serialised, such as maps with non-string keys,
+    /// give a `500 Internal Server Error` instead.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::{ContentType, HttpCode, Response};
+    /// let response = Response::json(&vec!["a", "b"]).code(HttpCode::Created);
+    /// assert_eq!(response.body, br#"["a","b"]"#);
+    /// assert_eq!(response.content_type, ContentType::Json);
+    /// ```
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        let response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Json, false);
+
+        return match serde_json::to_vec(value) {
+            Ok(body) => response.body(body),
+            Err(e) => {
+                error!("Failed to serialise JSON response: {}", e);
+                Response::json_error(
+                    HttpCode::InternalServerError,
+                    "Failed to serialise response",
+                )
+            }
+        };
+    }
+
+    /// Creates a response with the given code and an `{"error": message}`
+    /// JSON body.
+    pub fn json_error(code: HttpCode, message: &str) -> Self {
+        return Response::json(&serde_json::json!({ "error": message })).code(code);
+    }
+
+    /// Creates the JSON error response for an error returned while handling
+    /// a request, such as a body rejected by [`Request::json`].
+    ///
+    /// Client errors get their own status code (`400`, `403`, `404`, `409`,
+    /// `413`, `415`, `422`, ...) and describe the problem; any other error is sent as
+    /// a `500 Internal Server Error` without its details.
+    pub fn from_error(error: &ErrorType) -> Self {
+        let code: HttpCode = match error {
+            ErrorType::BadRequest(_) => HttpCode::BadRequest,
+            ErrorType::Forbidden(_) => HttpCode::Forbidden,
+            ErrorType::NotFound(_) => HttpCode::NotFound,
+            ErrorType::Conflict(_) => HttpCode::Conflict,
+            ErrorType::Timeout(_) => HttpCode::RequestTimeout,
+            ErrorType::PayloadTooLarge(_) => HttpCode::PayloadTooLarge,
+            ErrorType::UnsupportedMediaType(_) => HttpCode::UnsupportedMediaType,
+            ErrorType::UnprocessableEntity(_) => HttpCode::UnprocessableEntity,
+            ErrorType::HeadersTooLarge(_) => HttpCode::RequestHeaderFieldsTooLarge,
+            _ => {
+                return Response::json_error(HttpCode::InternalServerError, "Internal server error")
+            }
         };
+
+        return Response::json_error(code, error.get_msg());
+    }
+
+    /// Omits the body when the response is written while keeping the
+    /// headers that describe it, such as `Content-Length`. Used to answer
+    /// `HEAD` requests with the response a `GET` would get.
+    pub fn without_body(mut self) -> Self {
+        self.omit_body = true;
+        return self;
     }
 
     pub fn code(mut self, code: HttpCode) -> Self {
@@ -175,6 +690,7 @@ impl Response {
 
     pub fn content_type(mut self, content_type: ContentType) -> Self {
         self.content_type = content_type;
+        self.set_header("Content-Type", content_type.to_string());
         return self;
     }
 
@@ -183,37 +699,171 @@ impl Response {
         return self;
     }
 
-    pub fn compression(mut self, compression: bool) -> Self {
-        self.compression = compression;
-        // add header
-        if compression {
-            for header in &self.headers {
-                if header.title == "Content-Encoding" {
-                    return self;
-                }
-            }
-            self.add_header(String::from("Content-Encoding"), String::from("gzip"));
-        } else {
-            let mut index: isize = -1;
-            for (i, _) in self.headers.iter().enumerate() {
-                if &self.headers[i].title == "Content-Encoding" {
-                    index = i as isize;
-                }
-            }
+    /// Sets the `Cache-Control` header, e.g. `"public, max-age=3600"`.
+    pub fn cache_control(mut self, value: &str) -> Self {
+        self.set_header("Cache-Control", value.to_string());
+        return self;
+    }
 
-            if index > 0 {
-                self.headers.remove(index as usize);
-            }
+    /// Sends the body incrementally with `Transfer-Encoding: chunked`, taking
+    /// each chunk from `stream` instead of from `body`.
+    pub fn chunked(mut self, stream: BodyStream) -> Self {
+        self.stream = Some(StreamBody::Channel(stream));
+        return self;
+    }
+
+    /// Streams exactly `length` bytes from `reader` as the body instead of
+    /// `body`.
+    pub fn reader<R>(mut self, reader: R, length: u64) -> Self
+    where
+        R: AsyncRead + Send + Unpin + 'static,
+    {
+        self.stream = Some(StreamBody::Reader {
+            reader: Box::new(reader),
+            length,
+        });
+        return self;
+    }
+
+    /// Streams `length` bytes of `file`, starting at `offset`, as the body
+    /// instead of `body`.
+    pub fn file(mut self, file: File, offset: u64, length: u64) -> Self {
+        self.stream = Some(StreamBody::File {
+            file,
+            offset,
+            length,
+        });
+        return self;
+    }
+
+    /// Gzip-encodes the body if `compression` is set, and sends it
+    /// unencoded otherwise.
+    pub fn compression(self, compression: bool) -> Self {
+        return match compression {
+            true => self.encoding(Encoding::Gzip),
+            false => self.encoding(Encoding::Identity),
+        };
+    }
+
+    /// Sets the content coding applied to the body when it is written,
+    /// updating the `Content-Encoding` header.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        match encoding {
+            Encoding::Identity => self
+                .headers
+                .retain(|h| !h.title.eq_ignore_ascii_case("Content-Encoding")),
+            encoding => self.set_header("Content-Encoding", encoding.to_string()),
         }
         return self;
     }
+
+    /// Marks the body as already encoded with `encoding`, e.g. when it was
+    /// read from a precompressed file. The `Content-Encoding` header is set
+    /// but the body is written as is.
+    pub fn encoded(mut self, encoding: Encoding) -> Self {
+        self.encoding = Encoding::Identity;
+        self.set_header("Content-Encoding", encoding.to_string());
+        return self;
+    }
+
+    /// Returns `true` if the body is worth compressing: the status carries a
+    /// full body, the content type is not already compressed, the body is at
+    /// least [`MIN_COMPRESSION_SIZE`] bytes (or of unknown length) and no
+    /// encoding has been applied yet.
+    pub fn is_compressible(&self) -> bool {
+        if self.code == HttpCode::PartialContent || self.code == HttpCode::NotModified {
+            return false;
+        }
+
+        if self.encoding != Encoding::Identity || !self.content_type.is_compressible() {
+            return false;
+        }
+
+        if self
+            .headers
+            .iter()
+            .any(|h| h.title.eq_ignore_ascii_case("Content-Encoding"))
+        {
+            return false;
+        }
+
+        let length: Option<u64> = match &self.stream {
+            Some(stream) => stream.length(),
+            None => Some(self.body.len() as u64),
+        };
+        return match length {
+            Some(length) => length >= MIN_COMPRESSION_SIZE as u64,
+            None => true,
+        };
+    }
+
+    /// Adds `field` to the `Vary` header unless it is already listed.
+    pub fn add_vary(&mut self, field: &str) {
+        let existing: Option<&mut Header> = self
+            .headers
+            .iter_mut()
+            .find(|h| h.title.eq_ignore_ascii_case("Vary"));
+
+        match existing {
+            Some(header) => {
+                if !header
+                    .value
+                    .split(',')
+                    .any(|v| v.trim().eq_ignore_ascii_case(field))
+                {
+                    header.value = format!("{}, {}", header.value, field);
+                }
+            }
+            None => self.add_header(String::from("Vary"), field.to_string()),
+        }
+    }
+}
+
+/// Formats a timestamp as an HTTP date, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn http_date(time: DateTime<Utc>) -> String {
+    return time.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+}
+
+/// Parses an HTTP date as sent in `If-Modified-Since` or `Last-Modified`.
+///
+/// # Returns
+/// - `Some(DateTime<Utc>)` if `value` is a valid IMF-fixdate.
+/// - `None` otherwise.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{http_date, parse_http_date};
+/// let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+/// assert_eq!(http_date(date), "Sun, 06 Nov 1994 08:49:37 GMT");
+/// assert!(parse_http_date("yesterday").is_none());
+/// ```
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    return match DateTime::parse_from_rfc2822(value.trim()) {
+        Ok(date) => Some(date.with_timezone(&Utc)),
+        Err(_) => None,
+    };
 }
 
 pub struct Request {
-    pub headers: Vec<String>,
-    pub body: String,
+    pub headers: HeaderMap,
+    /// The request body exactly as received, after any chunked transfer
+    /// coding has been removed.
+    pub body: Vec<u8>,
     pub method: HttpMethod,
+    /// The request target exactly as it appeared in the request line.
     pub uri: String,
+    /// The percent-decoded path component of `uri`.
+    pub path: String,
+    /// Percent-decoded query string parameters. When a key is repeated the
+    /// first value is kept.
+    pub query: HashMap<String, String>,
+    /// Path parameters captured by the matched route pattern.
+    pub params: HashMap<String, String>,
+    pub protocol: Protocol,
+    /// The session authenticated by [`RequireSession`](crate::RequireSession)
+    /// for protected paths, `None` everywhere else.
+    pub session: Option<Session>,
 }
 
 impl Request {
@@ -223,218 +873,1612 @@ impl Request {
     }
 
     pub fn new(buffer: &[u8]) -> Result<Request, ErrorType> {
-        // unwrap is safe as request has been parsed for any issues before this is called
-        let request = String::from_utf8(buffer.to_vec()).unwrap();
+        // the body is kept as bytes; only the head has to be text
+        let (head, body): (&[u8], &[u8]) = match find_subsequence(buffer, b"\r\n\r\n") {
+            Some(end) => buffer.split_at(end + 4),
+            None => (buffer, &[]),
+        };
+        let request: String = decode_latin1(head);
 
         println!("{}\r\n", request);
 
         // split the request by line
         let request: Vec<&str> = request.lines().collect();
 
-        if request.len() < 3 {
+        // a head may have no header fields at all, only the request line
+        if request.is_empty() {
             error!("Recieved invalid request");
-            return Err(ErrorType::ConnectionError(String::from("Invalid request")));
+            return Err(ErrorType::BadRequest(String::from("Invalid request")));
         }
 
-        // get the http method from the first line
-        let method: HttpMethod =
-            HttpMethod::new(request[0].split_whitespace().collect::<Vec<&str>>()[0]);
+        // the request line is the method, the uri and the protocol version
+        let request_line: Vec<&str> = request[0].split_whitespace().collect();
 
-        // get the uri from the first line
-        let uri: String = request[0].split_whitespace().collect::<Vec<&str>>()[1].to_string();
-
-        // headers are the rest of the
-        let mut headers: Vec<String> = Vec::with_capacity(request.len() - 1);
-        let mut body: String = String::new();
-        let mut flag = false;
-        for line in &request[1..] {
-            if line.is_empty() {
-                flag = true;
-                continue;
-            }
-            if flag {
-                body.push_str(line);
-            } else {
-                headers.push(line.to_string());
-            }
+        if request_line.len() != 3 {
+            error!("Received invalid request line");
+            return Err(ErrorType::BadRequest(String::from("Invalid request line")));
         }
 
+        let method: HttpMethod = HttpMethod::new(request_line[0])?;
+
+        let uri: String = request_line[1].to_string();
+
+        let (path, query) = parse_uri(&uri)?;
+
+        let protocol: Protocol = Protocol::new(request_line[2]);
+
+        // headers are the rest of the lines up to the blank line
+        let headers: HeaderMap = HeaderMap::from_lines(request[1..].iter().copied());
+
         return Ok(Request {
             headers,
-            body,
+            body: body.to_vec(),
             method,
             uri,
+            path,
+            query,
+            params: HashMap::new(),
+            protocol,
+            session: None,
         });
     }
 
-    pub fn is_compression_supported(&self) -> bool {
-        for header in &self.headers {
-            let header = header.to_lowercase();
+    /// Returns the value of the query string parameter `name`.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        return self.query.get(name).map(|v| v.as_str());
+    }
 
-            if header.contains("firefox") {
-                return false;
-            }
+    /// Returns the name of the user authenticated by
+    /// [`RequireSession`](crate::RequireSession), if any.
+    pub fn user(&self) -> Option<&str> {
+        return self.session.as_ref().map(|s| s.username.as_str());
+    }
 
-            if header.contains("accept-encoding") {
-                if header.contains(',') {
-                    // multiple compression types
-                    let mut encodings: Vec<&str> =
-                        header.split(", ").map(|m| m.trim()).collect::<Vec<&str>>();
-                    encodings[0] = &encodings[0].split_whitespace().collect::<Vec<&str>>()[1];
-
-                    for encoding in encodings {
-                        if encoding == "gzip" || encoding.contains("gzip") {
-                            return true;
-                        }
-                    }
-                } else {
-                    if header
-                        .to_lowercase()
-                        .split_whitespace()
-                        .collect::<Vec<&str>>()[1]
-                        == "gzip"
-                    {
-                        return true;
-                    }
-                }
-            }
-        }
-        return false;
+    /// Returns the value of the path parameter `name` captured by the router.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        return self.params.get(name).map(|v| v.as_str());
     }
-}
 
-#[derive(Debug)]
-pub enum HttpCode {
-    Ok,
-    Created,
-    BadRequest,
-    Unauthorized,
-    NotFound,
-    MethodNotAllowed,
-    RequestTimeout,
-    Teapot,
-    InternalServerError,
-}
+    /// Determines whether the client wants the connection kept open after
+    /// this request.
+    ///
+    /// An explicit `Connection: close` or `Connection: keep-alive` token wins,
+    /// otherwise HTTP/1.1 connections persist and HTTP/1.0 connections close.
+    pub fn keep_alive(&self) -> bool {
+        let tokens: Vec<&str> = self.headers.get_list("Connection");
 
-impl Display for HttpCode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            HttpCode::Ok => write!(f, "200 OK"),
-            HttpCode::Created => write!(f, "201 Created"),
-            HttpCode::BadRequest => write!(f, "400 Bad Request"),
-            HttpCode::Unauthorized => write!(f, "401 Unauthorized"),
-            HttpCode::NotFound => write!(f, "404 Not Found"),
-            HttpCode::MethodNotAllowed => write!(f, "405 Method Not Allowed"),
-            HttpCode::RequestTimeout => write!(f, "408 Request Timeout"),
-            HttpCode::Teapot => write!(f, "418 I'm a teapot"),
-            HttpCode::InternalServerError => write!(f, "500 Internal Server Error"),
+        if tokens.iter().any(|t| t.eq_ignore_ascii_case("close")) {
+            return false;
         }
-    }
-}
 
-impl PartialEq for HttpCode {
-    fn eq(&self, other: &Self) -> bool {
-        match self {
-            HttpCode::Ok => match other {
-                HttpCode::Ok => true,
-                _ => false,
-            },
-            HttpCode::Created => match other {
-                HttpCode::Created => true,
-                _ => false,
-            },
-            HttpCode::BadRequest => match other {
-                HttpCode::BadRequest => true,
-                _ => false,
-            },
-            HttpCode::Unauthorized => match other {
-                HttpCode::Unauthorized => true,
-                _ => false,
-            },
-            HttpCode::NotFound => match other {
-                HttpCode::NotFound => true,
-                _ => false,
-            },
-            HttpCode::MethodNotAllowed => match other {
-                HttpCode::MethodNotAllowed => true,
-                _ => false,
-            },
-            HttpCode::RequestTimeout => match other {
-                HttpCode::RequestTimeout => true,
-                _ => false,
-            },
-            HttpCode::Teapot => match other {
-                HttpCode::Teapot => true,
-                _ => false,
-            },
-            HttpCode::InternalServerError => match other {
-                HttpCode::InternalServerError => true,
-                _ => false,
-            },
+        if tokens.iter().any(|t| t.eq_ignore_ascii_case("keep-alive")) {
+            return true;
         }
+
+        return self.protocol == Protocol::Http;
     }
-}
 
-#[derive(Debug)]
-pub enum HttpMethod {
-    GET,
-    POST,
-    PUT,
-    PATCH,
-    DELETE,
-}
+    /// Returns the lowercased media type of the body, without parameters,
+    /// e.g. `application/json`.
+    pub fn media_type(&self) -> Option<String> {
+        return self.headers.get("Content-Type").map(media_type);
+    }
 
-impl HttpMethod {
-    pub fn new(method: &str) -> HttpMethod {
-        if method.to_uppercase().contains("GET") {
-            HttpMethod::GET
-        } else if method.to_uppercase().contains("POST") {
-            HttpMethod::POST
-        } else if method.to_uppercase().contains("PUT") {
-            HttpMethod::PUT
-        } else if method.to_uppercase().contains("PATCH") {
-            HttpMethod::PATCH
-        } else {
-            HttpMethod::DELETE
-        }
+    /// Parses a `application/x-www-form-urlencoded` or `multipart/form-data`
+    /// body, see [`parse_form`]. Files in a multipart body are written to
+    /// `config.upload_dir`.
+    pub async fn form(&self, config: &FormConfig) -> Result<Form, ErrorType> {
+        return parse_form(&self.headers, &self.body, config).await;
     }
-}
 
-impl Display for HttpMethod {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            HttpMethod::GET => write!(f, "GET"),
-            HttpMethod::POST => write!(f, "POST"),
-            HttpMethod::PUT => write!(f, "PUT"),
-            HttpMethod::PATCH => write!(f, "PATCH"),
-            HttpMethod::DELETE => write!(f, "DELETE"),
+    /// Removes the content coding of a `gzip` or `deflate` compressed body,
+    /// see [`decode_content`].
+    ///
+    /// The `Content-Encoding` header is dropped and `Content-Length` updated,
+    /// so handlers see the request as if it had been sent uncompressed.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the body is decoded, or if it was not compressed.
+    /// - `Err(ErrorType::UnsupportedMediaType)` for any other coding.
+    /// - `Err(ErrorType::PayloadTooLarge)` if the body decodes to more than `max_size` bytes.
+    /// - `Err(ErrorType::BadRequest)` if the body is not validly encoded.
+    pub fn decode_body(&mut self, max_size: usize) -> Result<(), ErrorType> {
+        if !self.headers.contains("Content-Encoding") {
+            return Ok(());
         }
+
+        self.body = decode_content(&self.headers, &self.body, max_size)?;
+        self.headers.remove("Content-Encoding");
+        self.headers.remove("Content-Length");
+        self.headers
+            .insert("Content-Length", &self.body.len().to_string());
+
+        return Ok(());
     }
-}
 
-impl PartialEq for HttpMethod {
+    /// Deserialises the JSON body into `T`.
+    ///
+    /// # Returns
+    /// - `Ok(T)` with the parsed body.
+    /// - `Err(ErrorType::BadRequest)` if the body is not valid JSON.
+    /// - `Err(ErrorType::UnprocessableEntity)` if the JSON does not match `T`,
+    ///   e.g. a field is missing or has the wrong type.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::{ErrorType, Request};
+    /// let request = Request::new(b"POST / HTTP/1.1\r\nHost: a\r\n\r\n[1, 2]").unwrap();
+    /// assert_eq!(request.json::<Vec<u32>>().unwrap(), vec![1, 2]);
+    /// assert_eq!(
+    ///     request.json::<Vec<String>>(),
+    ///     Err(ErrorType::UnprocessableEntity(String::new()))
+    /// );
+    /// ```
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ErrorType> {
+        return match serde_json::from_slice(&self.body) {
+            Ok(value) => Ok(value),
+            Err(e) if e.classify() == Category::Data => Err(ErrorType::UnprocessableEntity(
+                format!("Invalid JSON body: {}", e),
+            )),
+            Err(e) => Err(ErrorType::BadRequest(format!("Invalid JSON body: {}", e))),
+        };
+    }
+
+    /// Returns the body as UTF-8 text.
+    ///
+    /// # Returns
+    /// - `Ok(&str)` borrowing the body.
+    /// - `Err(ErrorType::BadRequest)` if the body is not valid UTF-8.
+    pub fn text(&self) -> Result<&str, ErrorType> {
+        return match str::from_utf8(&self.body) {
+            Ok(t) => Ok(t),
+            Err(_) => Err(ErrorType::BadRequest(String::from(
+                "Request body is not valid UTF-8",
+            ))),
+        };
+    }
+
+    /// Parses the cookies of every `Cookie` header, see [`CookieJar`].
+    pub fn cookies(&self) -> CookieJar {
+        return CookieJar::from_headers(&self.headers);
+    }
+
+    /// Chooses the response encoding from the `Accept-Encoding` headers, see
+    /// [`negotiate_encoding`].
+    pub fn accepted_encoding(&self) -> Option<Encoding> {
+        return negotiate_encoding(&self.headers);
+    }
+}
+
+/// Splits a request target into its percent-decoded path and query parameters.
+///
+/// The path is normalised so every consumer sees the same one: empty
+/// segments are dropped, so `//files/x` becomes `/files/x`, while a trailing
+/// `/` is kept.
+///
+/// # Returns
+/// - `Ok((path, query))` if every escape sequence in the target is valid.
+/// - `Err(ErrorType::BadRequest)` if an escape is malformed, does not decode
+///   to UTF-8, or decodes to a `/` inside a path segment.
+pub fn parse_uri(uri: &str) -> Result<(String, HashMap<String, String>), ErrorType> {
+    let (path, query_string) = match uri.split_once('?') {
+        Some((p, q)) => (p, q),
+        None => (uri, ""),
+    };
+
+    let path: String = normalize_path(path)?;
+    let mut query: HashMap<String, String> = HashMap::new();
+
+    for (key, value) in parse_urlencoded(query_string)? {
+        query.entry(key).or_insert(value);
+    }
+
+    return Ok((path, query));
+}
+
+/// Percent-decodes a request path segment by segment and joins the
+/// non-empty segments with single slashes. `*`, the target of `OPTIONS *`,
+/// is returned as is.
+///
+/// # Returns
+/// - `Ok(String)` with the normalised path.
+/// - `Err(ErrorType::BadRequest)` if a segment is not valid percent-encoding
+///   or holds an encoded `/`, which would otherwise change where the path
+///   splits after decoding.
+fn normalize_path(path: &str) -> Result<String, ErrorType> {
+    if path == "*" {
+        return Ok(path.to_string());
+    }
+
+    let mut segments: Vec<String> = Vec::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let decoded: String = percent_decode(segment)?;
+        if decoded.contains('/') {
+            return Err(ErrorType::BadRequest(String::from(
+                "Encoded '/' in request path",
+            )));
+        }
+        segments.push(decoded);
+    }
+
+    let mut normalized: String = format!("/{}", segments.join("/"));
+    if path.ends_with('/') && !segments.is_empty() {
+        normalized.push('/');
+    }
+
+    return Ok(normalized);
+}
+
+/// Decodes `%XX` escape sequences in `input`.
+///
+/// # Returns
+/// - `Ok(String)` with the decoded text.
+/// - `Err(ErrorType::BadRequest)` if an escape is malformed or the result is not UTF-8.
+///
+/// # Example
+/// ```rust
+/// use rust_server::percent_decode;
+/// assert_eq!(percent_decode("/my%20file.txt").unwrap(), "/my file.txt");
+/// assert!(percent_decode("/bad%2").is_err());
+/// ```
+pub fn percent_decode(input: &str) -> Result<String, ErrorType> {
+    let bytes: &[u8] = input.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i: usize = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+
+        let byte: Option<u8> = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| str::from_utf8(hex).ok())
+            .filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match byte {
+            Some(b) => decoded.push(b),
+            None => {
+                return Err(ErrorType::BadRequest(format!(
+                    "Invalid percent-encoding: {}",
+                    input
+                )));
+            }
+        }
+        i += 3;
+    }
+
+    return match String::from_utf8(decoded) {
+        Ok(d) => Ok(d),
+        Err(_) => Err(ErrorType::BadRequest(format!(
+            "Invalid percent-encoding: {}",
+            input
+        ))),
+    };
+}
+
+/// Default maximum size of the request line and headers (8 KiB).
+pub const DEFAULT_MAX_HEADER_SIZE: usize = 8 * 1024;
+/// Default maximum size of a request body (1 MiB).
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Default maximum size of a request body after its content coding has been
+/// removed (8 MiB).
+pub const DEFAULT_MAX_DECODED_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+/// Size limits applied while a request is being read from a connection.
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    /// Maximum number of bytes allowed for the request line and headers,
+    /// including the terminating blank line.
+    pub max_header_size: usize,
+    /// Maximum number of bytes allowed for the request body.
+    pub max_body_size: usize,
+    /// Maximum number of bytes a `Content-Encoding` compressed body may
+    /// expand to.
+    pub max_decoded_body_size: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        return RequestLimits {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_decoded_body_size: DEFAULT_MAX_DECODED_BODY_SIZE,
+        };
+    }
+}
+
+/// Incremental HTTP/1.1 request parser.
+///
+/// Bytes are fed in as they are read from the socket and buffered until the
+/// header terminator (`\r\n\r\n`) and `Content-Length` bytes of body have
+/// arrived. Any bytes past the end of a complete request are kept for the
+/// next call, so requests spanning several reads are reassembled correctly.
+#[derive(Debug)]
+pub struct RequestParser {
+    buffer: Vec<u8>,
+    limits: RequestLimits,
+}
+
+impl RequestParser {
+    pub fn new(limits: RequestLimits) -> Self {
+        return RequestParser {
+            buffer: Vec::new(),
+            limits,
+        };
+    }
+
+    /// Appends bytes read from the connection to the internal buffer.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns `true` if no unparsed bytes are buffered.
+    pub fn is_empty(&self) -> bool {
+        return self.buffer.is_empty();
+    }
+
+    /// Attempts to take one complete request from the buffered bytes.
+    ///
+    /// Bodies sent with `Transfer-Encoding: chunked` are decoded, any trailer
+    /// fields are merged into the headers and the chunked coding is replaced
+    /// with a `Content-Length` header, so the returned request always carries
+    /// a plain body.
+    ///
+    /// # Returns
+    /// - `Ok(Some(bytes))` with the raw request (headers and body) once it is complete.
+    /// - `Ok(None)` if more bytes must be read before the request is complete.
+    /// - `Err(ErrorType::HeadersTooLarge)` if the headers or trailers exceed `max_header_size`.
+    /// - `Err(ErrorType::PayloadTooLarge)` if the body exceeds `max_body_size`.
+    /// - `Err(ErrorType::BadRequest)` if the body framing headers are malformed.
+    pub fn next_request(&mut self) -> Result<Option<Vec<u8>>, ErrorType> {
+        let header_end: usize = match find_subsequence(&self.buffer, b"\r\n\r\n") {
+            Some(i) => i + 4,
+            None => {
+                if self.buffer.len() > self.limits.max_header_size {
+                    return Err(ErrorType::HeadersTooLarge(format!(
+                        "Request headers exceed {} bytes",
+                        self.limits.max_header_size
+                    )));
+                }
+                return Ok(None);
+            }
+        };
+
+        if header_end > self.limits.max_header_size {
+            return Err(ErrorType::HeadersTooLarge(format!(
+                "Request headers exceed {} bytes",
+                self.limits.max_header_size
+            )));
+        }
+
+        let head: String = decode_latin1(&self.buffer[..header_end]);
+        let headers: HeaderMap = HeaderMap::from_lines(head.lines().skip(1));
+
+        if is_chunked(&headers)? {
+            let chunked: ChunkedBody =
+                match decode_chunked(&self.buffer[header_end..], &self.limits)? {
+                    Some(c) => c,
+                    None => return Ok(None),
+                };
+
+            let request: Vec<u8> = rebuild_chunked_request(&head, &chunked);
+            self.buffer.drain(..header_end + chunked.consumed);
+            return Ok(Some(request));
+        }
+
+        let content_length: usize = parse_content_length(&headers)?;
+
+        if content_length > self.limits.max_body_size {
+            return Err(ErrorType::PayloadTooLarge(format!(
+                "Request body of {} bytes exceeds {} bytes",
+                content_length, self.limits.max_body_size
+            )));
+        }
+
+        if self.buffer.len() < header_end + content_length {
+            return Ok(None);
+        }
+
+        let request: Vec<u8> = self.buffer.drain(..header_end + content_length).collect();
+        return Ok(Some(request));
+    }
+}
+
+/// Maximum length of a single chunk-size line, including any chunk extensions.
+const MAX_CHUNK_LINE_SIZE: usize = 1024;
+
+/// A fully received chunked body.
+struct ChunkedBody {
+    /// The decoded body with all chunk framing removed.
+    body: Vec<u8>,
+    /// Raw trailer field lines sent after the last chunk.
+    trailers: Vec<String>,
+    /// Number of bytes of the connection buffer the chunked body occupied.
+    consumed: usize,
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`.
+pub(crate) fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack
+        .windows(needle.len())
+        .position(|window| window == needle);
+}
+
+/// Decodes a message head as ISO-8859-1, mapping every byte to the character
+/// with the same value.
+///
+/// Field values may legally carry bytes outside ASCII, so unlike a UTF-8
+/// conversion this never fails, and [`encode_latin1`] gives back the exact
+/// bytes that were received.
+pub(crate) fn decode_latin1(bytes: &[u8]) -> String {
+    return bytes.iter().map(|&b| b as char).collect();
+}
+
+/// Encodes text produced by [`decode_latin1`] back into bytes. Characters
+/// that do not fit in a single byte are replaced with `?`.
+pub(crate) fn encode_latin1(text: &str) -> Vec<u8> {
+    return text
+        .chars()
+        .map(|c| if (c as u32) <= 0xFF { c as u8 } else { b'?' })
+        .collect();
+}
+
+/// Reads the `Content-Length` header of a request.
+///
+/// A missing header means the request has no body. Conflicting or non-numeric
+/// values are rejected as a bad request.
+fn parse_content_length(headers: &HeaderMap) -> Result<usize, ErrorType> {
+    let mut content_length: Option<usize> = None;
+
+    for value in headers.get_all("Content-Length") {
+        // `usize::from_str` also accepts a leading `+`, which the grammar does not
+        let digits: bool = !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+
+        let length: usize = match value.parse() {
+            Ok(l) if digits => l,
+            _ => {
+                return Err(ErrorType::BadRequest(String::from(
+                    "Invalid Content-Length header",
+                )));
+            }
+        };
+
+        if content_length.is_some_and(|l| l != length) {
+            return Err(ErrorType::BadRequest(String::from(
+                "Conflicting Content-Length headers",
+            )));
+        }
+
+        content_length = Some(length);
+    }
+
+    return Ok(content_length.unwrap_or(0));
+}
+
+/// Determines whether the request body uses the chunked transfer coding.
+///
+/// Requests that combine `Transfer-Encoding` with `Content-Length`, or whose
+/// final transfer coding is not `chunked`, cannot be framed safely and are
+/// rejected.
+fn is_chunked(headers: &HeaderMap) -> Result<bool, ErrorType> {
+    let codings: Vec<String> = headers
+        .get_list("Transfer-Encoding")
+        .iter()
+        .map(|c| c.to_lowercase())
+        .collect();
+
+    if codings.is_empty() {
+        return Ok(false);
+    }
+
+    if headers.contains("Content-Length") {
+        return Err(ErrorType::BadRequest(String::from(
+            "Both Transfer-Encoding and Content-Length headers present",
+        )));
+    }
+
+    if codings.len() != 1 || codings[0] != "chunked" {
+        return Err(ErrorType::BadRequest(format!(
+            "Unsupported Transfer-Encoding: {}",
+            codings.join(", ")
+        )));
+    }
+
+    return Ok(true);
+}
+
+/// Decodes a chunked body from the bytes following the request headers.
+///
+/// # Returns
+/// - `Ok(Some(ChunkedBody))` once the last chunk and trailer section have arrived.
+/// - `Ok(None)` if more bytes must be read.
+/// - `Err(ErrorType)` if the framing is invalid or a size limit is exceeded.
+fn decode_chunked(data: &[u8], limits: &RequestLimits) -> Result<Option<ChunkedBody>, ErrorType> {
+    let mut position: usize = 0;
+    let mut body: Vec<u8> = Vec::new();
+
+    loop {
+        let line_end: usize = match find_subsequence(&data[position..], b"\r\n") {
+            Some(i) => position + i,
+            None => {
+                if data.len() - position > MAX_CHUNK_LINE_SIZE {
+                    return Err(ErrorType::BadRequest(String::from(
+                        "Chunk size line too long",
+                    )));
+                }
+                return Ok(None);
+            }
+        };
+
+        let size: usize = parse_chunk_size(&data[position..line_end])?;
+        position = line_end + 2;
+
+        if size == 0 {
+            break;
+        }
+
+        // checked before any arithmetic, as the size line may hold any 64-bit value
+        if size > limits.max_body_size - body.len() {
+            return Err(ErrorType::PayloadTooLarge(format!(
+                "Request body exceeds {} bytes",
+                limits.max_body_size
+            )));
+        }
+
+        if data.len() < position + size + 2 {
+            return Ok(None);
+        }
+
+        if &data[position + size..position + size + 2] != b"\r\n" {
+            return Err(ErrorType::BadRequest(String::from(
+                "Chunk data not terminated by CRLF",
+            )));
+        }
+
+        body.extend_from_slice(&data[position..position + size]);
+        position += size + 2;
+    }
+
+    let trailer_start: usize = position;
+    let mut trailers: Vec<String> = Vec::new();
+
+    loop {
+        let line_end: usize = match find_subsequence(&data[position..], b"\r\n") {
+            Some(i) => position + i,
+            None => {
+                if data.len() - trailer_start > limits.max_header_size {
+                    return Err(ErrorType::HeadersTooLarge(format!(
+                        "Request trailers exceed {} bytes",
+                        limits.max_header_size
+                    )));
+                }
+                return Ok(None);
+            }
+        };
+
+        if line_end - trailer_start > limits.max_header_size {
+            return Err(ErrorType::HeadersTooLarge(format!(
+                "Request trailers exceed {} bytes",
+                limits.max_header_size
+            )));
+        }
+
+        if line_end == position {
+            position += 2;
+            break;
+        }
+
+        trailers.push(decode_latin1(&data[position..line_end]));
+        position = line_end + 2;
+    }
+
+    return Ok(Some(ChunkedBody {
+        body,
+        trailers,
+        consumed: position,
+    }));
+}
+
+/// Parses a chunk-size line, ignoring any chunk extensions after `;`.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, ErrorType> {
+    let line: &str = match str::from_utf8(line) {
+        Ok(l) => l,
+        Err(_) => return Err(ErrorType::BadRequest(String::from("Invalid chunk size"))),
+    };
+
+    let size: &str = line.split(';').next().unwrap_or("").trim();
+
+    if size.is_empty() || !size.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ErrorType::BadRequest(String::from("Invalid chunk size")));
+    }
+
+    return match usize::from_str_radix(size, 16) {
+        Ok(s) => Ok(s),
+        Err(_) => Err(ErrorType::BadRequest(String::from("Invalid chunk size"))),
+    };
+}
+
+/// Reassembles a chunked request as a request with a plain body.
+///
+/// The `Transfer-Encoding` header is dropped, trailer fields are appended to
+/// the headers (except those that would change the message framing) and a
+/// `Content-Length` header is added for the decoded body.
+fn rebuild_chunked_request(headers: &str, chunked: &ChunkedBody) -> Vec<u8> {
+    let mut lines: Vec<&str> = headers
+        .lines()
+        .filter(|line| !line.is_empty())
+        .filter(|line| match line.split_once(':') {
+            Some((name, _)) => !name.trim().eq_ignore_ascii_case("transfer-encoding"),
+            None => true,
+        })
+        .collect();
+
+    for trailer in &chunked.trailers {
+        let name: &str = trailer.split(':').next().unwrap_or("").trim();
+        if ["content-length", "transfer-encoding", "host"]
+            .iter()
+            .any(|n| name.eq_ignore_ascii_case(n))
+        {
+            continue;
+        }
+        lines.push(trailer);
+    }
+
+    let content_length: String = format!("Content-Length: {}", chunked.body.len());
+    lines.push(&content_length);
+
+    let mut request: Vec<u8> = encode_latin1(&lines.join("\r\n"));
+    request.extend_from_slice(b"\r\n\r\n");
+    request.extend_from_slice(&chunked.body);
+    return request;
+}
+
+/// An HTTP response status code.
+///
+/// Every standard code has its own variant; any other code can be sent with
+/// [`HttpCode::Custom`] and a reason phrase of its own. Codes compare equal
+/// when their numeric values match, so `HttpCode::from(404)` equals
+/// `HttpCode::NotFound`.
+///
+/// # Example
+/// ```rust
+/// use rust_server::HttpCode;
+/// assert_eq!(HttpCode::from(404), HttpCode::NotFound);
+/// assert_eq!(HttpCode::SeeOther.as_u16(), 303);
+/// assert_eq!(HttpCode::Custom(299, String::from("Fine")).to_string(), "299 Fine");
+/// assert!(HttpCode::from(599).is_server_error());
+/// ```
+#[derive(Debug, Clone)]
+pub enum HttpCode {
+    Continue,
+    SwitchingProtocols,
+    Processing,
+    EarlyHints,
+    Ok,
+    Created,
+    Accepted,
+    NonAuthoritativeInformation,
+    NoContent,
+    ResetContent,
+    PartialContent,
+    MultiStatus,
+    AlreadyReported,
+    ImUsed,
+    MultipleChoices,
+    MovedPermanently,
+    Found,
+    SeeOther,
+    NotModified,
+    UseProxy,
+    TemporaryRedirect,
+    PermanentRedirect,
+    BadRequest,
+    Unauthorized,
+    PaymentRequired,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    ProxyAuthenticationRequired,
+    RequestTimeout,
+    Conflict,
+    Gone,
+    LengthRequired,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UriTooLong,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    ExpectationFailed,
+    Teapot,
+    MisdirectedRequest,
+    UnprocessableEntity,
+    Locked,
+    FailedDependency,
+    TooEarly,
+    UpgradeRequired,
+    PreconditionRequired,
+    TooManyRequests,
+    RequestHeaderFieldsTooLarge,
+    UnavailableForLegalReasons,
+    InternalServerError,
+    NotImplemented,
+    BadGateway,
+    ServiceUnavailable,
+    GatewayTimeout,
+    HttpVersionNotSupported,
+    VariantAlsoNegotiates,
+    InsufficientStorage,
+    LoopDetected,
+    NotExtended,
+    NetworkAuthenticationRequired,
+    /// A non-standard code with its reason phrase.
+    Custom(u16, String),
+}
+
+impl HttpCode {
+    /// The numeric status code.
+    pub fn as_u16(&self) -> u16 {
+        return match self {
+            HttpCode::Continue => 100,
+            HttpCode::SwitchingProtocols => 101,
+            HttpCode::Processing => 102,
+            HttpCode::EarlyHints => 103,
+            HttpCode::Ok => 200,
+            HttpCode::Created => 201,
+            HttpCode::Accepted => 202,
+            HttpCode::NonAuthoritativeInformation => 203,
+            HttpCode::NoContent => 204,
+            HttpCode::ResetContent => 205,
+            HttpCode::PartialContent => 206,
+            HttpCode::MultiStatus => 207,
+            HttpCode::AlreadyReported => 208,
+            HttpCode::ImUsed => 226,
+            HttpCode::MultipleChoices => 300,
+            HttpCode::MovedPermanently => 301,
+            HttpCode::Found => 302,
+            HttpCode::SeeOther => 303,
+            HttpCode::NotModified => 304,
+            HttpCode::UseProxy => 305,
+            HttpCode::TemporaryRedirect => 307,
+            HttpCode::PermanentRedirect => 308,
+            HttpCode::BadRequest => 400,
+            HttpCode::Unauthorized => 401,
+            HttpCode::PaymentRequired => 402,
+            HttpCode::Forbidden => 403,
+            HttpCode::NotFound => 404,
+            HttpCode::MethodNotAllowed => 405,
+            HttpCode::NotAcceptable => 406,
+            HttpCode::ProxyAuthenticationRequired => 407,
+            HttpCode::RequestTimeout => 408,
+            HttpCode::Conflict => 409,
+            HttpCode::Gone => 410,
+            HttpCode::LengthRequired => 411,
+            HttpCode::PreconditionFailed => 412,
+            HttpCode::PayloadTooLarge => 413,
+            HttpCode::UriTooLong => 414,
+            HttpCode::UnsupportedMediaType => 415,
+            HttpCode::RangeNotSatisfiable => 416,
+            HttpCode::ExpectationFailed => 417,
+            HttpCode::Teapot => 418,
+            HttpCode::MisdirectedRequest => 421,
+            HttpCode::UnprocessableEntity => 422,
+            HttpCode::Locked => 423,
+            HttpCode::FailedDependency => 424,
+            HttpCode::TooEarly => 425,
+            HttpCode::UpgradeRequired => 426,
+            HttpCode::PreconditionRequired => 428,
+            HttpCode::TooManyRequests => 429,
+            HttpCode::RequestHeaderFieldsTooLarge => 431,
+            HttpCode::UnavailableForLegalReasons => 451,
+            HttpCode::InternalServerError => 500,
+            HttpCode::NotImplemented => 501,
+            HttpCode::BadGateway => 502,
+            HttpCode::ServiceUnavailable => 503,
+            HttpCode::GatewayTimeout => 504,
+            HttpCode::HttpVersionNotSupported => 505,
+            HttpCode::VariantAlsoNegotiates => 506,
+            HttpCode::InsufficientStorage => 507,
+            HttpCode::LoopDetected => 508,
+            HttpCode::NotExtended => 510,
+            HttpCode::NetworkAuthenticationRequired => 511,
+            HttpCode::Custom(code, _) => *code,
+        };
+    }
+
+    /// The reason phrase sent after the code in the status line.
+    pub fn reason(&self) -> &str {
+        return match self {
+            HttpCode::Continue => "Continue",
+            HttpCode::SwitchingProtocols => "Switching Protocols",
+            HttpCode::Processing => "Processing",
+            HttpCode::EarlyHints => "Early Hints",
+            HttpCode::Ok => "OK",
+            HttpCode::Created => "Created",
+            HttpCode::Accepted => "Accepted",
+            HttpCode::NonAuthoritativeInformation => "Non-Authoritative Information",
+            HttpCode::NoContent => "No Content",
+            HttpCode::ResetContent => "Reset Content",
+            HttpCode::PartialContent => "Partial Content",
+            HttpCode::MultiStatus => "Multi-Status",
+            HttpCode::AlreadyReported => "Already Reported",
+            HttpCode::ImUsed => "IM Used",
+            HttpCode::MultipleChoices => "Multiple Choices",
+            HttpCode::MovedPermanently => "Moved Permanently",
+            HttpCode::Found => "Found",
+            HttpCode::SeeOther => "See Other",
+            HttpCode::NotModified => "Not Modified",
+            HttpCode::UseProxy => "Use Proxy",
+            HttpCode::TemporaryRedirect => "Temporary Redirect",
+            HttpCode::PermanentRedirect => "Permanent Redirect",
+            HttpCode::BadRequest => "Bad Request",
+            HttpCode::Unauthorized => "Unauthorized",
+            HttpCode::PaymentRequired => "Payment Required",
+            HttpCode::Forbidden => "Forbidden",
+            HttpCode::NotFound => "Not Found",
+            HttpCode::MethodNotAllowed => "Method Not Allowed",
+            HttpCode::NotAcceptable => "Not Acceptable",
+            HttpCode::ProxyAuthenticationRequired => "Proxy Authentication Required",
+            HttpCode::RequestTimeout => "Request Timeout",
+            HttpCode::Conflict => "Conflict",
+            HttpCode::Gone => "Gone",
+            HttpCode::LengthRequired => "Length Required",
+            HttpCode::PreconditionFailed => "Precondition Failed",
+            HttpCode::PayloadTooLarge => "Payload Too Large",
+            HttpCode::UriTooLong => "URI Too Long",
+            HttpCode::UnsupportedMediaType => "Unsupported Media Type",
+            HttpCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            HttpCode::ExpectationFailed => "Expectation Failed",
+            HttpCode::Teapot => "I'm a teapot",
+            HttpCode::MisdirectedRequest => "Misdirected Request",
+            HttpCode::UnprocessableEntity => "Unprocessable Entity",
+            HttpCode::Locked => "Locked",
+            HttpCode::FailedDependency => "Failed Dependency",
+            HttpCode::TooEarly => "Too Early",
+            HttpCode::UpgradeRequired => "Upgrade Required",
+            HttpCode::PreconditionRequired => "Precondition Required",
+            HttpCode::TooManyRequests => "Too Many Requests",
+            HttpCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
+            HttpCode::UnavailableForLegalReasons => "Unavailable For Legal Reasons",
+            HttpCode::InternalServerError => "Internal Server Error",
+            HttpCode::NotImplemented => "Not Implemented",
+            HttpCode::BadGateway => "Bad Gateway",
+            HttpCode::ServiceUnavailable => "Service Unavailable",
+            HttpCode::GatewayTimeout => "Gateway Timeout",
+            HttpCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+            HttpCode::VariantAlsoNegotiates => "Variant Also Negotiates",
+            HttpCode::InsufficientStorage => "Insufficient Storage",
+            HttpCode::LoopDetected => "Loop Detected",
+            HttpCode::NotExtended => "Not Extended",
+            HttpCode::NetworkAuthenticationRequired => "Network Authentication Required",
+            HttpCode::Custom(_, reason) => reason,
+        };
+    }
+
+    /// Returns `true` for `1xx` codes.
+    pub fn is_informational(&self) -> bool {
+        return (100..200).contains(&self.as_u16());
+    }
+
+    /// Returns `true` for `2xx` codes.
+    pub fn is_success(&self) -> bool {
+        return (200..300).contains(&self.as_u16());
+    }
+
+    /// Returns `true` for `3xx` codes.
+    pub fn is_redirection(&self) -> bool {
+        return (300..400).contains(&self.as_u16());
+    }
+
+    /// Returns `true` for `4xx` codes.
+    pub fn is_client_error(&self) -> bool {
+        return (400..500).contains(&self.as_u16());
+    }
+
+    /// Returns `true` for `5xx` codes.
+    pub fn is_server_error(&self) -> bool {
+        return (500..600).contains(&self.as_u16());
+    }
+
+    /// Returns `false` for the codes whose responses never carry a body:
+    /// `1xx`, `204 No Content` and `304 Not Modified`.
+    pub fn allows_body(&self) -> bool {
+        return !(self.is_informational()
+            || *self == HttpCode::NoContent
+            || *self == HttpCode::NotModified);
+    }
+}
+
+impl From<u16> for HttpCode {
+    /// Maps a numeric code to its standard variant, or to
+    /// `HttpCode::Custom` with an empty reason phrase if it has none.
+    fn from(code: u16) -> Self {
+        return match code {
+            100 => HttpCode::Continue,
+            101 => HttpCode::SwitchingProtocols,
+            102 => HttpCode::Processing,
+            103 => HttpCode::EarlyHints,
+            200 => HttpCode::Ok,
+            201 => HttpCode::Created,
+            202 => HttpCode::Accepted,
+            203 => HttpCode::NonAuthoritativeInformation,
+            204 => HttpCode::NoContent,
+            205 => HttpCode::ResetContent,
+            206 => HttpCode::PartialContent,
+            207 => HttpCode::MultiStatus,
+            208 => HttpCode::AlreadyReported,
+            226 => HttpCode::ImUsed,
+            300 => HttpCode::MultipleChoices,
+            301 => HttpCode::MovedPermanently,
+            302 => HttpCode::Found,
+            303 => HttpCode::SeeOther,
+            304 => HttpCode::NotModified,
+            305 => HttpCode::UseProxy,
+            307 => HttpCode::TemporaryRedirect,
+            308 => HttpCode::PermanentRedirect,
+            400 => HttpCode::BadRequest,
+            401 => HttpCode::Unauthorized,
+            402 => HttpCode::PaymentRequired,
+            403 => HttpCode::Forbidden,
+            404 => HttpCode::NotFound,
+            405 => HttpCode::MethodNotAllowed,
+            406 => HttpCode::NotAcceptable,
+            407 => HttpCode::ProxyAuthenticationRequired,
+            408 => HttpCode::RequestTimeout,
+            409 => HttpCode::Conflict,
+            410 => HttpCode::Gone,
+            411 => HttpCode::LengthRequired,
+            412 => HttpCode::PreconditionFailed,
+            413 => HttpCode::PayloadTooLarge,
+            414 => HttpCode::UriTooLong,
+            415 => HttpCode::UnsupportedMediaType,
+            416 => HttpCode::RangeNotSatisfiable,
+            417 => HttpCode::ExpectationFailed,
+            418 => HttpCode::Teapot,
+            421 => HttpCode::MisdirectedRequest,
+            422 => HttpCode::UnprocessableEntity,
+            423 => HttpCode::Locked,
+            424 => HttpCode::FailedDependency,
+            425 => HttpCode::TooEarly,
+            426 => HttpCode::UpgradeRequired,
+            428 => HttpCode::PreconditionRequired,
+            429 => HttpCode::TooManyRequests,
+            431 => HttpCode::RequestHeaderFieldsTooLarge,
+            451 => HttpCode::UnavailableForLegalReasons,
+            500 => HttpCode::InternalServerError,
+            501 => HttpCode::NotImplemented,
+            502 => HttpCode::BadGateway,
+            503 => HttpCode::ServiceUnavailable,
+            504 => HttpCode::GatewayTimeout,
+            505 => HttpCode::HttpVersionNotSupported,
+            506 => HttpCode::VariantAlsoNegotiates,
+            507 => HttpCode::InsufficientStorage,
+            508 => HttpCode::LoopDetected,
+            510 => HttpCode::NotExtended,
+            511 => HttpCode::NetworkAuthenticationRequired,
+            code => HttpCode::Custom(code, String::new()),
+        };
+    }
+}
+
+impl From<&HttpCode> for u16 {
+    fn from(code: &HttpCode) -> Self {
+        return code.as_u16();
+    }
+}
+
+impl Display for HttpCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.as_u16(), self.reason())
+    }
+}
+
+impl PartialEq for HttpCode {
     fn eq(&self, other: &Self) -> bool {
+        return self.as_u16() == other.as_u16();
+    }
+}
+
+/// An HTTP request method.
+///
+/// Methods are case-sensitive. Any valid method token that is not one of the
+/// standard methods is kept as an [`HttpMethod::Extension`], so routes can be
+/// registered for methods such as `PROPFIND`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HttpMethod {
+    GET,
+    HEAD,
+    POST,
+    PUT,
+    PATCH,
+    DELETE,
+    OPTIONS,
+    TRACE,
+    CONNECT,
+    Extension(String),
+}
+
+impl HttpMethod {
+    /// Parses a method token from a request line.
+    ///
+    /// # Returns
+    /// - `Ok(HttpMethod)` for a standard method, or an `HttpMethod::Extension`
+    ///   for any other valid token.
+    /// - `Err(ErrorType::BadRequest)` if `method` is not a valid token.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::HttpMethod;
+    /// assert_eq!(HttpMethod::new("HEAD").unwrap(), HttpMethod::HEAD);
+    /// assert_eq!(
+    ///     HttpMethod::new("PROPFIND").unwrap(),
+    ///     HttpMethod::Extension(String::from("PROPFIND"))
+    /// );
+    /// assert!(HttpMethod::new("GE(T").is_err());
+    /// ```
+    pub fn new(method: &str) -> Result<HttpMethod, ErrorType> {
+        if !is_token(method) {
+            return Err(ErrorType::BadRequest(String::from(
+                "Invalid request method",
+            )));
+        }
+
+        return Ok(match method {
+            "GET" => HttpMethod::GET,
+            "HEAD" => HttpMethod::HEAD,
+            "POST" => HttpMethod::POST,
+            "PUT" => HttpMethod::PUT,
+            "PATCH" => HttpMethod::PATCH,
+            "DELETE" => HttpMethod::DELETE,
+            "OPTIONS" => HttpMethod::OPTIONS,
+            "TRACE" => HttpMethod::TRACE,
+            "CONNECT" => HttpMethod::CONNECT,
+            other => HttpMethod::Extension(other.to_string()),
+        });
+    }
+}
+
+/// Returns `true` if `value` is a non-empty RFC 9110 token, the syntax of
+/// method names and header field names.
+pub fn is_token(value: &str) -> bool {
+    return !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+}
+
+impl Display for HttpMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            HttpMethod::GET => match other {
-                HttpMethod::GET => true,
-                _ => false,
-            },
-            HttpMethod::POST => match other {
-                HttpMethod::POST => true,
-                _ => false,
-            },
-            HttpMethod::PUT => match other {
-                HttpMethod::PUT => true,
-                _ => false,
-            },
-            HttpMethod::PATCH => match other {
-                HttpMethod::PATCH => true,
-                _ => false,
-            },
-            HttpMethod::DELETE => match other {
-                HttpMethod::DELETE => true,
-                _ => false,
-            },
+            HttpMethod::GET => write!(f, "GET"),
+            HttpMethod::HEAD => write!(f, "HEAD"),
+            HttpMethod::POST => write!(f, "POST"),
+            HttpMethod::PUT => write!(f, "PUT"),
+            HttpMethod::PATCH => write!(f, "PATCH"),
+            HttpMethod::DELETE => write!(f, "DELETE"),
+            HttpMethod::OPTIONS => write!(f, "OPTIONS"),
+            HttpMethod::TRACE => write!(f, "TRACE"),
+            HttpMethod::CONNECT => write!(f, "CONNECT"),
+            HttpMethod::Extension(method) => write!(f, "{}", method),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::STREAM_CHUNK_SIZE;
+    use std::io::Read;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn test_parser_waits_for_header_terminator() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"GET / HTTP/1.1\r\nHost: exam");
+        assert!(parser.next_request().unwrap().is_none());
+
+        parser.feed(b"ple.com\r\n\r\n");
+        let request = parser.next_request().unwrap().unwrap();
+        assert_eq!(request, b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn test_parser_reads_content_length_body() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"POST /login HTTP/1.1\r\nHost: a\r\ncontent-length: 10\r\n\r\n01234");
+        assert!(parser.next_request().unwrap().is_none());
+
+        parser.feed(b"56789GET / HTTP/1.1\r\n");
+        let request = parser.next_request().unwrap().unwrap();
+        assert!(request.ends_with(b"\r\n\r\n0123456789"));
+        assert!(!parser.is_empty());
+    }
+
+    #[test]
+    fn test_parser_headers_too_large() {
+        let limits = RequestLimits {
+            max_header_size: 32,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_decoded_body_size: DEFAULT_MAX_DECODED_BODY_SIZE,
+        };
+        let mut parser = RequestParser::new(limits);
+        parser.feed(b"GET / HTTP/1.1\r\nHost: example.com\r\nUser-Agent: test");
+
+        assert_eq!(
+            parser.next_request().unwrap_err(),
+            ErrorType::HeadersTooLarge(String::new())
+        );
+    }
+
+    #[test]
+    fn test_parser_payload_too_large() {
+        let limits = RequestLimits {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: 4,
+            max_decoded_body_size: DEFAULT_MAX_DECODED_BODY_SIZE,
+        };
+        let mut parser = RequestParser::new(limits);
+        parser.feed(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\n");
+
+        assert_eq!(
+            parser.next_request().unwrap_err(),
+            ErrorType::PayloadTooLarge(String::new())
+        );
+    }
+
+    #[test]
+    fn test_parser_decodes_chunked_body() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"POST /upload HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n");
+        parser.feed(b"5;ext=1\r\nhello\r\n6\r\n wor");
+        assert!(parser.next_request().unwrap().is_none());
+
+        parser.feed(b"ld\r\n0\r\nChecksum: abc\r\n\r\n");
+        let request = parser.next_request().unwrap().unwrap();
+        assert_eq!(
+            request,
+            b"POST /upload HTTP/1.1\r\nHost: a\r\nChecksum: abc\r\nContent-Length: 11\r\n\r\nhello world"
+        );
+        assert!(parser.is_empty());
+    }
+
+    #[test]
+    fn test_binary_body_is_kept_as_bytes() {
+        let body: &[u8] = &[0x00, 0xFF, 0x80, b'\r', b'\n', 0xC3, 0x28];
+        let mut raw: Vec<u8> =
+            b"POST /upload HTTP/1.1\r\nHost: a\r\nX-Name: caf\xE9\r\nContent-Length: 7\r\n\r\n"
+                .to_vec();
+        raw.extend_from_slice(body);
+
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(&raw);
+        let request: Vec<u8> = parser.next_request().unwrap().unwrap();
+        assert_eq!(request, raw);
+
+        let request: Request = Request::new(&request).unwrap();
+        assert_eq!(request.body, body);
+        assert_eq!(request.headers.get("X-Name"), Some("caf\u{e9}"));
+        assert!(request.text().is_err());
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let body: Vec<u8> = Encoding::Gzip.encode(b"{\"username\": \"a\"}");
+        let mut raw: Vec<u8> = format!(
+            "POST /login HTTP/1.1\r\nHost: a\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(&body);
+
+        let mut request: Request = Request::new(&raw).unwrap();
+        request.decode_body(1024).unwrap();
+        assert_eq!(request.body, b"{\"username\": \"a\"}");
+        assert!(!request.headers.contains("Content-Encoding"));
+        assert_eq!(request.headers.get("Content-Length"), Some("17"));
+
+        let mut request: Request = Request::new(&raw).unwrap();
+        assert_eq!(
+            request.decode_body(8),
+            Err(ErrorType::PayloadTooLarge(String::new()))
+        );
+
+        let mut request: Request = Request::new(
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Encoding: br\r\nContent-Length: 1\r\n\r\nx",
+        )
+        .unwrap();
+        assert_eq!(
+            request.decode_body(1024),
+            Err(ErrorType::UnsupportedMediaType(String::new()))
+        );
+    }
+
+    #[test]
+    fn test_chunked_request_keeps_raw_header_bytes() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"POST / HTTP/1.1\r\nX-Name: \xFF\r\nTransfer-Encoding: chunked\r\n\r\n");
+        parser.feed(b"2\r\n\x00\xFF\r\n0\r\n\r\n");
+
+        let request = parser.next_request().unwrap().unwrap();
+        assert_eq!(
+            request,
+            b"POST / HTTP/1.1\r\nX-Name: \xFF\r\nContent-Length: 2\r\n\r\n\x00\xFF"
+        );
+    }
+
+    #[test]
+    fn test_parser_chunked_with_content_length() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(
+            b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\n",
+        );
+
+        assert_eq!(
+            parser.next_request().unwrap_err(),
+            ErrorType::BadRequest(String::new())
+        );
+    }
+
+    #[test]
+    fn test_parser_chunked_payload_too_large() {
+        let limits = RequestLimits {
+            max_header_size: DEFAULT_MAX_HEADER_SIZE,
+            max_body_size: 8,
+            max_decoded_body_size: DEFAULT_MAX_DECODED_BODY_SIZE,
+        };
+        let mut parser = RequestParser::new(limits);
+        parser.feed(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n");
+        parser.feed(b"5\r\nhello\r\n5\r\n");
+
+        assert_eq!(
+            parser.next_request().unwrap_err(),
+            ErrorType::PayloadTooLarge(String::new())
+        );
+
+        for size in ["ffffffffffffffff", "fffffffffffffffe", "9"] {
+            let mut parser = RequestParser::new(limits);
+            parser.feed(b"POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n");
+            parser.feed(format!("{}\r\nhello\r\n", size).as_bytes());
+
+            assert_eq!(
+                parser.next_request().unwrap_err(),
+                ErrorType::PayloadTooLarge(String::new()),
+                "{}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn test_http_code_conversions() {
+        for code in 100..600u16 {
+            let http_code = HttpCode::from(code);
+            assert_eq!(http_code.as_u16(), code);
+            assert_eq!(u16::from(&http_code), code);
+        }
+
+        assert_eq!(HttpCode::from(409), HttpCode::Conflict);
+        assert_eq!(HttpCode::from(299), HttpCode::Custom(299, String::new()));
+        assert_eq!(
+            HttpCode::MovedPermanently.to_string(),
+            "301 Moved Permanently"
+        );
+        assert_eq!(
+            HttpCode::Custom(599, String::from("Custom")).to_string(),
+            "599 Custom"
+        );
+        assert!(HttpCode::TemporaryRedirect.is_redirection());
+        assert!(HttpCode::TooManyRequests.is_client_error());
+        assert!(!HttpCode::ServiceUnavailable.is_success());
+    }
+
+    #[test]
+    fn test_response_without_body() {
+        let mut response = Response::new(
+            Protocol::Http,
+            HttpCode::NoContent,
+            ContentType::Text,
+            false,
+        )
+        .body(b"ignored".to_vec());
+        let output = String::from_utf8(response.to_bytes()).unwrap();
+        assert!(output.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_response_writes_chunked_body() {
+        let (tx, rx) = mpsc::channel(4);
+        let mut response =
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false).chunked(rx);
+
+        tokio::spawn(async move {
+            tx.send(b"hello ".to_vec()).await.unwrap();
+            tx.send(Vec::new()).await.unwrap();
+            tx.send(b"world".to_vec()).await.unwrap();
+        });
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_response_unframed_body_without_chunked() {
+        let (tx, rx) = mpsc::channel(4);
+        let mut response =
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false).chunked(rx);
+        response.allow_chunked = false;
+        assert!(!response.has_known_length());
+
+        tokio::spawn(async move {
+            tx.send(b"hello ".to_vec()).await.unwrap();
+            tx.send(b"world".to_vec()).await.unwrap();
+        });
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(!output.contains("Transfer-Encoding"));
+        assert!(!output.contains("Content-Length"));
+        assert!(output.ends_with("\r\n\r\nhello world"));
+
+        let data: Vec<u8> = b"a compressible body ".repeat(100);
+        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, true)
+            .reader(std::io::Cursor::new(data.clone()), data.len() as u64);
+        response.allow_chunked = false;
+        assert!(!response.has_known_length());
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+
+        let head_end: usize = find_subsequence(&output, b"\r\n\r\n").unwrap() + 4;
+        let head: &str = str::from_utf8(&output[..head_end]).unwrap();
+        assert!(!head.contains("Transfer-Encoding"));
+        assert!(!head.contains("Content-Length"));
+        assert_eq!(
+            Encoding::Gzip
+                .decode(&output[head_end..], data.len())
+                .unwrap(),
+            data
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_streams_reader_body() {
+        let data: Vec<u8> = (0..STREAM_CHUNK_SIZE * 2 + 10).map(|i| i as u8).collect();
+        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            .reader(std::io::Cursor::new(data.clone()), data.len() as u64);
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+
+        let head_end: usize = find_subsequence(&output, b"\r\n\r\n").unwrap() + 4;
+        let head: &str = str::from_utf8(&output[..head_end]).unwrap();
+        assert!(head.contains(&format!("Content-Length: {}\r\n", data.len())));
+        assert!(!head.contains("Transfer-Encoding"));
+        assert_eq!(&output[head_end..], &data[..]);
+
+        // a reader that ends before the announced length is an error
+        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            .reader(&b"short"[..], 10);
+        assert!(response.write_to(&mut Vec::new()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_response_streams_compressed_file_body() {
+        let path = std::env::temp_dir().join(format!("rust_server_body_{}", std::process::id()));
+        std::fs::write(&path, "0123456789").unwrap();
+        let file = File::open(&path).await.unwrap();
+
+        let mut response =
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, true).file(file, 2, 5);
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+
+        let head_end: usize = find_subsequence(&output, b"\r\n\r\n").unwrap() + 4;
+        let head: &str = str::from_utf8(&output[..head_end]).unwrap();
+        assert!(head.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!head.contains("Content-Length"));
+
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n");
+        parser.feed(&output[head_end..]);
+        let request: Vec<u8> = parser.next_request().unwrap().unwrap();
+        let body_start: usize = find_subsequence(&request, b"\r\n\r\n").unwrap() + 4;
+
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(&request[body_start..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "23456");
+    }
+
+    #[cfg(all(target_os = "linux", feature = "sendfile"))]
+    #[tokio::test]
+    async fn test_response_sends_file_with_sendfile() {
+        use tokio::io::AsyncReadExt;
+
+        let path =
+            std::env::temp_dir().join(format!("rust_server_sendfile_{}", std::process::id()));
+        let data: Vec<u8> = (0..STREAM_CHUNK_SIZE * 20)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let reader = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut output: Vec<u8> = Vec::new();
+            socket.read_to_end(&mut output).await.unwrap();
+            return output;
+        });
+
+        let mut socket = TcpStream::connect(addr).await.unwrap();
+        let file = File::open(&path).await.unwrap();
+        let mut response = Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            .file(file, 1, data.len() as u64 - 1);
+        response.send(&mut socket).await.unwrap();
+        drop(socket);
+
+        let output: Vec<u8> = reader.await.unwrap();
+        let head_end: usize = find_subsequence(&output, b"\r\n\r\n").unwrap() + 4;
+        assert_eq!(&output[head_end..], &data[1..]);
+    }
+
+    #[test]
+    fn test_parser_pipelined_requests() {
+        let mut parser = RequestParser::new(RequestLimits::default());
+        parser.feed(b"GET /a HTTP/1.1\r\nHost: a\r\n\r\nGET /b HTTP/1.1\r\nHost: a\r\n\r\n");
+
+        let first = parser.next_request().unwrap().unwrap();
+        let second = parser.next_request().unwrap().unwrap();
+        assert!(first.starts_with(b"GET /a "));
+        assert!(second.starts_with(b"GET /b "));
+        assert!(parser.next_request().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_request_keep_alive() {
+        let request = Request::new(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert!(request.keep_alive());
+
+        let request =
+            Request::new(b"GET / HTTP/1.1\r\nHost: a\r\nConnection: Close\r\n\r\n").unwrap();
+        assert!(!request.keep_alive());
+
+        let request = Request::new(b"GET / HTTP/1.0\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(request.protocol, Protocol::Http10);
+        assert!(!request.keep_alive());
+
+        let request =
+            Request::new(b"GET / HTTP/1.0\r\nHost: a\r\nConnection: keep-alive\r\n\r\n").unwrap();
+        assert!(request.keep_alive());
+    }
+
+    #[test]
+    fn test_header_map_case_insensitive() {
+        let request =
+            Request::new(b"GET / HTTP/1.1\r\nhost: a\r\nX-Tag: one\r\nx-tag: two\r\n\r\n").unwrap();
+
+        assert_eq!(request.headers.get("Host"), Some("a"));
+        assert_eq!(request.headers.get("X-TAG"), Some("one"));
+        assert_eq!(request.headers.get_all("x-tag"), vec!["one", "two"]);
+        assert!(!request.headers.contains("Cookie"));
+        assert_eq!(request.headers.len(), 3);
+    }
+
+    #[test]
+    fn test_request_path_and_query() {
+        let request = Request::new(
+            b"GET /my%20files/a%2Bb.txt?tab=1&name=J%C3%BCrgen+S&tab=2&flag HTTP/1.1\r\nHost: a\r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.path, "/my files/a+b.txt");
+        assert_eq!(request.query_param("tab"), Some("1"));
+        assert_eq!(request.query_param("name"), Some("Jürgen S"));
+        assert_eq!(request.query_param("flag"), Some(""));
+        assert_eq!(request.query_param("missing"), None);
+    }
+
+    #[test]
+    fn test_request_path_is_normalized() {
+        for (target, path) in [
+            ("//files/x", "/files/x"),
+            ("/files//x/", "/files/x/"),
+            ("///", "/"),
+            ("/", "/"),
+            ("*", "*"),
+        ] {
+            let raw: String = format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", target);
+            assert_eq!(Request::new(raw.as_bytes()).unwrap().path, path);
+        }
+
+        for target in ["/%2Ffiles/x", "/files%2fx", "/a/%2F/b"] {
+            let raw: String = format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", target);
+            assert_eq!(
+                Request::new(raw.as_bytes()).err(),
+                Some(ErrorType::BadRequest(String::new()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_invalid_percent_encoding() {
+        let result = Request::new(b"GET /a%zz HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(result.err(), Some(ErrorType::BadRequest(String::new())));
+    }
+
+    #[test]
+    fn test_request_short_head() {
+        let request = Request::new(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!(request.method, HttpMethod::GET);
+        assert_eq!(request.path, "/");
+        assert_eq!(request.protocol, Protocol::Http10);
+        assert!(request.headers.is_empty());
+
+        for raw in [&b""[..], b"\r\n\r\n"] {
+            assert_eq!(
+                Request::new(raw).err(),
+                Some(ErrorType::BadRequest(String::new()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_line_needs_three_parts() {
+        for raw in [
+            &b"GET\r\nHost: a\r\n\r\n"[..],
+            b"GET /\r\nHost: a\r\n\r\n",
+            b"GET / HTTP/1.1 extra\r\nHost: a\r\n\r\n",
+            b"\r\nHost: a\r\n\r\n",
+        ] {
+            assert_eq!(
+                Request::new(raw).err(),
+                Some(ErrorType::BadRequest(String::new()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_body_keeps_line_breaks() {
+        let request =
+            Request::new(b"POST / HTTP/1.1\r\nHost: a\r\n\r\nline one\r\n\r\nline two\n").unwrap();
+        assert_eq!(request.body, b"line one\r\n\r\nline two\n");
+    }
+
+    #[test]
+    fn test_parser_invalid_content_length() {
+        for value in ["abc", "+3", "-3", "3 3", "0x3", ""] {
+            let mut parser = RequestParser::new(RequestLimits::default());
+            parser.feed(
+                format!(
+                    "POST / HTTP/1.1\r\nHost: a\r\nContent-Length: {}\r\n\r\nabc",
+                    value
+                )
+                .as_bytes(),
+            );
+
+            assert_eq!(
+                parser.next_request().unwrap_err(),
+                ErrorType::BadRequest(String::new()),
+                "{}",
+                value
+            );
         }
     }
 }
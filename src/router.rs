@@ -0,0 +1,800 @@
+use crate::{
+    ContentType, HttpCode, HttpMethod, Logger, Middleware, Next, Protocol, Request, Response,
+    StaticFiles, UploadDir,
+};
+use colored::Colorize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// The future returned by a route handler.
+pub type HandlerFuture = Pin<Box<dyn Future<Output = Response> + Send>>;
+
+/// A type-erased route handler.
+pub type Handler = Arc<dyn Fn(Request, Arc<Mutex<Logger>>) -> HandlerFuture + Send + Sync>;
+
+/// One segment of a route pattern.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    /// Matches a path segment exactly.
+    Literal(String),
+    /// Matches any single path segment and captures it under the given name.
+    Param(String),
+    /// Matches the remainder of the path (zero or more segments) and captures
+    /// it under the given name.
+    Wildcard(String),
+}
+
+/// A parsed route pattern such as `/users/:id` or `/static/*path`.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    /// Parses a route pattern.
+    ///
+    /// Segments starting with `:` capture a single path segment, and a final
+    /// segment starting with `*` captures the rest of the path. A bare `*`
+    /// captures under the name `*`.
+    ///
+    /// # Panics
+    /// Panics if a wildcard segment is not the last segment of the pattern.
+    pub fn new(pattern: &str) -> Self {
+        let parts: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+        let mut segments: Vec<Segment> = Vec::with_capacity(parts.len());
+
+        for (i, part) in parts.iter().enumerate() {
+            if let Some(name) = part.strip_prefix(':') {
+                segments.push(Segment::Param(name.to_string()));
+            } else if let Some(name) = part.strip_prefix('*') {
+                if i != parts.len() - 1 {
+                    panic!("Wildcard must be the last segment of route {}", pattern);
+                }
+                let name: &str = if name.is_empty() { "*" } else { name };
+                segments.push(Segment::Wildcard(name.to_string()));
+            } else {
+                segments.push(Segment::Literal(part.to_string()));
+            }
+        }
+
+        return PathPattern { segments };
+    }
+
+    /// Matches a request path against the pattern.
+    ///
+    /// # Returns
+    /// - `Some(params)` with the captured path parameters if the path matches.
+    /// - `None` if it does not.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::PathPattern;
+    /// let pattern = PathPattern::new("/users/:id/*rest");
+    /// let params = pattern.matches("/users/42/files/a.txt").unwrap();
+    /// assert_eq!(params["id"], "42");
+    /// assert_eq!(params["rest"], "files/a.txt");
+    /// assert!(pattern.matches("/users").is_none());
+    /// ```
+    pub fn matches(&self, path: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let mut params: HashMap<String, String> = HashMap::new();
+
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Wildcard(name) => {
+                    params.insert(name.clone(), parts.get(i..).unwrap_or(&[]).join("/"));
+                    return Some(params);
+                }
+                Segment::Literal(literal) => {
+                    if parts.get(i) != Some(&literal.as_str()) {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => match parts.get(i) {
+                    Some(part) => {
+                        params.insert(name.clone(), part.to_string());
+                    }
+                    None => return None,
+                },
+            }
+        }
+
+        if parts.len() != self.segments.len() {
+            return None;
+        }
+
+        return Some(params);
+    }
+}
+
+/// A single registered route.
+struct Route {
+    method: HttpMethod,
+    pattern: PathPattern,
+    handler: Handler,
+    cache_control: Option<String>,
+}
+
+/// Dispatches requests to handlers registered by method and path pattern.
+///
+/// Every request first passes through the middleware layers added with
+/// [`Router::layer`], in the order they were added, before reaching the
+/// route table. Routes are tried in the order they were registered. When no route matches
+/// the path a `404 Not Found` is returned, and when the path matches but the
+/// method does not a `405 Method Not Allowed` is returned with an `Allow`
+/// header listing the methods that are registered for it.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{ContentType, HttpCode, Protocol, Response, Router};
+/// let mut router = Router::new();
+/// router.get("/users/:id", |request, _logger| async move {
+///     let id = request.param("id").unwrap_or("").to_string();
+///     Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false).body(id.into_bytes())
+/// });
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        return Router {
+            routes: Vec::new(),
+            middlewares: Vec::new(),
+        };
+    }
+
+    /// Wraps every route in `middleware`.
+    ///
+    /// Layers run in the order they are added, so the first layer sees the
+    /// request first and the response last.
+    pub fn layer<M>(&mut self, middleware: M) -> &mut Self
+    where
+        M: Middleware + 'static,
+    {
+        self.middlewares.push(Arc::new(middleware));
+        return self;
+    }
+
+    /// Registers `handler` for requests with the given method whose path
+    /// matches `pattern`.
+    ///
+    /// # Arguments
+    /// - `method`: The HTTP method the route responds to.
+    /// - `pattern`: The path pattern, see [`PathPattern::new`].
+    /// - `handler`: An async function taking the request and the shared logger.
+    pub fn route<F, Fut>(&mut self, method: HttpMethod, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        let handler: Handler = Arc::new(move |request, logger| Box::pin(handler(request, logger)));
+        self.routes.push(Route {
+            method,
+            pattern: PathPattern::new(pattern),
+            handler,
+            cache_control: None,
+        });
+        return self;
+    }
+
+    /// Sets the `Cache-Control` policy of the most recently registered route.
+    ///
+    /// The policy is applied to the route's successful responses unless the
+    /// handler set a `Cache-Control` header itself. Responses of routes without
+    /// a policy, error responses, and the router's own `404`, `405` and `501`
+    /// responses are sent with `Cache-Control: no-store`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use rust_server::Router;
+    /// let mut router = Router::new();
+    /// router
+    ///     .static_dir("/assets", "static")
+    ///     .cache_control("public, max-age=3600");
+    /// ```
+    pub fn cache_control(&mut self, value: &str) -> &mut Self {
+        if let Some(route) = self.routes.last_mut() {
+            route.cache_control = Some(value.to_string());
+        }
+        return self;
+    }
+
+    pub fn get<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        return self.route(HttpMethod::GET, pattern, handler);
+    }
+
+    pub fn post<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        return self.route(HttpMethod::POST, pattern, handler);
+    }
+
+    pub fn put<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        return self.route(HttpMethod::PUT, pattern, handler);
+    }
+
+    pub fn patch<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        return self.route(HttpMethod::PATCH, pattern, handler);
+    }
+
+    pub fn delete<F, Fut>(&mut self, pattern: &str, handler: F) -> &mut Self
+    where
+        F: Fn(Request, Arc<Mutex<Logger>>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response> + Send + 'static,
+    {
+        return self.route(HttpMethod::DELETE, pattern, handler);
+    }
+
+    /// Serves the files below `dir` for `GET` requests under `prefix`.
+    ///
+    /// # Arguments
+    /// - `prefix`: The URL prefix, e.g. `/static`. A request for
+    ///   `/static/css/site.css` is served from `<dir>/css/site.css`.
+    /// - `dir`: The directory to serve, see [`StaticFiles`].
+    pub fn static_dir(&mut self, prefix: &str, dir: &str) -> &mut Self {
+        let files: Arc<StaticFiles> = Arc::new(StaticFiles::new(dir));
+        let pattern: String = format!("{}/*path", prefix.trim_end_matches('/'));
+
+        return self.get(&pattern, move |request, _logger| {
+            let files: Arc<StaticFiles> = Arc::clone(&files);
+            async move {
+                return files
+                    .serve(request.param("path").unwrap_or(""), &request.headers)
+                    .await;
+            }
+        });
+    }
+
+    /// Accepts `PUT`, `PATCH` and `DELETE` requests for files under `prefix`,
+    /// storing them in `uploads`. New files are answered with `201 Created`
+    /// and a `Location` header.
+    ///
+    /// The routes are not authenticated by themselves; protect `prefix` with
+    /// a middleware such as [`RequireSession`](crate::RequireSession).
+    ///
+    /// # Arguments
+    /// - `prefix`: The URL prefix, e.g. `/files`. A request for
+    ///   `/files/docs/a.txt` stores `docs/a.txt` below the upload root.
+    /// - `uploads`: The upload directory, see [`UploadDir`]. Pass an
+    ///   `Arc<UploadDir>` to share it with other handlers.
+    pub fn upload_dir(&mut self, prefix: &str, uploads: impl Into<Arc<UploadDir>>) -> &mut Self {
+        let uploads: Arc<UploadDir> = uploads.into();
+        let pattern: String = format!("{}/*path", prefix.trim_end_matches('/'));
+
+        let put: Arc<UploadDir> = Arc::clone(&uploads);
+        self.put(&pattern, move |request, _logger| {
+            let uploads: Arc<UploadDir> = Arc::clone(&put);
+            async move {
+                let mut response = uploads
+                    .put(request.param("path").unwrap_or(""), &request.body)
+                    .await;
+                if response.code == HttpCode::Created {
+                    let location: &str = match request.uri.split_once('?') {
+                        Some((path, _)) => path,
+                        None => &request.uri,
+                    };
+                    response.set_header("Location", location.to_string());
+                }
+                return response;
+            }
+        });
+
+        let patch: Arc<UploadDir> = Arc::clone(&uploads);
+        self.patch(&pattern, move |request, _logger| {
+            let uploads: Arc<UploadDir> = Arc::clone(&patch);
+            async move {
+                return uploads
+                    .patch(
+                        request.param("path").unwrap_or(""),
+                        &request.body,
+                        &request.headers,
+                    )
+                    .await;
+            }
+        });
+
+        return self.delete(&pattern, move |request, _logger| {
+            let uploads: Arc<UploadDir> = Arc::clone(&uploads);
+            async move {
+                return uploads.delete(request.param("path").unwrap_or("")).await;
+            }
+        });
+    }
+
+    /// Runs a request through the middleware layers and the route table.
+    ///
+    /// Responses to `HEAD` requests never carry a body, whether they come
+    /// from a route, the router itself or a middleware that answered early,
+    /// so the next request on the connection is not mistaken for body bytes.
+    ///
+    /// # Arguments
+    /// - `request`: The HTTP request to process.
+    /// - `logger`: A thread-safe `Logger` instance passed on to middleware and handlers.
+    ///
+    /// # Returns
+    /// The `Response` produced by the pipeline.
+    pub async fn handle(&self, request: Request, logger: Arc<Mutex<Logger>>) -> Response {
+        let head: bool = request.method == HttpMethod::HEAD;
+        let response: Response = Next::new(&self.middlewares, self)
+            .run(request, logger)
+            .await;
+
+        if head {
+            return response.without_body();
+        }
+        return response;
+    }
+
+    /// Finds the route for a request and runs its handler, bypassing middleware.
+    ///
+    /// Some methods are answered without a registered route:
+    /// - `HEAD` runs the `GET` route for the path and omits the body.
+    /// - `OPTIONS` returns `204 No Content` with an `Allow` header listing the
+    ///   methods of the path, or of the whole server for `OPTIONS *`.
+    /// - `TRACE` echoes the request back as `message/http`, leaving out
+    ///   credentials such as cookies.
+    /// - `CONNECT` and extension methods no route is registered for get
+    ///   `501 Not Implemented`.
+    ///
+    /// # Arguments
+    /// - `request`: The HTTP request to dispatch. Its `params` are filled in
+    ///   from the matched route pattern.
+    /// - `logger`: A thread-safe `Logger` instance passed on to the handler.
+    ///
+    /// # Returns
+    /// The handler's `Response`, or a `404`/`405`/`501` response if no route matches.
+    pub async fn dispatch(&self, mut request: Request, logger: Arc<Mutex<Logger>>) -> Response {
+        let mut allowed: Vec<HttpMethod> = Vec::new();
+        let mut get_route: Option<(&Route, HashMap<String, String>)> = None;
+
+        for route in &self.routes {
+            let params: HashMap<String, String> = match route.pattern.matches(&request.path) {
+                Some(p) => p,
+                None => continue,
+            };
+
+            if route.method == request.method {
+                request.params = params;
+                return run_route(route, request, logger).await;
+            }
+
+            if route.method == HttpMethod::GET && get_route.is_none() {
+                get_route = Some((route, params.clone()));
+            }
+            if !allowed.contains(&route.method) {
+                allowed.push(route.method.clone());
+            }
+        }
+
+        match request.method {
+            HttpMethod::HEAD => {
+                if let Some((route, params)) = get_route {
+                    request.params = params;
+                    return run_route(route, request, logger).await.without_body();
+                }
+            }
+            HttpMethod::OPTIONS if request.uri == "*" => {
+                let every: Vec<HttpMethod> = self.routes.iter().map(|r| r.method.clone()).collect();
+                return options_response(&every);
+            }
+            HttpMethod::OPTIONS if !allowed.is_empty() => {
+                return options_response(&allowed);
+            }
+            HttpMethod::TRACE => return trace_response(&request),
+            HttpMethod::CONNECT | HttpMethod::Extension(_)
+                if !self.routes.iter().any(|r| r.method == request.method) =>
+            {
+                println!(
+                    "{} {} {} {}",
+                    ">>".red().bold(),
+                    "Unsupported method for".red(),
+                    request.method.to_string().magenta(),
+                    request.uri.cyan()
+                );
+                return no_store(
+                    Response::new(
+                        Protocol::Http,
+                        HttpCode::NotImplemented,
+                        ContentType::Text,
+                        false,
+                    )
+                    .body(String::from("501: Method not implemented").into()),
+                );
+            }
+            _ => (),
+        }
+
+        if allowed.is_empty() {
+            println!(
+                "{} {} {} {}",
+                ">>".red().bold(),
+                "No matching routes for".red(),
+                request.method.to_string().magenta(),
+                request.uri.cyan()
+            );
+            return no_store(
+                Response::new(Protocol::Http, HttpCode::NotFound, ContentType::Text, false)
+                    .body(String::from("404: Invalid route").into()),
+            );
+        }
+
+        let mut response = Response::new(
+            Protocol::Http,
+            HttpCode::MethodNotAllowed,
+            ContentType::Text,
+            false,
+        )
+        .body(String::from("405: Method not allowed").into());
+        response.add_header(String::from("Allow"), allow_header(&allowed));
+
+        return no_store(response);
+    }
+}
+
+/// Runs a route's handler and applies the route's `Cache-Control` policy.
+async fn run_route(route: &Route, request: Request, logger: Arc<Mutex<Logger>>) -> Response {
+    let mut response = (route.handler)(request, logger).await;
+
+    if !response
+        .headers
+        .iter()
+        .any(|h| h.title.eq_ignore_ascii_case("Cache-Control"))
+    {
+        let policy: &str = match (&route.cache_control, &response.code) {
+            (
+                Some(policy),
+                HttpCode::Ok | HttpCode::Created | HttpCode::PartialContent | HttpCode::NotModified,
+            ) => policy,
+            _ => "no-store",
+        };
+        response.set_header("Cache-Control", policy.to_string());
+    }
+
+    return response;
+}
+
+/// Adds `Cache-Control: no-store` to an error response the router built
+/// itself, matching what [`run_route`] sends for a route's errors.
+fn no_store(mut response: Response) -> Response {
+    response.set_header("Cache-Control", String::from("no-store"));
+    return response;
+}
+
+/// Formats the `Allow` header for a path with routes for `methods`. `HEAD`
+/// is added when `GET` is allowed, and `OPTIONS` and `TRACE` are always
+/// allowed.
+fn allow_header(methods: &[HttpMethod]) -> String {
+    let mut allow: Vec<HttpMethod> = Vec::new();
+    for method in methods {
+        if !allow.contains(method) {
+            allow.push(method.clone());
+        }
+    }
+
+    let mut builtin: Vec<HttpMethod> = vec![HttpMethod::OPTIONS, HttpMethod::TRACE];
+    if allow.contains(&HttpMethod::GET) {
+        builtin.insert(0, HttpMethod::HEAD);
+    }
+    for method in builtin {
+        if !allow.contains(&method) {
+            allow.push(method);
+        }
+    }
+
+    return allow
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+}
+
+/// Builds the reply to an `OPTIONS` request.
+fn options_response(methods: &[HttpMethod]) -> Response {
+    let mut response = Response::new(
+        Protocol::Http,
+        HttpCode::NoContent,
+        ContentType::Text,
+        false,
+    );
+    response.add_header(String::from("Allow"), allow_header(methods));
+    return response;
+}
+
+/// Builds the reply to a `TRACE` request: the request line and headers as the
+/// server received them. Headers carrying credentials are left out so that a
+/// script cannot use `TRACE` to read cookies it has no access to.
+fn trace_response(request: &Request) -> Response {
+    let mut message: String = format!(
+        "{} {} {}\r\n",
+        request.method, request.uri, request.protocol
+    );
+    for header in request.headers.iter() {
+        if ["Cookie", "Authorization", "Proxy-Authorization"]
+            .iter()
+            .any(|h| header.title.eq_ignore_ascii_case(h))
+        {
+            continue;
+        }
+        message.push_str(&format!("{}\r\n", header));
+    }
+    message.push_str("\r\n");
+
+    return Response::new(
+        Protocol::Http,
+        HttpCode::Ok,
+        ContentType::MessageHttp,
+        false,
+    )
+    .body(message.into_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Header;
+
+    fn test_request(method: &str, uri: &str) -> Request {
+        let raw: String = format!("{} {} HTTP/1.1\r\nHost: a\r\n\r\n", method, uri);
+        return Request::new(raw.as_bytes()).unwrap();
+    }
+
+    fn test_router() -> Router {
+        let mut router = Router::new();
+        router
+            .get("/users/:id", |request, _logger| async move {
+                let id: String = request.param("id").unwrap_or("").to_string();
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+                    .body(id.into_bytes())
+            })
+            .delete("/users/:id", |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            });
+        return router;
+    }
+
+    #[test]
+    fn test_pattern_literal_and_params() {
+        let pattern = PathPattern::new("/users/:id");
+        assert_eq!(pattern.matches("/users/7").unwrap()["id"], "7");
+        assert_eq!(pattern.matches("/users/7/").unwrap()["id"], "7");
+        assert!(pattern.matches("/users").is_none());
+        assert!(pattern.matches("/users/7/posts").is_none());
+        assert!(pattern.matches("/accounts/7").is_none());
+
+        assert!(PathPattern::new("/").matches("/").is_some());
+        assert!(PathPattern::new("/").matches("/home").is_none());
+    }
+
+    #[test]
+    fn test_pattern_wildcard() {
+        let pattern = PathPattern::new("/static/*");
+        assert_eq!(
+            pattern.matches("/static/css/site.css").unwrap()["*"],
+            "css/site.css"
+        );
+        assert_eq!(pattern.matches("/static").unwrap()["*"], "");
+        assert!(pattern.matches("/other/site.css").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_router_dispatch_with_params() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let response = test_router()
+            .handle(test_request("GET", "/users/42"), logger)
+            .await;
+
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.body, b"42");
+    }
+
+    #[tokio::test]
+    async fn test_router_cache_control() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let mut router = test_router();
+        router
+            .get("/cached", |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            })
+            .cache_control("public, max-age=60")
+            .get("/own", |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+                    .cache_control("no-cache")
+            });
+
+        let cache_control = |response: &Response| -> String {
+            let values: Vec<&Header> = response
+                .headers
+                .iter()
+                .filter(|h| h.title == "Cache-Control")
+                .collect();
+            assert_eq!(values.len(), 1);
+            return values[0].value.clone();
+        };
+
+        let response = router
+            .handle(test_request("GET", "/cached"), Arc::clone(&logger))
+            .await;
+        assert_eq!(cache_control(&response), "public, max-age=60");
+
+        let response = router
+            .handle(test_request("GET", "/own"), Arc::clone(&logger))
+            .await;
+        assert_eq!(cache_control(&response), "no-cache");
+
+        let response = router
+            .handle(test_request("GET", "/users/1"), Arc::clone(&logger))
+            .await;
+        assert_eq!(cache_control(&response), "no-store");
+
+        // responses that never reach a route are not cached either
+        for (method, uri, code) in [
+            ("GET", "/missing", HttpCode::NotFound),
+            ("POST", "/cached", HttpCode::MethodNotAllowed),
+            ("CONNECT", "example.com:443", HttpCode::NotImplemented),
+        ] {
+            let response = router
+                .handle(test_request(method, uri), Arc::clone(&logger))
+                .await;
+            assert_eq!(response.code, code);
+            assert_eq!(cache_control(&response), "no-store");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_not_found_and_method_not_allowed() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let router = test_router();
+
+        let response = router
+            .handle(test_request("GET", "/missing"), Arc::clone(&logger))
+            .await;
+        assert_eq!(response.code, HttpCode::NotFound);
+
+        let response = router
+            .handle(test_request("POST", "/users/42"), logger)
+            .await;
+        assert_eq!(response.code, HttpCode::MethodNotAllowed);
+        assert!(response
+            .headers
+            .iter()
+            .any(|h| h.title == "Allow" && h.value == "GET, DELETE, HEAD, OPTIONS, TRACE"));
+    }
+
+    #[tokio::test]
+    async fn test_router_head_uses_get_route() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let mut response = test_router()
+            .handle(test_request("HEAD", "/users/42"), logger)
+            .await;
+
+        assert_eq!(response.code, HttpCode::Ok);
+        let output = String::from_utf8(response.to_bytes()).unwrap();
+        assert!(output.contains("Content-Length: 2\r\n"));
+        assert!(output.ends_with("\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_router_head_errors_have_no_body() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let mut router = test_router();
+        router.post("/login", |_request, _logger| async move {
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+        });
+
+        for (uri, code) in [
+            ("/missing", HttpCode::NotFound),
+            ("/login", HttpCode::MethodNotAllowed),
+        ] {
+            let mut response = router
+                .handle(test_request("HEAD", uri), Arc::clone(&logger))
+                .await;
+
+            assert_eq!(response.code, code);
+            let output = String::from_utf8(response.to_bytes()).unwrap();
+            assert!(output.contains("Content-Length: "));
+            assert!(output.ends_with("\r\n\r\n"), "{}", output);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_router_options() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let mut router = test_router();
+        router.post("/login", |_request, _logger| async move {
+            Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+        });
+        let allow = |response: &Response| -> String {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == "Allow")
+                .map(|h| h.value.clone())
+                .unwrap_or_default();
+        };
+
+        let response = router
+            .handle(test_request("OPTIONS", "/users/42"), Arc::clone(&logger))
+            .await;
+        assert_eq!(response.code, HttpCode::NoContent);
+        assert_eq!(allow(&response), "GET, DELETE, HEAD, OPTIONS, TRACE");
+
+        let response = router
+            .handle(test_request("OPTIONS", "*"), Arc::clone(&logger))
+            .await;
+        assert_eq!(allow(&response), "GET, DELETE, POST, HEAD, OPTIONS, TRACE");
+
+        let response = router
+            .handle(test_request("OPTIONS", "/missing"), logger)
+            .await;
+        assert_eq!(response.code, HttpCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_router_trace_hides_credentials() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let request = Request::new(
+            b"TRACE /users/1 HTTP/1.1\r\nHost: a\r\nCookie: session=secret\r\nX-Test: 1\r\n\r\n",
+        )
+        .unwrap();
+        let response = test_router().handle(request, logger).await;
+
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.content_type, ContentType::MessageHttp);
+        assert_eq!(
+            response.body,
+            b"TRACE /users/1 HTTP/1.1\r\nHost: a\r\nX-Test: 1\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_unknown_method_not_implemented() {
+        let logger: Arc<Mutex<Logger>> = Arc::new(Mutex::new(Logger::new("server.log")));
+        let mut router = test_router();
+        router.route(
+            HttpMethod::Extension(String::from("PURGE")),
+            "/users/:id",
+            |_request, _logger| async move {
+                Response::new(Protocol::Http, HttpCode::Ok, ContentType::Text, false)
+            },
+        );
+
+        let response = router
+            .handle(test_request("PROPFIND", "/users/1"), Arc::clone(&logger))
+            .await;
+        assert_eq!(response.code, HttpCode::NotImplemented);
+
+        let response = router
+            .handle(
+                test_request("CONNECT", "example.com:443"),
+                Arc::clone(&logger),
+            )
+            .await;
+        assert_eq!(response.code, HttpCode::NotImplemented);
+
+        let response = router
+            .handle(test_request("PURGE", "/users/1"), logger)
+            .await;
+        assert_eq!(response.code, HttpCode::Ok);
+    }
+}
@@ -11,14 +11,15 @@
 /// let result = handle_request(valid_buffer);
 /// assert!(result.is_ok());
 /// ```
-
 pub mod request_validation {
-    use crate::ErrorType;
+    use crate::{
+        decode_latin1, find_subsequence, percent_decode, ErrorType, HeaderMap, HttpMethod,
+    };
 
     /// Handles the request by parsing and validating it.
     ///
     /// This function performs multiple validation steps on the HTTP request, including:
-    /// - Converting the request to a UTF-8 string
+    /// - Decoding the request head; the body is left as opaque bytes
     /// - Validating the request line format (method, URI, protocol)
     /// - Checking for the correct `Host` header
     /// - Verifying that there are no buffer overflows
@@ -38,14 +39,9 @@ pub mod request_validation {
     /// assert!(result.is_ok());
     /// ```
     pub fn handle_request(buffer: &[u8]) -> Result<(), ErrorType> {
-        // convert into a utf8 string
-        let request = match String::from_utf8(buffer.to_vec()) {
-            Ok(r) => r,
-            Err(_) => {
-                let error: ErrorType = ErrorType::BadRequest(String::from("Invalid UTF-8 request"));
-                return Err(error);
-            }
-        };
+        // only the head is text, the body may hold arbitrary bytes
+        let head_end: usize = find_subsequence(buffer, b"\r\n\r\n").unwrap_or(buffer.len());
+        let request: String = decode_latin1(&buffer[..head_end]);
 
         // Split request into lines
         let mut request = request.lines();
@@ -59,7 +55,10 @@ pub mod request_validation {
         };
 
         request_line_validation(request_line)?;
-        validate_headers(request.clone())?;
+
+        // HTTP/1.0 predates the Host header, so only HTTP/1.1 and later require it
+        let require_host: bool = !request_line.ends_with("HTTP/1.0");
+        validate_headers(request.clone(), require_host)?;
         check_overflow(buffer)?;
 
         return Ok(());
@@ -67,28 +66,24 @@ pub mod request_validation {
 
     /// Validates the headers of the HTTP request.
     ///
-    /// This function checks that the `Host` header exists exactly once in the request headers.
+    /// This function checks that the `Host` header exists exactly once in the request headers,
+    /// or at most once if `require_host` is not set.
     ///
     /// # Parameters
     /// - `lines`: An iterator over the header lines.
+    /// - `require_host`: Whether a missing `Host` header is an error.
     ///
     /// # Returns
     /// - `Ok(())` if the headers are valid.
     /// - `Err(ErrorType)` if there are issues with the headers (e.g., multiple or missing `Host` header).
-    fn validate_headers<'a>(lines: impl Iterator<Item = &'a str>) -> Result<(), ErrorType> {
-        let mut host_count: u8 = 0;
-
-        for line in lines {
-            if line.is_empty() {
-                break;
-            }
-
-            if line.starts_with("Host:") {
-                host_count += 1;
-            }
-        }
-
-        if host_count != 1 {
+    fn validate_headers<'a>(
+        lines: impl Iterator<Item = &'a str>,
+        require_host: bool,
+    ) -> Result<(), ErrorType> {
+        let headers: HeaderMap = HeaderMap::from_lines(lines);
+        let host_count: usize = headers.get_all("Host").len();
+
+        if host_count > 1 || (host_count == 0 && require_host) {
             let error: ErrorType =
                 ErrorType::BadRequest(format!("Invalid host count: {}", host_count));
             return Err(error);
@@ -101,7 +96,11 @@ pub mod request_validation {
     ///
     /// This function checks the URI for various potential issues such as:
     /// - Invalid characters (e.g., control characters or forbidden characters like `..`)
-    /// - Suspicious patterns like directory traversal or malicious keywords.
+    /// - Malformed percent-encoding, or escapes that decode to forbidden characters
+    /// - Directory traversal, whether written plainly or percent-encoded.
+    ///
+    /// Where a path may lead on the file system is checked again when it is
+    /// resolved, by the static file and upload handlers.
     ///
     /// # Parameters
     /// - `uri`: The URI part of the request.
@@ -125,22 +124,21 @@ pub mod request_validation {
             return Err(error);
         }
 
-        let forbidden_characters = ['<', '>', '{', '}', '|', '\\', '^', '`', '[', ']', ' ', '%'];
+        // decode escapes so that encoded traversal or control characters are caught too
+        let decoded: String = match percent_decode(uri) {
+            Ok(d) => d,
+            Err(_) => {
+                let error: ErrorType = ErrorType::BadRequest(format!("Invalid uri: {}", uri));
+                return Err(error);
+            }
+        };
 
-        if uri.chars().any(|c| forbidden_characters.contains(&c)) {
+        if decoded.contains("..") || decoded.chars().any(|c| c.is_control()) {
             let error: ErrorType = ErrorType::BadRequest(format!("Invalid uri: {}", uri));
             return Err(error);
         }
 
-        let forbidden_words: [&str; 13] = [
-            "rm", "sh", "bash", "python", "perl", "exec", "delete", "drop", "cmd", "script", ";--",
-            "' OR '", "&&",
-        ];
-
-        if forbidden_words.iter().any(|w| uri.contains(w)) {
-            let error: ErrorType = ErrorType::BadRequest(format!("Invalid uri: {}", uri));
-            return Err(error);
-        }
+        let forbidden_characters = ['<', '>', '{', '}', '|', '\\', '^', '`', '[', ']', ' '];
 
         if uri.chars().any(|c| forbidden_characters.contains(&c)) {
             let error: ErrorType = ErrorType::BadRequest(format!("Invalid uri: {}", uri));
@@ -154,8 +152,9 @@ pub mod request_validation {
     ///
     /// This function checks the following:
     /// - The request line contains exactly three parts: method, URI, and protocol.
-    /// - The method is one of `GET`, `POST`, `PUT`, or `DELETE`.
-    /// - The URI starts with a `/` and is otherwise well-formed.
+    /// - The method is a valid token, parsed the same way as `HttpMethod::new`.
+    /// - The URI starts with a `/` and is otherwise well-formed. `OPTIONS`
+    ///   may also target `*`, and `CONNECT` targets a `host:port` authority.
     /// - The protocol is one of `HTTP/1.1`, `HTTP/1.0`, `HTTP/2`, or `HTTP/3`.
     ///
     /// # Parameters
@@ -165,6 +164,11 @@ pub mod request_validation {
     /// - `Ok(())` if the request line is valid.
     /// - `Err(ErrorType)` if the request line is invalid.
     fn request_line_validation(request_line: &str) -> Result<(), ErrorType> {
+        if !request_line.is_ascii() {
+            let error: ErrorType = ErrorType::BadRequest(String::from("Invalid request line"));
+            return Err(error);
+        }
+
         let request_line_parts: Vec<&str> = request_line.split_whitespace().collect();
 
         // validate method, URI and  protocol
@@ -179,20 +183,51 @@ pub mod request_validation {
             request_line_parts[2],
         );
 
-        if !["GET", "POST", "PUT", "DELETE"].contains(&method) {
-            let error: ErrorType = ErrorType::BadRequest(String::from("Invalid request method"));
-            return Err(error);
+        match (HttpMethod::new(method)?, uri) {
+            (HttpMethod::OPTIONS, "*") => (),
+            (HttpMethod::CONNECT, authority) => validate_authority(authority)?,
+            (_, uri) => {
+                if !uri.starts_with('/') {
+                    let error: ErrorType =
+                        ErrorType::BadRequest(String::from("Invalid request uri"));
+                    return Err(error);
+                }
+
+                validate_uri(uri)?;
+            }
         }
 
-        if !uri.starts_with('/') {
-            let error: ErrorType = ErrorType::BadRequest(String::from("Invalid request uri"));
+        if !["HTTP/1.1", "HTTP/1.0", "HTTP/2", "HTTP/3"].contains(&protocol) {
+            let error: ErrorType = ErrorType::BadRequest(String::from("Invalid request protocol"));
             return Err(error);
         }
 
-        validate_uri(uri)?;
+        return Ok(());
+    }
 
-        if !["HTTP/1.1", "HTTP/1.0", "HTTP/2", "HTTP/3"].contains(&protocol) {
-            let error: ErrorType = ErrorType::BadRequest(String::from("Invalid request protocol"));
+    /// Validates the `host:port` target of a `CONNECT` request.
+    ///
+    /// # Parameters
+    /// - `authority`: The request target, e.g. `example.com:443`.
+    ///
+    /// # Returns
+    /// - `Ok(())` if the target is a host name followed by a numeric port.
+    /// - `Err(ErrorType)` otherwise.
+    fn validate_authority(authority: &str) -> Result<(), ErrorType> {
+        let valid: bool = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                !host.is_empty()
+                    && host
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-.[]:".contains(c))
+                    && port.parse::<u16>().is_ok()
+            }
+            None => false,
+        };
+
+        if !valid {
+            let error: ErrorType =
+                ErrorType::BadRequest(format!("Invalid authority: {}", authority));
             return Err(error);
         }
 
@@ -201,16 +236,17 @@ pub mod request_validation {
 
     /// Checks for overflow in the request data.
     ///
-    /// This function verifies that the request data ends with the correct line break sequence (`\r\n\r\n`).
+    /// This function verifies that the request head is terminated by an empty line (`\r\n\r\n`).
+    /// Anything after it is the body and is not inspected.
     ///
     /// # Parameters
     /// - `data`: The raw request data.
     ///
     /// # Returns
     /// - `Ok(())` if the request data is correctly terminated.
-    /// - `Err(ErrorType)` if the data does not end with `\r\n\r\n`.
+    /// - `Err(ErrorType)` if the data does not contain `\r\n\r\n`.
     fn check_overflow(data: &[u8]) -> Result<(), ErrorType> {
-        if &data[data.len() - 4..] != b"\r\n\r\n" {
+        if find_subsequence(data, b"\r\n\r\n").is_none() {
             let error: ErrorType = ErrorType::BadRequest(String::from("Request overflow"));
             return Err(error);
         }
@@ -231,25 +267,34 @@ pub mod request_validation {
         }
 
         #[test]
-        fn test_handle_request_invalid_utf8() {
-            let invalid_utf8: &[u8] = &[0x80, 0x81, 0x82, 0x83];
+        fn test_handle_request_non_ascii_request_line() {
+            let non_ascii: &[u8] = b"GET /\x80\x81 HTTP/1.1\r\nHost: example.com\r\n\r\n";
 
-            let result: ErrorType = match handle_request(invalid_utf8) {
+            let result: ErrorType = match handle_request(non_ascii) {
                 Ok(_) => ErrorType::InternalServerError(String::from("Wrong result")),
                 Err(e) => e,
             };
-            // Should return a BadRequest error due to invalid UTF-8
+            // Should return a BadRequest error since the request line must be ASCII
             assert_eq!(
                 result,
-                ErrorType::BadRequest(String::from("Invalid UTF-8 request"))
+                ErrorType::BadRequest(String::from("Invalid request line"))
             );
         }
 
+        #[test]
+        fn test_handle_request_binary_body() {
+            let mut buffer: Vec<u8> =
+                b"POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Length: 6\r\n\r\n".to_vec();
+            buffer.extend_from_slice(&[0x00, 0xFF, 0x80, b'\n', 0xC3, 0x28]);
+
+            assert!(handle_request(&buffer).is_ok());
+        }
+
         #[test]
         fn test_validate_headers_missing_host() {
-            let headers = vec!["GET / HTTP/1.1", "User-Agent: test"];
+            let headers = ["GET / HTTP/1.1", "User-Agent: test"];
 
-            let result: ErrorType = match validate_headers(headers.iter().copied()) {
+            let result: ErrorType = match validate_headers(headers.iter().copied(), true) {
                 Ok(_) => ErrorType::InternalServerError(String::from("Wrong result")),
                 Err(e) => e,
             };
@@ -261,11 +306,18 @@ pub mod request_validation {
             );
         }
 
+        #[test]
+        fn test_handle_request_http10_without_headers() {
+            assert!(handle_request(b"GET / HTTP/1.0\r\n\r\n").is_ok());
+            assert!(handle_request(b"GET / HTTP/1.1\r\n\r\n").is_err());
+            assert!(handle_request(b"GET / HTTP/1.0\r\nHost: a\r\nHost: b\r\n\r\n").is_err());
+        }
+
         #[test]
         fn test_validate_headers_multiple_host() {
-            let headers = vec!["Host: example.com", "Host: another.com", "User-Agent: test"];
+            let headers = ["Host: example.com", "Host: another.com", "User-Agent: test"];
 
-            let result: ErrorType = match validate_headers(headers.iter().copied()) {
+            let result: ErrorType = match validate_headers(headers.iter().copied(), true) {
                 Ok(_) => ErrorType::InternalServerError(String::from("Wrong result")),
                 Err(e) => e,
             };
@@ -286,6 +338,28 @@ pub mod request_validation {
             assert!(validate_uri(valid_uri).is_ok());
         }
 
+        #[test]
+        fn test_validate_uri_ordinary_paths_and_queries() {
+            for uri in [
+                "/static/form.css",
+                "/static/fresh.js",
+                "/static/script.js",
+                "/files/push.txt",
+                "/files/delete-me.txt",
+                "/search?q=fresh",
+                "/search?a=1&&b=2",
+                "/search?cmd=drop",
+            ] {
+                assert!(validate_uri(uri).is_ok(), "{}", uri);
+                assert!(
+                    handle_request(format!("GET {} HTTP/1.1\r\nHost: a\r\n\r\n", uri).as_bytes())
+                        .is_ok(),
+                    "{}",
+                    uri
+                );
+            }
+        }
+
         #[test]
         fn test_validate_uri_invalid_path_traversal() {
             let invalid_uri = "/../../etc/passwd";
@@ -318,6 +392,14 @@ pub mod request_validation {
             );
         }
 
+        #[test]
+        fn test_validate_uri_percent_encoding() {
+            assert!(validate_uri("/home?name=J%C3%BCrgen").is_ok());
+            assert!(validate_uri("/files/%2e%2e/secret").is_err());
+            assert!(validate_uri("/home%0a").is_err());
+            assert!(validate_uri("/home%G1").is_err());
+        }
+
         #[test]
         fn test_request_line_validation_valid() {
             let valid_line = "GET /index.html HTTP/1.1";
@@ -328,7 +410,7 @@ pub mod request_validation {
 
         #[test]
         fn test_request_line_validation_invalid_method() {
-            let invalid_line = "INVALID /index.html HTTP/1.1";
+            let invalid_line = "IN\"VALID /index.html HTTP/1.1";
 
             let result: ErrorType = match request_line_validation(invalid_line) {
                 Ok(_) => ErrorType::InternalServerError(String::from("Wrong result")),
@@ -341,6 +423,25 @@ pub mod request_validation {
             );
         }
 
+        #[test]
+        fn test_request_line_validation_methods() {
+            for line in [
+                "HEAD / HTTP/1.1",
+                "PATCH /files HTTP/1.1",
+                "OPTIONS * HTTP/1.1",
+                "OPTIONS /files HTTP/1.1",
+                "TRACE / HTTP/1.1",
+                "CONNECT example.com:443 HTTP/1.1",
+                "PROPFIND /files HTTP/1.1",
+            ] {
+                assert!(request_line_validation(line).is_ok(), "{}", line);
+            }
+
+            assert!(request_line_validation("GET * HTTP/1.1").is_err());
+            assert!(request_line_validation("CONNECT /files HTTP/1.1").is_err());
+            assert!(request_line_validation("G(T / HTTP/1.1").is_err());
+        }
+
         #[test]
         fn test_request_line_validation_invalid_protocol() {
             let invalid_line = "GET /index.html HTTP/2.0";
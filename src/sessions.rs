@@ -0,0 +1,701 @@
+use crate::{Cookie, CookieJar, CookieKey, ErrorType, SameSite, StoreFuture};
+use chrono::Utc;
+use log::error;
+use rand::distributions::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::sync::Mutex;
+
+/// Name of the cookie holding the session ID.
+pub const SESSION_COOKIE: &str = "session";
+
+/// Length of a session ID. 32 alphanumeric characters hold about 190 bits of
+/// randomness.
+pub const SESSION_ID_LENGTH: usize = 32;
+
+/// How often `last_seen` is written back for a session in use. Idle expiry
+/// is accurate to this interval, and a busy session does not cost a store
+/// write on every request.
+const TOUCH_INTERVAL: i64 = 60;
+
+/// A logged in session, stored on the server and identified by the random
+/// ID in the client's `session` cookie.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    /// The session ID handed to the client. In a [`SessionStore`] this is
+    /// the [`session_key`] of the ID instead.
+    pub id: String,
+    /// The user the session belongs to.
+    pub username: String,
+    /// When the session was issued, in seconds since the Unix epoch.
+    pub created_at: i64,
+    /// When the session was last used, in seconds since the Unix epoch.
+    pub last_seen: i64,
+}
+
+/// Storage for [`Session`]s. Expiry is decided by the [`SessionManager`];
+/// a store only keeps and removes records.
+///
+/// The manager stores every session under the [`session_key`] of its ID and
+/// passes only keys to the store, so the store is an index of keys and a
+/// copy of it holds no ID a client could present.
+pub trait SessionStore: Send + Sync {
+    /// Stores a new session.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the session is stored.
+    /// - `Err(ErrorType::NotFound)` if a store that tracks users does not know the session's user.
+    fn insert(&self, session: Session) -> StoreFuture<'_, ()>;
+
+    /// Looks up the session stored under exactly the given key.
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Session>>;
+
+    /// Sets the `last_seen` time of a session, if it still exists.
+    fn touch<'a>(&'a self, id: &'a str, last_seen: i64) -> StoreFuture<'a, ()>;
+
+    /// Removes a session, returning whether it existed.
+    fn remove<'a>(&'a self, id: &'a str) -> StoreFuture<'a, bool>;
+
+    /// Removes every session of a user, returning how many there were.
+    fn remove_user<'a>(&'a self, username: &'a str) -> StoreFuture<'a, usize>;
+
+    /// Removes every session created before `created_before` or last seen
+    /// before `seen_before`, returning how many there were.
+    fn purge(&self, created_before: i64, seen_before: i64) -> StoreFuture<'_, usize>;
+}
+
+/// Keeps sessions in memory. They are lost when the server restarts, which
+/// logs every user out.
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+impl MemorySessionStore {
+    pub fn new() -> Self {
+        return MemorySessionStore::default();
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn insert(&self, session: Session) -> StoreFuture<'_, ()> {
+        return Box::pin(async move {
+            self.sessions
+                .lock()
+                .await
+                .insert(session.id.clone(), session);
+            return Ok(());
+        });
+    }
+
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Session>> {
+        return Box::pin(async move {
+            return Ok(self.sessions.lock().await.get(id).cloned());
+        });
+    }
+
+    fn touch<'a>(&'a self, id: &'a str, last_seen: i64) -> StoreFuture<'a, ()> {
+        return Box::pin(async move {
+            if let Some(session) = self.sessions.lock().await.get_mut(id) {
+                session.last_seen = last_seen;
+            }
+            return Ok(());
+        });
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> StoreFuture<'a, bool> {
+        return Box::pin(async move {
+            return Ok(self.sessions.lock().await.remove(id).is_some());
+        });
+    }
+
+    fn remove_user<'a>(&'a self, username: &'a str) -> StoreFuture<'a, usize> {
+        return Box::pin(async move {
+            let mut sessions = self.sessions.lock().await;
+            let count: usize = sessions.len();
+            sessions.retain(|_, s| s.username != username);
+            return Ok(count - sessions.len());
+        });
+    }
+
+    fn purge(&self, created_before: i64, seen_before: i64) -> StoreFuture<'_, usize> {
+        return Box::pin(async move {
+            let mut sessions = self.sessions.lock().await;
+            let count: usize = sessions.len();
+            sessions.retain(|_, s| s.created_at >= created_before && s.last_seen >= seen_before);
+            return Ok(count - sessions.len());
+        });
+    }
+}
+
+/// Default time after which a session expires however much it is used (24 hours).
+pub const DEFAULT_ABSOLUTE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Default time after which an unused session expires (1 hour).
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Default time between two purges of expired sessions (5 minutes).
+pub const DEFAULT_PURGE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Lifetimes and cookie attributes applied by a [`SessionManager`].
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// How long a session lasts after it is issued.
+    pub absolute_timeout: Duration,
+    /// How long a session lasts after it was last used.
+    pub idle_timeout: Duration,
+    /// The least time between two purges of expired sessions.
+    pub purge_interval: Duration,
+    /// Marks the session cookie `Secure`. Enable it when clients reach the
+    /// server over HTTPS, e.g. through a TLS terminating proxy.
+    pub secure_cookie: bool,
+    /// The `SameSite` attribute of the session cookie.
+    pub same_site: SameSite,
+    /// Signs the session cookie when set, so only IDs issued by a server
+    /// holding the key are looked up.
+    pub cookie_key: Option<CookieKey>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        return SessionConfig {
+            absolute_timeout: DEFAULT_ABSOLUTE_TIMEOUT,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            purge_interval: DEFAULT_PURGE_INTERVAL,
+            secure_cookie: false,
+            same_site: SameSite::Lax,
+            cookie_key: None,
+        };
+    }
+}
+
+/// Issues, checks and revokes sessions kept in a [`SessionStore`].
+///
+/// A session expires once it is older than the absolute timeout or has not
+/// been used for the idle timeout. Expired sessions are never accepted, and
+/// are removed from the store by a purge that runs at most once per purge
+/// interval, triggered by the manager's own calls, or on demand with
+/// [`SessionManager::purge_expired`].
+///
+/// # Example
+/// ```rust
+/// use rust_server::{MemorySessionStore, SessionConfig, SessionManager};
+/// use std::sync::Arc;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let sessions = SessionManager::new(Arc::new(MemorySessionStore::new()), SessionConfig::default());
+/// let session = sessions.create("hayley").await.unwrap();
+/// assert!(sessions.validate(&session.id).await.unwrap().is_some());
+///
+/// sessions.revoke(&session.id).await.unwrap();
+/// assert!(sessions.validate(&session.id).await.unwrap().is_none());
+/// # }
+/// ```
+pub struct SessionManager {
+    store: Arc<dyn SessionStore>,
+    config: SessionConfig,
+    /// The time from which the next purge may run.
+    next_purge: AtomicI64,
+}
+
+impl SessionManager {
+    pub fn new(store: Arc<dyn SessionStore>, config: SessionConfig) -> Self {
+        return SessionManager {
+            store,
+            config,
+            next_purge: AtomicI64::new(0),
+        };
+    }
+
+    /// Issues a new session for `username`.
+    ///
+    /// # Returns
+    /// - `Ok(Session)` with the fresh session.
+    /// - `Err(ErrorType)` if the store fails.
+    pub async fn create(&self, username: &str) -> Result<Session, ErrorType> {
+        self.purge_if_due().await;
+        return self.issue(username, Utc::now().timestamp()).await;
+    }
+
+    /// Stores a session under a new random ID, returning it with the ID.
+    async fn issue(&self, username: &str, created_at: i64) -> Result<Session, ErrorType> {
+        let id: String = generate_session_id();
+        let session = Session {
+            id: session_key(&id),
+            username: username.to_string(),
+            created_at,
+            last_seen: Utc::now().timestamp(),
+        };
+        self.store.insert(session.clone()).await?;
+
+        return Ok(Session { id, ..session });
+    }
+
+    /// Checks a session ID and records that the session is in use.
+    ///
+    /// Only IDs of exactly [`SESSION_ID_LENGTH`] characters are looked up,
+    /// by their [`session_key`], and the stored key must equal the key of the
+    /// ID, compared in constant time. Empty, truncated or extended IDs never
+    /// match.
+    ///
+    /// # Returns
+    /// - `Ok(Some(Session))` with the user the session belongs to, if it
+    ///   exists and has not expired.
+    /// - `Ok(None)` if it does not exist or has expired. An expired session
+    ///   is removed.
+    /// - `Err(ErrorType)` if the store fails.
+    pub async fn validate(&self, id: &str) -> Result<Option<Session>, ErrorType> {
+        self.purge_if_due().await;
+
+        if id.len() != SESSION_ID_LENGTH {
+            return Ok(None);
+        }
+
+        let key: String = session_key(id);
+        let mut session: Session = match self.store.get(&key).await? {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        // do not trust the store's lookup to have compared the whole key
+        if !bool::from(session.id.as_bytes().ct_eq(key.as_bytes())) {
+            return Ok(None);
+        }
+
+        let now: i64 = Utc::now().timestamp();
+        if self.is_expired(&session, now) {
+            self.store.remove(&key).await?;
+            return Ok(None);
+        }
+
+        if now - session.last_seen >= TOUCH_INTERVAL {
+            self.store.touch(&key, now).await?;
+            session.last_seen = now;
+        }
+
+        return Ok(Some(Session {
+            id: id.to_string(),
+            ..session
+        }));
+    }
+
+    /// Reads the session cookie from a request's cookies and validates it,
+    /// see [`SessionManager::session_id`] and [`SessionManager::validate`].
+    pub async fn authenticate(&self, cookies: &CookieJar) -> Result<Option<Session>, ErrorType> {
+        return match self.session_id(cookies) {
+            Some(id) => self.validate(&id).await,
+            None => Ok(None),
+        };
+    }
+
+    /// Replaces a session with one under a new ID, for the same user and
+    /// expiring at the same time. Call it whenever the privileges of a
+    /// session change, so an ID captured before the change is worthless.
+    ///
+    /// # Returns
+    /// - `Ok(Some(Session))` with the new session.
+    /// - `Ok(None)` if the session does not exist or has expired.
+    /// - `Err(ErrorType)` if the store fails.
+    pub async fn rotate(&self, id: &str) -> Result<Option<Session>, ErrorType> {
+        let old: Session = match self.validate(id).await? {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+
+        let session: Session = self.issue(&old.username, old.created_at).await?;
+        self.store.remove(&session_key(id)).await?;
+
+        return Ok(Some(session));
+    }
+
+    /// Ends a session, e.g. on logout. Unknown IDs are ignored.
+    pub async fn revoke(&self, id: &str) -> Result<(), ErrorType> {
+        self.store.remove(&session_key(id)).await?;
+        return Ok(());
+    }
+
+    /// Ends every session of a user, returning how many were ended.
+    pub async fn revoke_user(&self, username: &str) -> Result<usize, ErrorType> {
+        return self.store.remove_user(username).await;
+    }
+
+    /// Removes every expired session from the store, returning how many
+    /// were removed.
+    pub async fn purge_expired(&self) -> Result<usize, ErrorType> {
+        let now: i64 = Utc::now().timestamp();
+        return self
+            .store
+            .purge(
+                now - self.config.absolute_timeout.as_secs() as i64,
+                now - self.config.idle_timeout.as_secs() as i64,
+            )
+            .await;
+    }
+
+    /// Builds the `session` cookie handing `session` to the client. It is
+    /// `HttpOnly`, valid for the whole site and kept by the browser until
+    /// the session's absolute timeout.
+    pub fn cookie(&self, session: &Session) -> Cookie {
+        let age: u64 = (Utc::now().timestamp() - session.created_at).max(0) as u64;
+        let max_age: Duration = self
+            .config
+            .absolute_timeout
+            .saturating_sub(Duration::from_secs(age));
+
+        let cookie: Cookie = match &self.config.cookie_key {
+            Some(key) => Cookie::signed(SESSION_COOKIE, &session.id, key),
+            None => Cookie::new(SESSION_COOKIE, &session.id),
+        };
+        return cookie
+            .path("/")
+            .max_age(max_age)
+            .secure(self.config.secure_cookie)
+            .http_only(true)
+            .same_site(self.config.same_site);
+    }
+
+    /// Builds the cookie that deletes the `session` cookie from the client.
+    pub fn removal_cookie(&self) -> Cookie {
+        return Cookie::removal(SESSION_COOKIE)
+            .path("/")
+            .secure(self.config.secure_cookie)
+            .http_only(true)
+            .same_site(self.config.same_site);
+    }
+
+    /// Reads the session ID from a request's cookies.
+    ///
+    /// # Returns
+    /// - `Some(String)` with the ID of the `session` cookie.
+    /// - `None` if there is no such cookie, or, when a cookie key is set, its
+    ///   signature does not match.
+    pub fn session_id(&self, cookies: &CookieJar) -> Option<String> {
+        return match &self.config.cookie_key {
+            Some(key) => cookies.get_signed(SESSION_COOKIE, key),
+            None => cookies.get(SESSION_COOKIE).map(|id| id.to_string()),
+        };
+    }
+
+    fn is_expired(&self, session: &Session, now: i64) -> bool {
+        return now - session.created_at > self.config.absolute_timeout.as_secs() as i64
+            || now - session.last_seen > self.config.idle_timeout.as_secs() as i64;
+    }
+
+    /// Purges expired sessions if the purge interval has passed since the
+    /// last purge. Failures are logged, since callers do not depend on them.
+    async fn purge_if_due(&self) {
+        let now: i64 = Utc::now().timestamp();
+        let next: i64 = self.next_purge.load(Ordering::Relaxed);
+        if now < next {
+            return;
+        }
+
+        // only the caller that moves the deadline runs the purge
+        let following: i64 = now + self.config.purge_interval.as_secs() as i64;
+        if self
+            .next_purge
+            .compare_exchange(next, following, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        if let Err(e) = self.purge_expired().await {
+            error!("Failed to purge expired sessions: {}", e);
+        }
+    }
+}
+
+/// Derives the key a session is stored under from its ID: the hex-encoded
+/// SHA-256 digest of the ID.
+pub fn session_key(id: &str) -> String {
+    return Sha256::digest(id.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+}
+
+/// Generates a random session ID from the operating system's secure random
+/// number generator.
+fn generate_session_id() -> String {
+    return (0..SESSION_ID_LENGTH)
+        .map(|_| OsRng.sample(Alphanumeric) as char)
+        .collect();
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn session(id: &str, username: &str, created_at: i64, last_seen: i64) -> Session {
+        return Session {
+            id: id.to_string(),
+            username: username.to_string(),
+            created_at,
+            last_seen,
+        };
+    }
+
+    /// Runs the same checks against any store implementation. The store must
+    /// accept sessions for the users `hayley` and `sloth`.
+    pub(crate) async fn check_session_store(sessions: &dyn SessionStore) {
+        sessions
+            .insert(session("a", "hayley", 100, 100))
+            .await
+            .unwrap();
+        sessions
+            .insert(session("b", "hayley", 200, 300))
+            .await
+            .unwrap();
+        sessions
+            .insert(session("c", "sloth", 300, 300))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            sessions.get("a").await.unwrap(),
+            Some(session("a", "hayley", 100, 100))
+        );
+        assert_eq!(sessions.get("").await.unwrap(), None);
+        assert_eq!(sessions.get("ab").await.unwrap(), None);
+
+        sessions.touch("a", 150).await.unwrap();
+        sessions.touch("missing", 150).await.unwrap();
+        assert_eq!(sessions.get("a").await.unwrap().unwrap().last_seen, 150);
+
+        assert!(sessions.remove("c").await.unwrap());
+        assert!(!sessions.remove("c").await.unwrap());
+
+        sessions
+            .insert(session("c", "sloth", 300, 300))
+            .await
+            .unwrap();
+        sessions
+            .insert(session("d", "sloth", 400, 400))
+            .await
+            .unwrap();
+
+        // "a" is too old and "b" has been idle too long
+        assert_eq!(sessions.purge(150, 250).await.unwrap(), 1);
+        assert!(sessions.get("a").await.unwrap().is_none());
+        assert_eq!(sessions.purge(200, 301).await.unwrap(), 2);
+        assert!(sessions.get("b").await.unwrap().is_none());
+        assert!(sessions.get("c").await.unwrap().is_none());
+
+        assert_eq!(sessions.remove_user("sloth").await.unwrap(), 1);
+        assert_eq!(sessions.remove_user("sloth").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_memory_session_store() {
+        check_session_store(&MemorySessionStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sessions_are_fresh_and_revocable() {
+        let sessions = SessionManager::new(
+            Arc::new(MemorySessionStore::new()),
+            SessionConfig::default(),
+        );
+
+        let first: Session = sessions.create("hayley").await.unwrap();
+        let second: Session = sessions.create("hayley").await.unwrap();
+        assert_ne!(first.id, second.id);
+        assert_eq!(first.id.len(), SESSION_ID_LENGTH);
+        assert_eq!(
+            sessions
+                .validate(&first.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .username,
+            "hayley"
+        );
+        assert!(sessions.validate("").await.unwrap().is_none());
+        assert!(sessions.validate(&first.id[..8]).await.unwrap().is_none());
+
+        let rotated: Session = sessions.rotate(&first.id).await.unwrap().unwrap();
+        assert_ne!(rotated.id, first.id);
+        assert_eq!(rotated.created_at, first.created_at);
+        assert!(sessions.validate(&first.id).await.unwrap().is_none());
+        assert!(sessions.validate(&rotated.id).await.unwrap().is_some());
+        assert!(sessions.rotate(&first.id).await.unwrap().is_none());
+
+        sessions.revoke(&rotated.id).await.unwrap();
+        assert!(sessions.validate(&rotated.id).await.unwrap().is_none());
+
+        sessions.create("sloth").await.unwrap();
+        assert_eq!(sessions.revoke_user("hayley").await.unwrap(), 1);
+        assert!(sessions.validate(&second.id).await.unwrap().is_none());
+    }
+
+    /// A session ID made of `c` repeated.
+    fn id(c: char) -> String {
+        return c.to_string().repeat(SESSION_ID_LENGTH);
+    }
+
+    #[tokio::test]
+    async fn test_sessions_expire() {
+        let store = Arc::new(MemorySessionStore::new());
+        let config = SessionConfig {
+            absolute_timeout: Duration::from_secs(1000),
+            idle_timeout: Duration::from_secs(100),
+            purge_interval: Duration::from_secs(1000),
+            ..SessionConfig::default()
+        };
+        let sessions = SessionManager::new(store.clone(), config);
+        let now: i64 = Utc::now().timestamp();
+        let (fresh, idle, old) = (id('f'), id('i'), id('o'));
+
+        for (id, created_at, last_seen) in [
+            (&fresh, now - 990, now - 90),
+            (&idle, now - 500, now - 101),
+            (&old, now - 1001, now),
+        ] {
+            let record = session(&session_key(id), "a", created_at, last_seen);
+            store.insert(record).await.unwrap();
+        }
+
+        // the first call also purges, removing the expired sessions
+        let valid: Session = sessions.validate(&fresh).await.unwrap().unwrap();
+        assert!(valid.last_seen >= now);
+        assert!(store.get(&session_key(&idle)).await.unwrap().is_none());
+        assert!(store.get(&session_key(&old)).await.unwrap().is_none());
+
+        // expired sessions are refused even before the next purge
+        let record = session(&session_key(&idle), "a", now - 500, now - 101);
+        store.insert(record).await.unwrap();
+        assert!(sessions.validate(&idle).await.unwrap().is_none());
+        assert!(store.get(&session_key(&idle)).await.unwrap().is_none());
+
+        let record = session(&session_key(&old), "a", now - 1001, now);
+        store.insert(record).await.unwrap();
+        assert_eq!(sessions.purge_expired().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_validate_rejects_partial_ids() {
+        let store = Arc::new(MemorySessionStore::new());
+        let sessions = SessionManager::new(store.clone(), SessionConfig::default());
+        let session: Session = sessions.create("hayley").await.unwrap();
+
+        // the store only holds the key of the ID
+        assert!(store.get(&session.id).await.unwrap().is_none());
+        assert!(store
+            .get(&session_key(&session.id))
+            .await
+            .unwrap()
+            .is_some());
+
+        let mut changed: String = session.id.clone();
+        changed.replace_range(..1, if changed.starts_with('a') { "b" } else { "a" });
+        for id in [
+            String::new(),
+            session.id[..1].to_string(),
+            session.id[..SESSION_ID_LENGTH - 1].to_string(),
+            session.id[1..].to_string(),
+            format!("{}a", session.id),
+            format!(" {}", &session.id[1..]),
+            session.id.to_uppercase(),
+            session.id.to_lowercase(),
+            changed,
+            session_key(&session.id),
+        ] {
+            if id == session.id {
+                continue;
+            }
+            assert!(sessions.validate(&id).await.unwrap().is_none(), "{:?}", id);
+        }
+
+        let valid: Session = sessions.validate(&session.id).await.unwrap().unwrap();
+        assert_eq!(valid.username, "hayley");
+        assert_eq!(valid.id, session.id);
+    }
+
+    /// A broken store that answers every lookup with the same session.
+    struct AnySessionStore(Session);
+
+    impl SessionStore for AnySessionStore {
+        fn insert(&self, _session: Session) -> StoreFuture<'_, ()> {
+            return Box::pin(async { Ok(()) });
+        }
+
+        fn get<'a>(&'a self, _id: &'a str) -> StoreFuture<'a, Option<Session>> {
+            return Box::pin(async { Ok(Some(self.0.clone())) });
+        }
+
+        fn touch<'a>(&'a self, _id: &'a str, _last_seen: i64) -> StoreFuture<'a, ()> {
+            return Box::pin(async { Ok(()) });
+        }
+
+        fn remove<'a>(&'a self, _id: &'a str) -> StoreFuture<'a, bool> {
+            return Box::pin(async { Ok(false) });
+        }
+
+        fn remove_user<'a>(&'a self, _username: &'a str) -> StoreFuture<'a, usize> {
+            return Box::pin(async { Ok(0) });
+        }
+
+        fn purge(&self, _created_before: i64, _seen_before: i64) -> StoreFuture<'_, usize> {
+            return Box::pin(async { Ok(0) });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_compares_the_stored_key() {
+        let now: i64 = Utc::now().timestamp();
+        let record = session(&session_key(&id('a')), "hayley", now, now);
+        let sessions =
+            SessionManager::new(Arc::new(AnySessionStore(record)), SessionConfig::default());
+
+        assert!(sessions.validate(&id('a')).await.unwrap().is_some());
+        assert!(sessions.validate(&id('b')).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_cookies() {
+        let sessions = SessionManager::new(
+            Arc::new(MemorySessionStore::new()),
+            SessionConfig::default(),
+        );
+        let session: Session = sessions.create("hayley").await.unwrap();
+        assert_eq!(
+            sessions.cookie(&session).to_header().unwrap(),
+            format!(
+                "session={}; Path=/; Max-Age=86400; HttpOnly; SameSite=Lax",
+                session.id
+            )
+        );
+        assert_eq!(
+            sessions.removal_cookie().to_header().unwrap(),
+            "session=; Path=/; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT; HttpOnly; SameSite=Lax"
+        );
+
+        let cookies = CookieJar::parse(&format!("theme=dark; session={}", session.id));
+        assert_eq!(sessions.session_id(&cookies), Some(session.id.clone()));
+
+        let config = SessionConfig {
+            secure_cookie: true,
+            same_site: SameSite::Strict,
+            cookie_key: Some(CookieKey::generate()),
+            ..SessionConfig::default()
+        };
+        let signed = SessionManager::new(Arc::new(MemorySessionStore::new()), config);
+        let header: String = signed.cookie(&session).to_header().unwrap();
+        assert!(header.starts_with(&format!("session={}.", session.id)));
+        assert!(header.ends_with("; Secure; HttpOnly; SameSite=Strict"));
+
+        // unsigned and forged IDs are not even looked up
+        assert_eq!(signed.session_id(&cookies), None);
+        assert_eq!(
+            signed.session_id(&CookieJar::parse(&header)),
+            Some(session.id.clone())
+        );
+    }
+}
@@ -0,0 +1,516 @@
+use crate::{ErrorType, Session, SessionStore, StoreFuture, User, UserStore};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row, TransactionBehavior};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task;
+
+/// Schema migrations, applied in order. The database's `user_version` pragma
+/// records how many of them have run, so each runs exactly once.
+const MIGRATIONS: [&str; 3] = [
+    "
+    CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        username TEXT NOT NULL UNIQUE,
+        password_hash TEXT NOT NULL,
+        created_at INTEGER NOT NULL DEFAULT (unixepoch())
+    );
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at INTEGER NOT NULL DEFAULT (unixepoch())
+    );
+    CREATE INDEX sessions_user_id ON sessions(user_id);
+    ",
+    // sessions gain an idle timeout; existing ones never expired, so they are dropped
+    "
+    DROP TABLE sessions;
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+        created_at INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    CREATE INDEX sessions_user_id ON sessions(user_id);
+    CREATE INDEX sessions_created_at ON sessions(created_at);
+    CREATE INDEX sessions_last_seen ON sessions(last_seen);
+    ",
+    // sessions are stored under the key derived from their ID, so ones stored by ID never match
+    "
+    DELETE FROM sessions;
+    ",
+];
+
+/// How long a write waits for another connection to release the database.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Keeps users and their sessions in an SQLite database.
+///
+/// It is also a [`SessionStore`], so sessions can share the database and are
+/// removed along with their user.
+///
+/// Usernames and session IDs are looked up through indexes instead of a scan,
+/// every change runs in a transaction, and the `UNIQUE` constraint on the
+/// username means concurrent signups for the same name cannot both succeed,
+/// even from several processes sharing the database. The schema is created
+/// and migrated when the store is opened.
+///
+/// Queries run on Tokio's blocking thread pool.
+///
+/// # Example
+/// ```rust
+/// use rust_server::{Session, SessionStore, SqliteUserStore, User, UserStore};
+/// # #[tokio::main]
+/// # async fn main() {
+/// let store = SqliteUserStore::in_memory().unwrap();
+/// let user = User {
+///     username: String::from("hayley"),
+///     password_hash: String::from("$argon2id$..."),
+/// };
+/// store.create(user.clone()).await.unwrap();
+/// assert_eq!(UserStore::get(&store, "hayley").await.unwrap(), Some(user));
+///
+/// let session = Session {
+///     id: String::from("abc"),
+///     username: String::from("hayley"),
+///     created_at: 0,
+///     last_seen: 0,
+/// };
+/// store.insert(session.clone()).await.unwrap();
+/// assert_eq!(SessionStore::get(&store, "abc").await.unwrap(), Some(session));
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SqliteUserStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteUserStore {
+    /// Opens the database at `path`, creating it and its directory if they
+    /// do not exist, and brings its schema up to date.
+    ///
+    /// # Returns
+    /// - `Ok(SqliteUserStore)` once the database is ready.
+    /// - `Err(ErrorType::InternalServerError)` if it cannot be opened or migrated.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ErrorType> {
+        let path: &Path = path.as_ref();
+
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                if let Err(e) = std::fs::create_dir_all(parent) {
+                    return Err(ErrorType::InternalServerError(format!(
+                        "Failed to create {}: {}",
+                        parent.display(),
+                        e
+                    )));
+                }
+            }
+        }
+
+        let connection: Connection = Connection::open(path).map_err(database_error)?;
+        // write-ahead logging lets lookups proceed while a signup commits
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(database_error)?;
+        return SqliteUserStore::from_connection(connection);
+    }
+
+    /// Creates a store in a private in-memory database, for tests.
+    pub fn in_memory() -> Result<Self, ErrorType> {
+        return SqliteUserStore::from_connection(
+            Connection::open_in_memory().map_err(database_error)?,
+        );
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Self, ErrorType> {
+        connection
+            .pragma_update(None, "foreign_keys", true)
+            .map_err(database_error)?;
+        connection
+            .busy_timeout(BUSY_TIMEOUT)
+            .map_err(database_error)?;
+        migrate(&mut connection)?;
+
+        return Ok(SqliteUserStore {
+            connection: Arc::new(Mutex::new(connection)),
+        });
+    }
+
+    /// Runs `query` with the connection on the blocking thread pool.
+    fn run<T, F>(&self, query: F) -> StoreFuture<'static, T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, ErrorType> + Send + 'static,
+    {
+        let connection: Arc<Mutex<Connection>> = Arc::clone(&self.connection);
+
+        return Box::pin(async move {
+            let result = task::spawn_blocking(move || {
+                let mut connection = match connection.lock() {
+                    Ok(c) => c,
+                    Err(_) => {
+                        return Err(ErrorType::InternalServerError(String::from(
+                            "User database lock poisoned",
+                        )));
+                    }
+                };
+                return query(&mut connection);
+            })
+            .await;
+
+            return match result {
+                Ok(r) => r,
+                Err(e) => Err(ErrorType::InternalServerError(format!(
+                    "User database query failed: {}",
+                    e
+                ))),
+            };
+        });
+    }
+}
+
+impl UserStore for SqliteUserStore {
+    fn create(&self, user: User) -> StoreFuture<'_, ()> {
+        return self.run(move |connection| {
+            let transaction = connection
+                .transaction_with_behavior(TransactionBehavior::Immediate)
+                .map_err(database_error)?;
+
+            let inserted = transaction.execute(
+                "INSERT INTO users (username, password_hash) VALUES (?1, ?2)",
+                params![user.username, user.password_hash],
+            );
+            match inserted {
+                Ok(_) => (),
+                Err(rusqlite::Error::SqliteFailure(e, _))
+                    if e.code == ErrorCode::ConstraintViolation =>
+                {
+                    return Err(ErrorType::Conflict(format!(
+                        "User {} already exists",
+                        user.username
+                    )));
+                }
+                Err(e) => return Err(database_error(e)),
+            }
+
+            return transaction.commit().map_err(database_error);
+        });
+    }
+
+    fn get<'a>(&'a self, username: &'a str) -> StoreFuture<'a, Option<User>> {
+        let username: String = username.to_string();
+        return self.run(move |connection| {
+            return connection
+                .query_row(
+                    "SELECT username, password_hash FROM users WHERE username = ?1",
+                    params![username],
+                    read_user,
+                )
+                .optional()
+                .map_err(database_error);
+        });
+    }
+
+    fn update(&self, user: User) -> StoreFuture<'_, ()> {
+        return self.run(move |connection| {
+            let updated: usize = connection
+                .execute(
+                    "UPDATE users SET password_hash = ?2 WHERE username = ?1",
+                    params![user.username, user.password_hash],
+                )
+                .map_err(database_error)?;
+
+            if updated == 0 {
+                return Err(ErrorType::NotFound(format!(
+                    "User {} does not exist",
+                    user.username
+                )));
+            }
+            return Ok(());
+        });
+    }
+
+    fn delete<'a>(&'a self, username: &'a str) -> StoreFuture<'a, ()> {
+        let username: String = username.to_string();
+        return self.run(move |connection| {
+            // sessions are removed with the user through ON DELETE CASCADE
+            let deleted: usize = connection
+                .execute("DELETE FROM users WHERE username = ?1", params![username])
+                .map_err(database_error)?;
+
+            if deleted == 0 {
+                return Err(ErrorType::NotFound(format!(
+                    "User {} does not exist",
+                    username
+                )));
+            }
+            return Ok(());
+        });
+    }
+}
+
+impl SessionStore for SqliteUserStore {
+    fn insert(&self, session: Session) -> StoreFuture<'_, ()> {
+        return self.run(move |connection| {
+            let inserted: usize = connection
+                .execute(
+                    "INSERT INTO sessions (id, user_id, created_at, last_seen)
+                     SELECT ?1, id, ?3, ?4 FROM users WHERE username = ?2",
+                    params![
+                        session.id,
+                        session.username,
+                        session.created_at,
+                        session.last_seen
+                    ],
+                )
+                .map_err(database_error)?;
+
+            if inserted == 0 {
+                return Err(ErrorType::NotFound(format!(
+                    "User {} does not exist",
+                    session.username
+                )));
+            }
+            return Ok(());
+        });
+    }
+
+    fn get<'a>(&'a self, id: &'a str) -> StoreFuture<'a, Option<Session>> {
+        let id: String = id.to_string();
+        return self.run(move |connection| {
+            return connection
+                .query_row(
+                    "SELECT sessions.id, users.username, sessions.created_at, sessions.last_seen
+                     FROM sessions JOIN users ON users.id = sessions.user_id
+                     WHERE sessions.id = ?1",
+                    params![id],
+                    read_session,
+                )
+                .optional()
+                .map_err(database_error);
+        });
+    }
+
+    fn touch<'a>(&'a self, id: &'a str, last_seen: i64) -> StoreFuture<'a, ()> {
+        let id: String = id.to_string();
+        return self.run(move |connection| {
+            connection
+                .execute(
+                    "UPDATE sessions SET last_seen = ?2 WHERE id = ?1",
+                    params![id, last_seen],
+                )
+                .map_err(database_error)?;
+            return Ok(());
+        });
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> StoreFuture<'a, bool> {
+        let id: String = id.to_string();
+        return self.run(move |connection| {
+            let removed: usize = connection
+                .execute("DELETE FROM sessions WHERE id = ?1", params![id])
+                .map_err(database_error)?;
+            return Ok(removed > 0);
+        });
+    }
+
+    fn remove_user<'a>(&'a self, username: &'a str) -> StoreFuture<'a, usize> {
+        let username: String = username.to_string();
+        return self.run(move |connection| {
+            return connection
+                .execute(
+                    "DELETE FROM sessions
+                     WHERE user_id = (SELECT id FROM users WHERE username = ?1)",
+                    params![username],
+                )
+                .map_err(database_error);
+        });
+    }
+
+    fn purge(&self, created_before: i64, seen_before: i64) -> StoreFuture<'_, usize> {
+        return self.run(move |connection| {
+            return connection
+                .execute(
+                    "DELETE FROM sessions WHERE created_at < ?1 OR last_seen < ?2",
+                    params![created_before, seen_before],
+                )
+                .map_err(database_error);
+        });
+    }
+}
+
+/// Applies the migrations the database has not run yet, each in its own
+/// transaction.
+///
+/// # Returns
+/// - `Ok(())` once the schema is current.
+/// - `Err(ErrorType::InternalServerError)` if a migration fails, leaving the
+///   database at the last migration that succeeded.
+fn migrate(connection: &mut Connection) -> Result<(), ErrorType> {
+    let version: usize = connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(database_error)?;
+
+    if version > MIGRATIONS.len() {
+        return Err(ErrorType::InternalServerError(format!(
+            "User database schema version {} is newer than this server supports",
+            version
+        )));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction().map_err(database_error)?;
+        transaction
+            .execute_batch(migration)
+            .map_err(database_error)?;
+        transaction
+            .pragma_update(None, "user_version", index + 1)
+            .map_err(database_error)?;
+        transaction.commit().map_err(database_error)?;
+    }
+
+    return Ok(());
+}
+
+fn read_user(row: &Row) -> rusqlite::Result<User> {
+    return Ok(User {
+        username: row.get(0)?,
+        password_hash: row.get(1)?,
+    });
+}
+
+fn read_session(row: &Row) -> rusqlite::Result<Session> {
+    return Ok(Session {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        created_at: row.get(2)?,
+        last_seen: row.get(3)?,
+    });
+}
+
+fn database_error(error: rusqlite::Error) -> ErrorType {
+    return ErrorType::InternalServerError(format!("User database error: {}", error));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sessions::tests::check_session_store;
+    use crate::users::tests::check_store;
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        check_store(&SqliteUserStore::in_memory().unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_session_store() {
+        let store = SqliteUserStore::in_memory().unwrap();
+        for username in ["hayley", "sloth"] {
+            let user = User {
+                username: username.to_string(),
+                password_hash: String::from("hash"),
+            };
+            store.create(user).await.unwrap();
+        }
+        check_session_store(&store).await;
+
+        // sessions belong to a known user and go away with it
+        let session = Session {
+            id: String::from("e"),
+            username: String::from("nobody"),
+            created_at: 0,
+            last_seen: 0,
+        };
+        assert_eq!(
+            store.insert(session.clone()).await,
+            Err(ErrorType::NotFound(String::new()))
+        );
+        store
+            .insert(Session {
+                username: String::from("hayley"),
+                ..session
+            })
+            .await
+            .unwrap();
+        store.delete("hayley").await.unwrap();
+        assert_eq!(SessionStore::get(&store, "e").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_schema_and_reopen() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("rust_server_sqlite_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path: PathBuf = dir.join("users.db");
+
+        let users = SqliteUserStore::open(&path).unwrap();
+        let user = User {
+            username: String::from("hayley"),
+            password_hash: String::from("hash"),
+        };
+        users.create(user.clone()).await.unwrap();
+        drop(users);
+
+        // reopening runs no migration twice and keeps the data
+        let users = SqliteUserStore::open(&path).unwrap();
+        assert_eq!(UserStore::get(&users, "hayley").await.unwrap(), Some(user));
+
+        let connection = users.connection.lock().unwrap();
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+
+        // lookups by username and session, and purges, use indexes
+        for query in [
+            "SELECT * FROM users WHERE username = 'a'",
+            "SELECT * FROM sessions WHERE id = 'a'",
+            "SELECT * FROM sessions WHERE user_id = 1",
+            "SELECT * FROM sessions WHERE last_seen < 1",
+        ] {
+            let plan: String = connection
+                .query_row(&format!("EXPLAIN QUERY PLAN {}", query), [], |row| {
+                    row.get(3)
+                })
+                .unwrap();
+            assert!(plan.contains("USING INDEX"), "{}", plan);
+        }
+        drop(connection);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_signups_create_one_user() {
+        let users = SqliteUserStore::in_memory().unwrap();
+
+        let mut tasks = Vec::new();
+        for i in 0..16 {
+            let users = users.clone();
+            tasks.push(tokio::spawn(async move {
+                let user = User {
+                    username: String::from("hayley"),
+                    password_hash: format!("hash{}", i),
+                };
+                return users.create(user).await;
+            }));
+        }
+
+        let mut created: usize = 0;
+        for task in tasks {
+            match task.await.unwrap() {
+                Ok(_) => created += 1,
+                Err(e) => assert_eq!(e, ErrorType::Conflict(String::new())),
+            }
+        }
+        assert_eq!(created, 1);
+
+        let connection = users.connection.lock().unwrap();
+        let count: usize = connection
+            .query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+}
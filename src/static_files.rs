@@ -0,0 +1,795 @@
+use crate::{
+    http_date, negotiate_encoding_among, parse_http_date, range_request, BodyReader, ByteRange,
+    ContentType, Encoding, ErrorType, HeaderMap, HttpCode, Protocol, RangeRequest, Response,
+};
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use log::error;
+use rand::Rng;
+use std::ffi::OsString;
+use std::fs::Metadata;
+use std::io::{Cursor, ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Files, or ranges of files, larger than this are streamed from disk while
+/// the response is written instead of being read into memory first.
+pub const STREAM_THRESHOLD: u64 = 256 * 1024;
+
+/// Serves the files below a directory on disk.
+///
+/// Request paths are resolved relative to the root directory and then
+/// canonicalized, so `..` segments and symbolic links can never reach a file
+/// outside of it. Directories are served through their index file.
+///
+/// Every file response carries an `ETag` and a `Last-Modified` header, and
+/// requests whose `If-None-Match` or `If-Modified-Since` header shows the
+/// client already holds the current version get `304 Not Modified`. `Range`
+/// requests are answered with `206 Partial Content`, reading only the
+/// requested slices of the file.
+///
+/// When a file has precompressed copies next to it, such as `app.js.br` or
+/// `app.js.gz`, the copy matching the negotiated `Accept-Encoding` is served
+/// directly with the matching `Content-Encoding`. Copies older than the file
+/// itself are ignored.
+///
+/// # Example
+/// ```rust
+/// use rust_server::StaticFiles;
+/// let files = StaticFiles::new("static").index("home.html");
+/// ```
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    index: String,
+    precompressed: bool,
+}
+
+impl StaticFiles {
+    /// Creates a file server rooted at `root`, using `index.html` as the
+    /// index file for directories.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        return StaticFiles {
+            root: root.into(),
+            index: String::from("index.html"),
+            precompressed: true,
+        };
+    }
+
+    /// Sets the file served when a directory is requested.
+    pub fn index(mut self, index: &str) -> Self {
+        self.index = index.to_string();
+        return self;
+    }
+
+    /// Enables or disables serving precompressed `.br`, `.gz` and `.zst`
+    /// copies of files. Enabled by default.
+    pub fn precompressed(mut self, enabled: bool) -> Self {
+        self.precompressed = enabled;
+        return self;
+    }
+
+    /// Resolves a request path to a file below the root directory.
+    ///
+    /// # Arguments
+    /// - `relative`: The percent-decoded path relative to the root, e.g. `css/site.css`.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` with the canonical path of the file to serve.
+    /// - `Err(ErrorType::NotFound)` if the file does not exist or the path
+    ///   would leave the root directory.
+    /// - `Err(ErrorType::ReadError)` if the file system cannot be queried.
+    pub async fn resolve(&self, relative: &str) -> Result<PathBuf, ErrorType> {
+        let mut path: PathBuf = self.root.clone();
+        for component in Path::new(relative).components() {
+            match component {
+                Component::Normal(part) => path.push(part),
+                Component::CurDir => (),
+                _ => {
+                    return Err(ErrorType::NotFound(format!(
+                        "Path {} leaves the static root",
+                        relative
+                    )))
+                }
+            }
+        }
+
+        let root: PathBuf = canonicalize(&self.root).await?;
+        let mut path: PathBuf = canonicalize(&path).await?;
+
+        if fs::metadata(&path)
+            .await
+            .map(|m| m.is_dir())
+            .unwrap_or(false)
+        {
+            path = canonicalize(&path.join(&self.index)).await?;
+        }
+
+        // symbolic links are followed by canonicalize, so check the final target
+        if !path.starts_with(&root) {
+            return Err(ErrorType::NotFound(format!(
+                "Path {} leaves the static root",
+                relative
+            )));
+        }
+
+        return match fs::metadata(&path).await {
+            Ok(metadata) if metadata.is_file() => Ok(path),
+            Ok(_) => Err(ErrorType::NotFound(format!("{} is not a file", relative))),
+            Err(e) => Err(ErrorType::ReadError(format!(
+                "Failed to read metadata of {}: {}",
+                relative, e
+            ))),
+        };
+    }
+
+    /// Builds the response for a request path.
+    ///
+    /// # Arguments
+    /// - `relative`: The percent-decoded path relative to the root.
+    /// - `headers`: The request headers, checked for conditional and range
+    ///   request headers.
+    ///
+    /// # Returns
+    /// A `Response` with the file as its body and a `Content-Type` detected
+    /// from its extension, `304 Not Modified` if the client's cached copy is
+    /// current, `206 Partial Content` or `416 Range Not Satisfiable` for range
+    /// requests, `404 Not Found` if there is no such file, or
+    /// `500 Internal Server Error` if it cannot be read.
+    pub async fn serve(&self, relative: &str, headers: &HeaderMap) -> Response {
+        let path: PathBuf = match self.resolve(relative).await {
+            Ok(p) => p,
+            Err(e) => return error_response(relative, e),
+        };
+
+        let mut metadata: Metadata = match fs::metadata(&path).await {
+            Ok(m) => m,
+            Err(e) => {
+                return error_response(
+                    relative,
+                    ErrorType::ReadError(format!("Failed to read {}: {}", relative, e)),
+                )
+            }
+        };
+        let content_type: ContentType = ContentType::from_path(&path);
+
+        // switch to a precompressed copy if the client accepts one
+        let mut path: PathBuf = path;
+        let mut precompressed: Option<Encoding> = None;
+        let mut varies: bool = false;
+        if self.precompressed && content_type.is_compressible() {
+            let sidecars: Vec<(Encoding, PathBuf, Metadata)> =
+                self.sidecars(&path, &metadata).await;
+            let available: Vec<Encoding> = sidecars.iter().map(|(e, _, _)| *e).collect();
+            varies = !sidecars.is_empty();
+
+            if let Some(encoding) = negotiate_encoding_among(headers, &available) {
+                if let Some((_, p, m)) = sidecars.into_iter().find(|(e, _, _)| *e == encoding) {
+                    path = p;
+                    metadata = m;
+                    precompressed = Some(encoding);
+                }
+            }
+        }
+
+        let etag: String = etag(&metadata);
+        let last_modified: Option<DateTime<Utc>> = metadata.modified().ok().map(DateTime::from);
+        let length: u64 = metadata.len();
+
+        let result: Result<Response, ErrorType> = if is_not_modified(headers, &etag, last_modified)
+        {
+            Ok(Response::new(
+                Protocol::Http,
+                HttpCode::NotModified,
+                content_type,
+                false,
+            ))
+        } else {
+            match range_request(headers, length, &etag, last_modified) {
+                RangeRequest::Full => {
+                    let response = Response::new(Protocol::Http, HttpCode::Ok, content_type, false);
+                    file_body(response, &path, 0, length).await
+                }
+                RangeRequest::Partial(ranges) => {
+                    partial_response(&path, content_type, &ranges, length).await
+                }
+                RangeRequest::Unsatisfiable => {
+                    let mut response = Response::new(
+                        Protocol::Http,
+                        HttpCode::RangeNotSatisfiable,
+                        ContentType::Text,
+                        false,
+                    )
+                    .body(String::from("416: Range not satisfiable").into());
+                    response
+                        .add_header(String::from("Content-Range"), format!("bytes */{}", length));
+                    Ok(response)
+                }
+            }
+        };
+
+        let mut response: Response = match result {
+            Ok(r) => r,
+            Err(e) => return error_response(relative, e),
+        };
+
+        if let Some(encoding) = precompressed {
+            if response.code != HttpCode::RangeNotSatisfiable {
+                response = response.encoded(encoding);
+            }
+        }
+        if varies {
+            response.add_vary("Accept-Encoding");
+        }
+
+        response.add_header(String::from("Accept-Ranges"), String::from("bytes"));
+        response.add_header(String::from("ETag"), etag);
+        if let Some(time) = last_modified {
+            response.add_header(String::from("Last-Modified"), http_date(time));
+        }
+        return response;
+    }
+
+    /// Finds the precompressed copies of `path`, e.g. `app.js.br` next to
+    /// `app.js`, in the order of [`Encoding::SUPPORTED`].
+    ///
+    /// # Arguments
+    /// - `path`: The canonical path of the file.
+    /// - `metadata`: The file's metadata. Copies older than the file are skipped.
+    ///
+    /// # Returns
+    /// The encoding, canonical path and metadata of each usable copy.
+    async fn sidecars(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> Vec<(Encoding, PathBuf, Metadata)> {
+        let root: PathBuf = match canonicalize(&self.root).await {
+            Ok(r) => r,
+            Err(_) => return Vec::new(),
+        };
+        let modified: Option<SystemTime> = metadata.modified().ok();
+
+        let mut sidecars: Vec<(Encoding, PathBuf, Metadata)> = Vec::new();
+        for encoding in Encoding::SUPPORTED {
+            let extension: &str = match encoding.file_extension() {
+                Some(e) => e,
+                None => continue,
+            };
+
+            let mut name: OsString = path.as_os_str().to_os_string();
+            name.push(".");
+            name.push(extension);
+
+            // the copy may be a symbolic link, so check its target stays in the root
+            let sidecar: PathBuf = match canonicalize(Path::new(&name)).await {
+                Ok(p) if p.starts_with(&root) => p,
+                _ => continue,
+            };
+            let sidecar_metadata: Metadata = match fs::metadata(&sidecar).await {
+                Ok(m) if m.is_file() => m,
+                _ => continue,
+            };
+
+            let stale: bool = match (sidecar_metadata.modified().ok(), modified) {
+                (Some(sidecar_time), Some(time)) => sidecar_time < time,
+                _ => false,
+            };
+            if !stale {
+                sidecars.push((encoding, sidecar, sidecar_metadata));
+            }
+        }
+
+        return sidecars;
+    }
+}
+
+/// Builds a `206 Partial Content` response holding `ranges` of the file.
+///
+/// A single range is sent as is with a `Content-Range` header. Several ranges
+/// are sent as a `multipart/byteranges` body with one part per range.
+///
+/// # Returns
+/// - `Ok(Response)` with the requested slices.
+/// - `Err(ErrorType::ReadError)` if the file cannot be read.
+async fn partial_response(
+    path: &Path,
+    content_type: ContentType,
+    ranges: &[ByteRange],
+    length: u64,
+) -> Result<Response, ErrorType> {
+    let mut response = Response::new(
+        Protocol::Http,
+        HttpCode::PartialContent,
+        content_type,
+        false,
+    );
+
+    if let [range] = ranges {
+        response.add_header(String::from("Content-Range"), range.content_range(length));
+        return file_body(response, path, range.start, range.size()).await;
+    }
+
+    let boundary: String = rand::thread_rng()
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+
+    let mut body: BodyReader = Box::new(tokio::io::empty());
+    let mut body_length: u64 = 0;
+    for range in ranges {
+        let head: Vec<u8> = format!(
+            "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+            boundary,
+            content_type,
+            range.content_range(length)
+        )
+        .into_bytes();
+        let part: File = open_at(path, range.start).await?;
+
+        body_length += head.len() as u64 + range.size() + 2;
+        body = Box::new(
+            body.chain(Cursor::new(head))
+                .chain(part.take(range.size()))
+                .chain(&b"\r\n"[..]),
+        );
+    }
+    let tail: Vec<u8> = format!("--{}--\r\n", boundary).into_bytes();
+    body_length += tail.len() as u64;
+    body = Box::new(body.chain(Cursor::new(tail)));
+
+    response.set_header(
+        "Content-Type",
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+    return reader_body(response, body, body_length).await;
+}
+
+/// Attaches `length` bytes of the file starting at `offset` as the body of
+/// `response`, reading them into memory if they fit within
+/// [`STREAM_THRESHOLD`] and streaming them from disk otherwise.
+async fn file_body(
+    response: Response,
+    path: &Path,
+    offset: u64,
+    length: u64,
+) -> Result<Response, ErrorType> {
+    let file: File = open_at(path, offset).await?;
+    if length > STREAM_THRESHOLD {
+        return Ok(response.file(file, offset, length));
+    }
+    return reader_body(response, Box::new(file.take(length)), length).await;
+}
+
+/// Attaches the `length` bytes produced by `reader` as the body of
+/// `response`, reading them into memory if they fit within
+/// [`STREAM_THRESHOLD`] and streaming them otherwise.
+async fn reader_body(
+    response: Response,
+    mut reader: BodyReader,
+    length: u64,
+) -> Result<Response, ErrorType> {
+    if length > STREAM_THRESHOLD {
+        return Ok(response.reader(reader, length));
+    }
+
+    let mut body: Vec<u8> = Vec::with_capacity(length as usize);
+    return match reader.read_to_end(&mut body).await {
+        Ok(n) if n as u64 == length => Ok(response.body(body)),
+        Ok(n) => Err(ErrorType::ReadError(format!(
+            "Expected {} bytes but read {}",
+            length, n
+        ))),
+        Err(e) => Err(ErrorType::ReadError(format!("Failed to read file: {}", e))),
+    };
+}
+
+/// Opens a file positioned at byte `offset`.
+async fn open_at(path: &Path, offset: u64) -> Result<File, ErrorType> {
+    let mut file: File = match File::open(path).await {
+        Ok(f) => f,
+        Err(e) => {
+            return Err(ErrorType::ReadError(format!(
+                "Failed to open {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+
+    return match file.seek(SeekFrom::Start(offset)).await {
+        Ok(_) => Ok(file),
+        Err(e) => Err(ErrorType::ReadError(format!(
+            "Failed to seek to byte {} of {}: {}",
+            offset,
+            path.display(),
+            e
+        ))),
+    };
+}
+
+/// Builds a strong entity tag from a file's size and modification time.
+fn etag(metadata: &Metadata) -> String {
+    let modified: u128 = match metadata.modified() {
+        Ok(time) => time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0),
+        Err(_) => 0,
+    };
+    return format!("\"{:x}-{:x}\"", metadata.len(), modified);
+}
+
+/// Evaluates the conditional request headers against a file's validators.
+///
+/// `If-None-Match` takes precedence; `If-Modified-Since` is only consulted
+/// when it is absent, as required by RFC 9110.
+///
+/// # Returns
+/// `true` if the client's cached copy is current and `304 Not Modified`
+/// should be sent instead of the file.
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if headers.contains("If-None-Match") {
+        // weak comparison: W/"x" matches "x"
+        return headers
+            .get_list("If-None-Match")
+            .iter()
+            .any(|tag| *tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    return match (
+        headers.get("If-Modified-Since").and_then(parse_http_date),
+        last_modified,
+    ) {
+        // HTTP dates have one second resolution
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    };
+}
+
+/// Canonicalizes a path, mapping a missing file to `ErrorType::NotFound`.
+async fn canonicalize(path: &Path) -> Result<PathBuf, ErrorType> {
+    return match fs::canonicalize(path).await {
+        Ok(p) => Ok(p),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(ErrorType::NotFound(format!(
+            "File {} does not exist",
+            path.display()
+        ))),
+        Err(e) => Err(ErrorType::ReadError(format!(
+            "Failed to resolve {}: {}",
+            path.display(),
+            e
+        ))),
+    };
+}
+
+/// Maps a failure to serve `relative` to a `404` or `500` response.
+fn error_response(relative: &str, error: ErrorType) -> Response {
+    println!(
+        "{} {} {}",
+        ">>".red().bold(),
+        "Unable to serve static file".red(),
+        relative.cyan()
+    );
+
+    return match error {
+        ErrorType::NotFound(_) => {
+            Response::new(Protocol::Http, HttpCode::NotFound, ContentType::Text, false)
+                .body(String::from("404: File not found").into())
+        }
+        e => {
+            error!("Failed to serve static file: {}", e);
+            Response::new(
+                Protocol::Http,
+                HttpCode::InternalServerError,
+                ContentType::Text,
+                false,
+            )
+            .body(String::from("Problem occured when reading file.").into())
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a fresh directory tree for a test:
+    /// `<name>/public/{site.css, docs/index.html}` and `<name>/secret.txt`.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!(
+            "rust_server_static_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("public/docs")).unwrap();
+        std::fs::write(dir.join("public/site.css"), "body {}").unwrap();
+        std::fs::write(dir.join("public/docs/index.html"), "<p>docs</p>").unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        return dir;
+    }
+
+    fn content_type(response: &Response) -> &str {
+        return &response
+            .headers
+            .iter()
+            .find(|h| h.title == "Content-Type")
+            .unwrap()
+            .value;
+    }
+
+    #[tokio::test]
+    async fn test_serves_file_with_mime_type() {
+        let dir = test_dir("mime");
+        let files = StaticFiles::new(dir.join("public"));
+
+        let response = files.serve("site.css", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.body, b"body {}");
+        assert_eq!(content_type(&response), "text/css");
+    }
+
+    #[tokio::test]
+    async fn test_serves_index_for_directory() {
+        let dir = test_dir("index");
+        let files = StaticFiles::new(dir.join("public"));
+
+        let response = files.serve("docs", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.body, b"<p>docs</p>");
+        assert_eq!(content_type(&response), "text/html");
+
+        // the root itself has no index file
+        assert_eq!(
+            files.serve("", &HeaderMap::new()).await.code,
+            HttpCode::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_and_escaping_paths_are_not_found() {
+        let dir = test_dir("escape");
+        let files = StaticFiles::new(dir.join("public"));
+
+        assert_eq!(
+            files.serve("missing.js", &HeaderMap::new()).await.code,
+            HttpCode::NotFound
+        );
+        assert_eq!(
+            files.serve("../secret.txt", &HeaderMap::new()).await.code,
+            HttpCode::NotFound
+        );
+        assert_eq!(
+            files
+                .serve("docs/../../secret.txt", &HeaderMap::new())
+                .await
+                .code,
+            HttpCode::NotFound
+        );
+        assert_eq!(
+            files
+                .serve(dir.join("secret.txt").to_str().unwrap(), &HeaderMap::new())
+                .await
+                .code,
+            HttpCode::NotFound
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let dir = test_dir("conditional");
+        let files = StaticFiles::new(dir.join("public"));
+
+        let response = files.serve("site.css", &HeaderMap::new()).await;
+        let header = |name: &str| -> String {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == name)
+                .unwrap()
+                .value
+                .clone();
+        };
+        let etag: String = header("ETag");
+        let last_modified: String = header("Last-Modified");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("If-None-Match", &format!("\"other\", W/{}", etag));
+        let response = files.serve("site.css", &headers).await;
+        assert_eq!(response.code, HttpCode::NotModified);
+        assert!(response.body.is_empty());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("If-Modified-Since", &last_modified);
+        assert_eq!(
+            files.serve("site.css", &headers).await.code,
+            HttpCode::NotModified
+        );
+
+        // If-None-Match takes precedence over If-Modified-Since
+        headers.insert("If-None-Match", "\"other\"");
+        assert_eq!(files.serve("site.css", &headers).await.code, HttpCode::Ok);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("If-Modified-Since", "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(files.serve("site.css", &headers).await.code, HttpCode::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_range_requests() {
+        let dir = test_dir("range");
+        std::fs::write(dir.join("public/data.txt"), "0123456789").unwrap();
+        let files = StaticFiles::new(dir.join("public"));
+        let header = |response: &Response, name: &str| -> String {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == name)
+                .unwrap()
+                .value
+                .clone();
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Range", "bytes=2-4");
+        let response = files.serve("data.txt", &headers).await;
+        assert_eq!(response.code, HttpCode::PartialContent);
+        assert_eq!(response.body, b"234");
+        assert_eq!(header(&response, "Content-Range"), "bytes 2-4/10");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Range", "bytes=0-0,-2");
+        let response = files.serve("data.txt", &headers).await;
+        assert_eq!(response.code, HttpCode::PartialContent);
+        let content_type: String = header(&response, "Content-Type");
+        let boundary: &str = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let expected: String = format!(
+            "--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-0/10\r\n\r\n0\r\n\
+             --{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\r\n\
+             --{b}--\r\n",
+            b = boundary
+        );
+        assert_eq!(String::from_utf8(response.body.clone()).unwrap(), expected);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Range", "bytes=10-");
+        let response = files.serve("data.txt", &headers).await;
+        assert_eq!(response.code, HttpCode::RangeNotSatisfiable);
+        assert_eq!(header(&response, "Content-Range"), "bytes */10");
+
+        // a stale If-Range falls back to the full file
+        headers.insert("If-Range", "\"stale\"");
+        let response = files.serve("data.txt", &headers).await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert_eq!(response.body, b"0123456789");
+    }
+
+    #[tokio::test]
+    async fn test_large_files_are_streamed() {
+        let dir = test_dir("stream");
+        let data: Vec<u8> = (0..STREAM_THRESHOLD + 1).map(|i| (i % 256) as u8).collect();
+        std::fs::write(dir.join("public/large.bin"), &data).unwrap();
+        let files = StaticFiles::new(dir.join("public"));
+
+        let mut response = files.serve("large.bin", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::Ok);
+        assert!(response.body.is_empty());
+        assert_eq!(
+            response.stream.as_ref().unwrap().length(),
+            Some(data.len() as u64)
+        );
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+        assert!(output.ends_with(&data));
+
+        // several ranges adding up to more than the threshold are streamed too
+        let mut headers = HeaderMap::new();
+        headers.insert("Range", &format!("bytes=0-0,1-{}", STREAM_THRESHOLD));
+        let mut response = files.serve("large.bin", &headers).await;
+        assert_eq!(response.code, HttpCode::PartialContent);
+        assert!(response.body.is_empty());
+
+        let mut output: Vec<u8> = Vec::new();
+        response.write_to(&mut output).await.unwrap();
+        let content_type: &str = &response
+            .headers
+            .iter()
+            .find(|h| h.title == "Content-Type")
+            .unwrap()
+            .value;
+        let boundary: &str = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert!(output.ends_with(format!("\r\n--{}--\r\n", boundary).as_bytes()));
+        let length: String = response
+            .headers
+            .iter()
+            .find(|h| h.title == "Content-Length")
+            .unwrap()
+            .value
+            .clone();
+        let head_end: usize = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert_eq!(length, (output.len() - head_end).to_string());
+    }
+
+    #[tokio::test]
+    async fn test_serves_precompressed_sidecars() {
+        let dir = test_dir("sidecar");
+        std::fs::write(dir.join("public/app.js"), "plain").unwrap();
+        std::fs::write(dir.join("public/app.js.br"), "brotli").unwrap();
+        std::fs::write(dir.join("public/app.js.gz"), "gzip").unwrap();
+        let files = StaticFiles::new(dir.join("public"));
+        let header = |response: &Response, name: &str| -> Option<String> {
+            return response
+                .headers
+                .iter()
+                .find(|h| h.title == name)
+                .map(|h| h.value.clone());
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", "gzip, br");
+        let response = files.serve("app.js", &headers).await;
+        assert_eq!(response.body, b"brotli");
+        assert_eq!(header(&response, "Content-Encoding").unwrap(), "br");
+        assert_eq!(
+            header(&response, "Content-Type").unwrap(),
+            "text/javascript"
+        );
+        assert_eq!(header(&response, "Vary").unwrap(), "Accept-Encoding");
+        assert!(!response.is_compressible());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", "gzip, br;q=0.5");
+        let response = files.serve("app.js", &headers).await;
+        assert_eq!(response.body, b"gzip");
+        assert_eq!(header(&response, "Content-Encoding").unwrap(), "gzip");
+
+        // no acceptable copy: the original, left to on-the-fly compression
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", "deflate");
+        let response = files.serve("app.js", &headers).await;
+        assert_eq!(response.body, b"plain");
+        assert!(header(&response, "Content-Encoding").is_none());
+        assert_eq!(header(&response, "Vary").unwrap(), "Accept-Encoding");
+
+        // a copy older than the file is ignored
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("public/app.js.gz"))
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", "gzip");
+        let response = files.serve("app.js", &headers).await;
+        assert_eq!(response.body, b"plain");
+
+        let files = files.precompressed(false);
+        let mut headers = HeaderMap::new();
+        headers.insert("Accept-Encoding", "br");
+        let response = files.serve("app.js", &headers).await;
+        assert_eq!(response.body, b"plain");
+        assert!(header(&response, "Vary").is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlink_out_of_root_is_not_found() {
+        let dir = test_dir("symlink");
+        std::os::unix::fs::symlink(dir.join("secret.txt"), dir.join("public/link.txt")).unwrap();
+        let files = StaticFiles::new(dir.join("public"));
+
+        assert_eq!(
+            files.serve("link.txt", &HeaderMap::new()).await.code,
+            HttpCode::NotFound
+        );
+    }
+}
@@ -0,0 +1,590 @@
+use crate::{ContentType, ErrorType, HeaderMap, HttpCode, Protocol, Response};
+use colored::Colorize;
+use log::error;
+use rand::Rng;
+use std::ffi::OsStr;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+/// The largest file an [`UploadDir`] lets a client create by default.
+pub const DEFAULT_MAX_UPLOAD_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Stores files uploaded with `PUT`, modified with `PATCH` and removed with
+/// `DELETE` below a directory on disk.
+///
+/// Request paths are resolved relative to the root directory. Paths with `..`
+/// segments, and symbolic links leading out of the root, are refused with
+/// `403 Forbidden`, as are paths outside of the allowed directories when any
+/// have been configured. Missing parent directories are created on upload.
+///
+/// # Example
+/// ```rust
+/// use rust_server::UploadDir;
+/// let uploads = UploadDir::new("data/uploads")
+///     .allow_dir("docs")
+///     .allow_dir("images")
+///     .max_size(1024 * 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UploadDir {
+    root: PathBuf,
+    allowed_dirs: Vec<PathBuf>,
+    max_size: u64,
+}
+
+impl UploadDir {
+    /// Creates an upload directory rooted at `root`, accepting files up to
+    /// [`DEFAULT_MAX_UPLOAD_SIZE`] anywhere below it. The root is created on
+    /// the first upload if it does not exist.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        return UploadDir {
+            root: root.into(),
+            allowed_dirs: Vec::new(),
+            max_size: DEFAULT_MAX_UPLOAD_SIZE,
+        };
+    }
+
+    /// Restricts uploads to the directory `dir`, relative to the root, and
+    /// the directories below it. Can be called several times to allow more
+    /// than one directory.
+    pub fn allow_dir(mut self, dir: &str) -> Self {
+        self.allowed_dirs.push(PathBuf::from(dir.trim_matches('/')));
+        return self;
+    }
+
+    /// Sets the largest size in bytes a file may reach through `PUT` or `PATCH`.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        return self;
+    }
+
+    /// Creates or replaces a file.
+    ///
+    /// The body is written to a temporary file that is renamed over the
+    /// target once complete, so readers never see a partial upload.
+    ///
+    /// # Arguments
+    /// - `relative`: The percent-decoded path relative to the root.
+    /// - `body`: The new contents of the file.
+    ///
+    /// # Returns
+    /// `201 Created` for a new file, `204 No Content` for a replaced one,
+    /// `413 Payload Too Large` if the body exceeds the size limit, or an
+    /// error response from [`UploadDir::resolve`].
+    pub async fn put(&self, relative: &str, body: &[u8]) -> Response {
+        if body.len() as u64 > self.max_size {
+            return error_response(relative, self.too_large(relative));
+        }
+
+        let path: PathBuf = match self.resolve(relative, true).await {
+            Ok(p) => p,
+            Err(e) => return error_response(relative, e),
+        };
+        let existed: bool = fs::metadata(&path).await.is_ok();
+
+        let file_name: &OsStr = path.file_name().unwrap_or_default();
+        let temp: PathBuf = path.with_file_name(format!(
+            ".{}.{:08x}.upload",
+            file_name.to_string_lossy(),
+            rand::thread_rng().gen::<u32>()
+        ));
+
+        if let Err(e) = fs::write(&temp, body).await {
+            let _ = fs::remove_file(&temp).await;
+            return error_response(
+                relative,
+                ErrorType::WriteError(format!("Failed to write {}: {}", relative, e)),
+            );
+        }
+        if let Err(e) = fs::rename(&temp, &path).await {
+            let _ = fs::remove_file(&temp).await;
+            return error_response(
+                relative,
+                ErrorType::WriteError(format!("Failed to replace {}: {}", relative, e)),
+            );
+        }
+
+        if existed {
+            return Response::new(
+                Protocol::Http,
+                HttpCode::NoContent,
+                ContentType::Text,
+                false,
+            );
+        }
+        return Response::new(Protocol::Http, HttpCode::Created, ContentType::Text, false)
+            .body(String::from("File created.").into());
+    }
+
+    /// Modifies an existing file.
+    ///
+    /// Without a `Content-Range` header the body is appended to the file.
+    /// With `Content-Range: bytes <first>-<last>/*` the body overwrites those
+    /// bytes instead, extending the file if it reaches past the end.
+    ///
+    /// # Arguments
+    /// - `relative`: The percent-decoded path relative to the root.
+    /// - `body`: The bytes to write.
+    /// - `headers`: The request headers, checked for `Content-Range`.
+    ///
+    /// # Returns
+    /// `204 No Content` once the file has been updated, `404 Not Found` if it
+    /// does not exist, `400 Bad Request` for a malformed `Content-Range` or one
+    /// whose length differs from the body, `416 Range Not Satisfiable` if the
+    /// range starts past the end of the file, or `413 Payload Too Large` if the
+    /// file would exceed the size limit.
+    pub async fn patch(&self, relative: &str, body: &[u8], headers: &HeaderMap) -> Response {
+        let path: PathBuf = match self.resolve(relative, false).await {
+            Ok(p) => p,
+            Err(e) => return error_response(relative, e),
+        };
+        let length: u64 = match fs::metadata(&path).await {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return error_response(
+                    relative,
+                    ErrorType::NotFound(format!("File {} does not exist", relative)),
+                )
+            }
+            Err(e) => {
+                return error_response(
+                    relative,
+                    ErrorType::ReadError(format!("Failed to read metadata of {}: {}", relative, e)),
+                )
+            }
+        };
+
+        let offset: u64 = match headers.get("Content-Range") {
+            None => length,
+            Some(value) => match parse_content_range(value) {
+                // compared without `+ 1` so a range ending at u64::MAX cannot overflow
+                Some((first, last))
+                    if !body.is_empty() && last - first == body.len() as u64 - 1 =>
+                {
+                    first
+                }
+                _ => {
+                    return error_response(
+                        relative,
+                        ErrorType::BadRequest(format!("Invalid Content-Range: {}", value)),
+                    )
+                }
+            },
+        };
+
+        if offset > length {
+            let mut response = Response::new(
+                Protocol::Http,
+                HttpCode::RangeNotSatisfiable,
+                ContentType::Text,
+                false,
+            )
+            .body(String::from("416: Range starts past the end of the file").into());
+            response.add_header(String::from("Content-Range"), format!("bytes */{}", length));
+            return response;
+        }
+        if length.max(offset + body.len() as u64) > self.max_size {
+            return error_response(relative, self.too_large(relative));
+        }
+
+        let mut file = match OpenOptions::new().write(true).open(&path).await {
+            Ok(f) => f,
+            Err(e) => {
+                return error_response(
+                    relative,
+                    ErrorType::WriteError(format!("Failed to open {}: {}", relative, e)),
+                )
+            }
+        };
+        let written = match file.seek(SeekFrom::Start(offset)).await {
+            Ok(_) => match file.write_all(body).await {
+                Ok(_) => file.flush().await,
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = written {
+            return error_response(
+                relative,
+                ErrorType::WriteError(format!("Failed to update {}: {}", relative, e)),
+            );
+        }
+
+        return Response::new(
+            Protocol::Http,
+            HttpCode::NoContent,
+            ContentType::Text,
+            false,
+        );
+    }
+
+    /// Removes a file.
+    ///
+    /// # Returns
+    /// `204 No Content` once the file has been removed, `404 Not Found` if it
+    /// does not exist, or an error response from [`UploadDir::resolve`].
+    pub async fn delete(&self, relative: &str) -> Response {
+        let path: PathBuf = match self.resolve(relative, false).await {
+            Ok(p) => p,
+            Err(e) => return error_response(relative, e),
+        };
+
+        return match fs::remove_file(&path).await {
+            Ok(_) => Response::new(
+                Protocol::Http,
+                HttpCode::NoContent,
+                ContentType::Text,
+                false,
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => error_response(
+                relative,
+                ErrorType::NotFound(format!("File {} does not exist", relative)),
+            ),
+            Err(e) => error_response(
+                relative,
+                ErrorType::WriteError(format!("Failed to remove {}: {}", relative, e)),
+            ),
+        };
+    }
+
+    /// Resolves a request path to a file path below the root directory.
+    ///
+    /// Every directory on the way is canonicalized and checked to still be
+    /// inside the root, so symbolic links cannot lead out of it.
+    ///
+    /// # Arguments
+    /// - `relative`: The percent-decoded path relative to the root.
+    /// - `create_dirs`: Whether missing parent directories are created.
+    ///
+    /// # Returns
+    /// - `Ok(PathBuf)` with the path of the file, which may not exist yet.
+    /// - `Err(ErrorType::BadRequest)` if the path is empty or names a directory.
+    /// - `Err(ErrorType::Forbidden)` if the path leaves the root or an allowed directory.
+    /// - `Err(ErrorType::NotFound)` if a parent directory is missing and
+    ///   `create_dirs` is not set.
+    /// - `Err(ErrorType::WriteError)` or `Err(ErrorType::ReadError)` if the
+    ///   file system cannot be queried or changed.
+    async fn resolve(&self, relative: &str, create_dirs: bool) -> Result<PathBuf, ErrorType> {
+        let mut parts: Vec<&OsStr> = Vec::new();
+        for component in Path::new(relative).components() {
+            match component {
+                Component::Normal(part) => parts.push(part),
+                Component::CurDir => (),
+                _ => {
+                    return Err(ErrorType::Forbidden(format!(
+                        "Path {} leaves the upload root",
+                        relative
+                    )))
+                }
+            }
+        }
+        let (file_name, dirs) = match parts.split_last() {
+            Some(split) => split,
+            None => return Err(ErrorType::BadRequest(String::from("No file name given"))),
+        };
+        let lexical: PathBuf = parts.iter().collect();
+        if !self.is_allowed(&lexical) {
+            return Err(ErrorType::Forbidden(format!(
+                "Uploads to {} are not allowed",
+                relative
+            )));
+        }
+
+        if let Err(e) = fs::create_dir_all(&self.root).await {
+            return Err(ErrorType::WriteError(format!(
+                "Failed to create upload root {}: {}",
+                self.root.display(),
+                e
+            )));
+        }
+        let root: PathBuf = canonicalize(&self.root).await?;
+
+        let mut dir: PathBuf = root.clone();
+        for part in dirs {
+            let next: PathBuf = dir.join(part);
+            match fs::symlink_metadata(&next).await {
+                Ok(_) => {
+                    dir = canonicalize(&next).await?;
+                    if !dir.starts_with(&root) {
+                        return Err(ErrorType::Forbidden(format!(
+                            "Path {} leaves the upload root",
+                            relative
+                        )));
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound && create_dirs => {
+                    if let Err(e) = fs::create_dir(&next).await {
+                        return Err(ErrorType::WriteError(format!(
+                            "Failed to create directory for {}: {}",
+                            relative, e
+                        )));
+                    }
+                    dir = next;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    return Err(ErrorType::NotFound(format!(
+                        "File {} does not exist",
+                        relative
+                    )))
+                }
+                Err(e) => {
+                    return Err(ErrorType::ReadError(format!(
+                        "Failed to read metadata of {}: {}",
+                        relative, e
+                    )))
+                }
+            }
+        }
+
+        let mut path: PathBuf = dir.join(file_name);
+        if let Ok(metadata) = fs::symlink_metadata(&path).await {
+            if metadata.file_type().is_symlink() {
+                // a dangling link would let a write create its target anywhere
+                path = match canonicalize(&path).await {
+                    Ok(target) if target.starts_with(&root) => target,
+                    _ => {
+                        return Err(ErrorType::Forbidden(format!(
+                            "Path {} leaves the upload root",
+                            relative
+                        )))
+                    }
+                };
+            }
+            if fs::metadata(&path)
+                .await
+                .map(|m| m.is_dir())
+                .unwrap_or(false)
+            {
+                return Err(ErrorType::BadRequest(format!(
+                    "{} is a directory",
+                    relative
+                )));
+            }
+        }
+
+        // checked again on the resolved path so links between directories count too
+        if !self.is_allowed(path.strip_prefix(&root).unwrap_or(&path)) {
+            return Err(ErrorType::Forbidden(format!(
+                "Uploads to {} are not allowed",
+                relative
+            )));
+        }
+
+        return Ok(path);
+    }
+
+    /// Returns `true` if `relative`, a path relative to the root, is inside
+    /// one of the allowed directories, or if no directories were configured.
+    fn is_allowed(&self, relative: &Path) -> bool {
+        return self.allowed_dirs.is_empty()
+            || self.allowed_dirs.iter().any(|d| relative.starts_with(d));
+    }
+
+    fn too_large(&self, relative: &str) -> ErrorType {
+        return ErrorType::PayloadTooLarge(format!(
+            "{} would exceed the upload limit of {} bytes",
+            relative, self.max_size
+        ));
+    }
+}
+
+/// Parses a `Content-Range: bytes <first>-<last>/<total or *>` header.
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let range: &str = value.trim().strip_prefix("bytes ")?;
+    let (range, _total) = range.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let first: u64 = first.trim().parse().ok()?;
+    let last: u64 = last.trim().parse().ok()?;
+    if last < first {
+        return None;
+    }
+    return Some((first, last));
+}
+
+async fn canonicalize(path: &Path) -> Result<PathBuf, ErrorType> {
+    return match fs::canonicalize(path).await {
+        Ok(p) => Ok(p),
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(ErrorType::NotFound(format!(
+            "File {} does not exist",
+            path.display()
+        ))),
+        Err(e) => Err(ErrorType::ReadError(format!(
+            "Failed to resolve {}: {}",
+            path.display(),
+            e
+        ))),
+    };
+}
+
+/// Maps a failed upload to `relative` to an error response.
+fn error_response(relative: &str, error: ErrorType) -> Response {
+    println!(
+        "{} {} {}",
+        ">>".red().bold(),
+        "Unable to update file".red(),
+        relative.cyan()
+    );
+
+    let (code, message): (HttpCode, &str) = match error {
+        ErrorType::BadRequest(_) => (HttpCode::BadRequest, "400: Invalid file path"),
+        ErrorType::Forbidden(_) => (HttpCode::Forbidden, "403: Path not allowed"),
+        ErrorType::NotFound(_) => (HttpCode::NotFound, "404: File not found"),
+        ErrorType::PayloadTooLarge(_) => (HttpCode::PayloadTooLarge, "413: File too large"),
+        e => {
+            error!("Failed to update uploaded file: {}", e);
+            (
+                HttpCode::InternalServerError,
+                "Problem occured when writing file.",
+            )
+        }
+    };
+
+    return Response::new(Protocol::Http, code, ContentType::Text, false)
+        .body(String::from(message).into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir: PathBuf = std::env::temp_dir().join(format!(
+            "rust_server_uploads_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        return dir;
+    }
+
+    #[tokio::test]
+    async fn test_put_creates_then_replaces() {
+        let dir = test_dir("put");
+        let uploads = UploadDir::new(dir.join("root"));
+
+        let response = uploads.put("docs/a.txt", b"first").await;
+        assert_eq!(response.code, HttpCode::Created);
+        assert_eq!(
+            std::fs::read(dir.join("root/docs/a.txt")).unwrap(),
+            b"first"
+        );
+
+        let response = uploads.put("docs/a.txt", b"second").await;
+        assert_eq!(response.code, HttpCode::NoContent);
+        assert_eq!(
+            std::fs::read(dir.join("root/docs/a.txt")).unwrap(),
+            b"second"
+        );
+
+        // no temporary files are left behind
+        assert_eq!(std::fs::read_dir(dir.join("root/docs")).unwrap().count(), 1);
+
+        assert_eq!(uploads.put("", b"x").await.code, HttpCode::BadRequest);
+        assert_eq!(uploads.put("docs", b"x").await.code, HttpCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn test_patch_appends_and_overwrites() {
+        let dir = test_dir("patch");
+        let uploads = UploadDir::new(&dir).max_size(12);
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+
+        let response = uploads.patch("a.txt", b" world", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::NoContent);
+        assert_eq!(std::fs::read(dir.join("a.txt")).unwrap(), b"hello world");
+
+        let headers = HeaderMap::from_lines(["Content-Range: bytes 0-4/*"].into_iter());
+        let response = uploads.patch("a.txt", b"HELLO", &headers).await;
+        assert_eq!(response.code, HttpCode::NoContent);
+        assert_eq!(std::fs::read(dir.join("a.txt")).unwrap(), b"HELLO world");
+
+        let response = uploads.patch("a.txt", b"HEL", &headers).await;
+        assert_eq!(response.code, HttpCode::BadRequest);
+        let response = uploads.patch("a.txt", b"", &headers).await;
+        assert_eq!(response.code, HttpCode::BadRequest);
+
+        for line in [
+            "Content-Range: bytes 0-18446744073709551615/*",
+            "Content-Range: bytes 1-18446744073709551615/*",
+        ] {
+            let headers = HeaderMap::from_lines([line].into_iter());
+            let response = uploads.patch("a.txt", b"x", &headers).await;
+            assert_eq!(response.code, HttpCode::BadRequest, "{}", line);
+        }
+
+        let headers = HeaderMap::from_lines(["Content-Range: bytes 20-21/*"].into_iter());
+        let response = uploads.patch("a.txt", b"!!", &headers).await;
+        assert_eq!(response.code, HttpCode::RangeNotSatisfiable);
+
+        let response = uploads.patch("a.txt", b"!!", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::PayloadTooLarge);
+        assert_eq!(std::fs::read(dir.join("a.txt")).unwrap(), b"HELLO world");
+
+        let response = uploads.patch("missing.txt", b"x", &HeaderMap::new()).await;
+        assert_eq!(response.code, HttpCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_limits_and_allowed_dirs() {
+        let dir = test_dir("limits");
+        let uploads = UploadDir::new(dir.join("root"))
+            .allow_dir("docs")
+            .max_size(4);
+
+        assert_eq!(
+            uploads.put("docs/a.txt", b"1234").await.code,
+            HttpCode::Created
+        );
+        assert_eq!(
+            uploads.put("docs/b.txt", b"12345").await.code,
+            HttpCode::PayloadTooLarge
+        );
+        assert_eq!(uploads.put("a.txt", b"1").await.code, HttpCode::Forbidden);
+        assert_eq!(
+            uploads.put("other/a.txt", b"1").await.code,
+            HttpCode::Forbidden
+        );
+        assert_eq!(
+            uploads.put("docs/../a.txt", b"1").await.code,
+            HttpCode::Forbidden
+        );
+        assert!(!dir.join("root/a.txt").exists());
+        assert!(!dir.join("root/other").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_symlink_cannot_leave_root() {
+        let dir = test_dir("symlink");
+        std::fs::create_dir_all(dir.join("root")).unwrap();
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside"), dir.join("root/link")).unwrap();
+        std::os::unix::fs::symlink(dir.join("outside/new.txt"), dir.join("root/dangling")).unwrap();
+        let uploads = UploadDir::new(dir.join("root"));
+
+        assert_eq!(
+            uploads.put("link/a.txt", b"x").await.code,
+            HttpCode::Forbidden
+        );
+        assert_eq!(
+            uploads.put("dangling", b"x").await.code,
+            HttpCode::Forbidden
+        );
+        assert!(!dir.join("outside/a.txt").exists());
+        assert!(!dir.join("outside/new.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let dir = test_dir("delete");
+        let uploads = UploadDir::new(&dir);
+        std::fs::write(dir.join("a.txt"), "x").unwrap();
+
+        assert_eq!(uploads.delete("a.txt").await.code, HttpCode::NoContent);
+        assert!(!dir.join("a.txt").exists());
+        assert_eq!(uploads.delete("a.txt").await.code, HttpCode::NotFound);
+    }
+}
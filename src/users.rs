@@ -0,0 +1,355 @@
+use crate::ErrorType;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+/// The future returned by the methods of a [`UserStore`].
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, ErrorType>> + Send + 'a>>;
+
+/// A registered account.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// The Argon2 hash of the password, in PHC string format.
+    pub password_hash: String,
+}
+
+/// Storage for registered users.
+///
+/// Usernames are compared exactly, so `hay` never finds the account
+/// `hayley`, and every username is stored at most once. Sessions are kept
+/// apart from users, in a [`SessionStore`](crate::SessionStore).
+///
+/// # Example
+/// ```rust
+/// use rust_server::{MemoryUserStore, User, UserStore};
+/// # #[tokio::main]
+/// # async fn main() {
+/// let users = MemoryUserStore::new();
+/// let user = User {
+///     username: String::from("hayley"),
+///     password_hash: String::from("$argon2id$..."),
+/// };
+/// users.create(user.clone()).await.unwrap();
+/// assert!(users.create(user.clone()).await.is_err());
+/// assert_eq!(users.get("hayley").await.unwrap(), Some(user));
+/// assert_eq!(users.get("hay").await.unwrap(), None);
+/// # }
+/// ```
+pub trait UserStore: Send + Sync {
+    /// Adds a new user.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the user is stored.
+    /// - `Err(ErrorType::Conflict)` if the username is already taken.
+    fn create(&self, user: User) -> StoreFuture<'_, ()>;
+
+    /// Looks up the user with exactly the given username.
+    fn get<'a>(&'a self, username: &'a str) -> StoreFuture<'a, Option<User>>;
+
+    /// Replaces the stored user with the same username.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the user is updated.
+    /// - `Err(ErrorType::NotFound)` if no user has that username.
+    fn update(&self, user: User) -> StoreFuture<'_, ()>;
+
+    /// Removes the user with the given username.
+    ///
+    /// # Returns
+    /// - `Ok(())` once the user is removed.
+    /// - `Err(ErrorType::NotFound)` if no user has that username.
+    fn delete<'a>(&'a self, username: &'a str) -> StoreFuture<'a, ()>;
+}
+
+/// Keeps users in memory. Nothing is persisted, which makes it the store to
+/// use in tests.
+#[derive(Debug, Default)]
+pub struct MemoryUserStore {
+    users: Mutex<Vec<User>>,
+}
+
+impl MemoryUserStore {
+    pub fn new() -> Self {
+        return MemoryUserStore::default();
+    }
+}
+
+impl UserStore for MemoryUserStore {
+    fn create(&self, user: User) -> StoreFuture<'_, ()> {
+        return Box::pin(async move {
+            return insert_user(&mut *self.users.lock().await, user);
+        });
+    }
+
+    fn get<'a>(&'a self, username: &'a str) -> StoreFuture<'a, Option<User>> {
+        return Box::pin(async move {
+            return Ok(find_user(&self.users.lock().await, username));
+        });
+    }
+
+    fn update(&self, user: User) -> StoreFuture<'_, ()> {
+        return Box::pin(async move {
+            return replace_user(&mut self.users.lock().await, user);
+        });
+    }
+
+    fn delete<'a>(&'a self, username: &'a str) -> StoreFuture<'a, ()> {
+        return Box::pin(async move {
+            return remove_user(&mut *self.users.lock().await, username);
+        });
+    }
+}
+
+/// Keeps users in a file as JSON Lines, one JSON object per user and line.
+///
+/// Every change rewrites the file through a temporary file that is renamed
+/// into place, so readers never see a half-written record. The file and its
+/// directory are created on the first write.
+#[derive(Debug)]
+pub struct FileUserStore {
+    path: PathBuf,
+    /// Serialises read-modify-write cycles on the file.
+    lock: Mutex<()>,
+}
+
+impl FileUserStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        return FileUserStore {
+            path: path.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+        };
+    }
+
+    /// Reads every user from the file. A missing file holds no users.
+    async fn load(&self) -> Result<Vec<User>, ErrorType> {
+        let contents: String = match fs::read_to_string(&self.path).await {
+            Ok(c) => c,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(ErrorType::ReadError(format!(
+                    "Failed to read users from {}: {}",
+                    self.path.display(),
+                    e
+                )));
+            }
+        };
+
+        let mut users: Vec<User> = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(user) => users.push(user),
+                Err(e) => {
+                    return Err(ErrorType::InternalServerError(format!(
+                        "Invalid user record on line {} of {}: {}",
+                        number + 1,
+                        self.path.display(),
+                        e
+                    )));
+                }
+            }
+        }
+
+        return Ok(users);
+    }
+
+    /// Replaces the file with `users`, one record per line.
+    async fn save(&self, users: &[User]) -> Result<(), ErrorType> {
+        let mut contents: Vec<u8> = Vec::new();
+        for user in users {
+            match serde_json::to_writer(&mut contents, user) {
+                Ok(_) => contents.push(b'\n'),
+                Err(e) => {
+                    return Err(ErrorType::InternalServerError(format!(
+                        "Failed to serialise user: {}",
+                        e
+                    )));
+                }
+            }
+        }
+
+        let write_error = |e: std::io::Error| {
+            return ErrorType::WriteError(format!(
+                "Failed to write users to {}: {}",
+                self.path.display(),
+                e
+            ));
+        };
+
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent).await.map_err(write_error)?;
+            }
+        }
+
+        let mut temp: PathBuf = self.path.clone();
+        temp.as_mut_os_string().push(".tmp");
+        fs::write(&temp, &contents).await.map_err(write_error)?;
+        fs::rename(&temp, &self.path).await.map_err(write_error)?;
+
+        return Ok(());
+    }
+
+    /// Loads the users, applies `change` and writes them back.
+    async fn modify<F>(&self, change: F) -> Result<(), ErrorType>
+    where
+        F: FnOnce(&mut Vec<User>) -> Result<(), ErrorType> + Send,
+    {
+        let _guard = self.lock.lock().await;
+        let mut users: Vec<User> = self.load().await?;
+        change(&mut users)?;
+        return self.save(&users).await;
+    }
+}
+
+impl UserStore for FileUserStore {
+    fn create(&self, user: User) -> StoreFuture<'_, ()> {
+        return Box::pin(self.modify(move |users| insert_user(users, user)));
+    }
+
+    fn get<'a>(&'a self, username: &'a str) -> StoreFuture<'a, Option<User>> {
+        return Box::pin(async move {
+            let _guard = self.lock.lock().await;
+            return Ok(find_user(&self.load().await?, username));
+        });
+    }
+
+    fn update(&self, user: User) -> StoreFuture<'_, ()> {
+        return Box::pin(self.modify(move |users| replace_user(users, user)));
+    }
+
+    fn delete<'a>(&'a self, username: &'a str) -> StoreFuture<'a, ()> {
+        return Box::pin(self.modify(move |users| remove_user(users, username)));
+    }
+}
+
+fn find_user(users: &[User], username: &str) -> Option<User> {
+    return users.iter().find(|u| u.username == username).cloned();
+}
+
+fn insert_user(users: &mut Vec<User>, user: User) -> Result<(), ErrorType> {
+    if users.iter().any(|u| u.username == user.username) {
+        return Err(ErrorType::Conflict(format!(
+            "User {} already exists",
+            user.username
+        )));
+    }
+    users.push(user);
+    return Ok(());
+}
+
+fn replace_user(users: &mut [User], user: User) -> Result<(), ErrorType> {
+    return match users.iter_mut().find(|u| u.username == user.username) {
+        Some(existing) => {
+            *existing = user;
+            Ok(())
+        }
+        None => Err(ErrorType::NotFound(format!(
+            "User {} does not exist",
+            user.username
+        ))),
+    };
+}
+
+fn remove_user(users: &mut Vec<User>, username: &str) -> Result<(), ErrorType> {
+    let count: usize = users.len();
+    users.retain(|u| u.username != username);
+
+    if users.len() == count {
+        return Err(ErrorType::NotFound(format!(
+            "User {} does not exist",
+            username
+        )));
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn user(username: &str, password_hash: &str) -> User {
+        return User {
+            username: username.to_string(),
+            password_hash: password_hash.to_string(),
+        };
+    }
+
+    /// Runs the same checks against any store implementation.
+    pub(crate) async fn check_store(users: &dyn UserStore) {
+        users.create(user("hayley", "s1")).await.unwrap();
+        users.create(user("hay", "s2")).await.unwrap();
+        assert_eq!(
+            users.create(user("hayley", "s3")).await,
+            Err(ErrorType::Conflict(String::new()))
+        );
+
+        assert_eq!(
+            users.get("hayley").await.unwrap(),
+            Some(user("hayley", "s1"))
+        );
+        assert_eq!(users.get("hay").await.unwrap(), Some(user("hay", "s2")));
+        assert_eq!(users.get("hayl").await.unwrap(), None);
+
+        users.update(user("hayley", "s4")).await.unwrap();
+        assert_eq!(
+            users.get("hayley").await.unwrap(),
+            Some(user("hayley", "s4"))
+        );
+        assert_eq!(
+            users.update(user("nobody", "s5")).await,
+            Err(ErrorType::NotFound(String::new()))
+        );
+
+        users.delete("hay").await.unwrap();
+        assert_eq!(users.get("hay").await.unwrap(), None);
+        assert_eq!(
+            users.delete("hay").await,
+            Err(ErrorType::NotFound(String::new()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        check_store(&MemoryUserStore::new()).await;
+    }
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("rust_server_users_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path: PathBuf = dir.join("users.jsonl");
+
+        check_store(&FileUserStore::new(&path)).await;
+
+        // one record per line, each terminated by a newline
+        let contents: String = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        assert!(contents.ends_with("}\n"));
+
+        // a fresh store reads the same users back
+        let users = FileUserStore::new(&path);
+        assert_eq!(
+            users.get("hayley").await.unwrap(),
+            Some(user("hayley", "s4"))
+        );
+        users.create(user("a|b", "s6")).await.unwrap();
+        assert_eq!(users.get("a|b").await.unwrap(), Some(user("a|b", "s6")));
+
+        std::fs::write(&path, "not json\n").unwrap();
+        assert_eq!(
+            users.get("hayley").await,
+            Err(ErrorType::InternalServerError(String::new()))
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}